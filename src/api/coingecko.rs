@@ -1,24 +1,128 @@
-// This file contains functions to interact with the CoinGecko API, retrieving cryptocurrency data and news.
+// This file contains functions to interact with the CoinGecko API, retrieving cryptocurrency market data.
 
-use reqwest::Error;
-use serde::Deserialize;
+use std::collections::HashMap;
 
-#[derive(Deserialize)]
-struct CoinGeckoResponse {
-    articles: Vec<Article>,
+use serde::{Deserialize, Serialize};
+
+use crate::api::{url, FetchError, HttpClient};
+use crate::config::SourceConfig;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CoinListEntry {
+    pub id: String,
+    pub symbol: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CoinMarket {
+    pub id: String,
+    pub symbol: String,
+    pub name: String,
+    pub current_price: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub market_cap_rank: Option<u32>,
+    pub total_volume: Option<f64>,
+    pub price_change_24h: Option<f64>,
+    pub price_change_percentage_24h: Option<f64>,
+    pub last_updated: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct SimplePrice {
+    pub price: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub change_24h: Option<f64>,
+}
+
+// Each point is `[unix milliseconds, value]`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MarketChart {
+    pub prices: Vec<[f64; 2]>,
+    pub market_caps: Vec<[f64; 2]>,
+    pub total_volumes: Vec<[f64; 2]>,
 }
 
-#[derive(Deserialize)]
-struct Article {
-    title: String,
-    source: String,
-    published_at: String,
-    summary: String,
-    url: String,
+pub async fn fetch_coin_list(client: &HttpClient, source: &SourceConfig) -> Result<Vec<CoinListEntry>, FetchError> {
+    let url = url(&source.base_url, &["coins", "list"], &[])?;
+    Ok(client.get_json(source, url.as_str()).await?.value)
 }
 
-pub async fn fetch_news(crypto: &str) -> Result<Vec<Article>, Error> {
-    let url = format!("https://api.coingecko.com/api/v3/news?query={}", crypto);
-    let response: CoinGeckoResponse = reqwest::get(&url).await?.json().await?;
-    Ok(response.articles)
-}
\ No newline at end of file
+// Prices keyed by coin id, e.g. `bitcoin`.
+pub async fn fetch_simple_price(
+    client: &HttpClient,
+    source: &SourceConfig,
+    ids: &[String],
+    vs_currency: &str,
+) -> Result<HashMap<String, SimplePrice>, FetchError> {
+    let url = url(
+        &source.base_url,
+        &["simple", "price"],
+        &[
+            ("ids", &ids.join(",")),
+            ("vs_currencies", vs_currency),
+            ("include_market_cap", "true"),
+            ("include_24hr_change", "true"),
+        ],
+    )?;
+    let response: HashMap<String, HashMap<String, Option<f64>>> = client.get_json(source, url.as_str()).await?.value;
+    Ok(response
+        .into_iter()
+        .map(|(id, values)| {
+            let price = SimplePrice {
+                price: values.get(vs_currency).copied().flatten(),
+                market_cap: values.get(&format!("{}_market_cap", vs_currency)).copied().flatten(),
+                change_24h: values.get(&format!("{}_24h_change", vs_currency)).copied().flatten(),
+            };
+            (id, price)
+        })
+        .collect())
+}
+
+// The most `coins/markets` returns per page.
+const MARKETS_PER_PAGE: usize = 250;
+
+// Several coins share popular tickers, so a request asks for few enough symbols that all of their
+// coins fit on one page.
+const SYMBOLS_PER_REQUEST: usize = 50;
+
+// Markets for coins whose ticker is in `symbols`, highest market cap first for each ticker. Several
+// coins can share a ticker, so callers usually want the first match per symbol.
+pub async fn fetch_coin_markets(
+    client: &HttpClient,
+    source: &SourceConfig,
+    symbols: &[String],
+    vs_currency: &str,
+) -> Result<Vec<CoinMarket>, FetchError> {
+    let mut markets = Vec::new();
+    for chunk in symbols.chunks(SYMBOLS_PER_REQUEST) {
+        let url = url(
+            &source.base_url,
+            &["coins", "markets"],
+            &[
+                ("vs_currency", vs_currency),
+                ("symbols", &chunk.join(",").to_lowercase()),
+                ("order", "market_cap_desc"),
+                ("per_page", &MARKETS_PER_PAGE.to_string()),
+            ],
+        )?;
+        let page: Vec<CoinMarket> = client.get_json(source, url.as_str()).await?.value;
+        markets.extend(page);
+    }
+    Ok(markets)
+}
+
+pub async fn fetch_market_chart(
+    client: &HttpClient,
+    source: &SourceConfig,
+    id: &str,
+    vs_currency: &str,
+    days: u32,
+) -> Result<MarketChart, FetchError> {
+    let url = url(
+        &source.base_url,
+        &["coins", id, "market_chart"],
+        &[("vs_currency", vs_currency), ("days", &days.to_string())],
+    )?;
+    Ok(client.get_json(source, url.as_str()).await?.value)
+}
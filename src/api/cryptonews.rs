@@ -1,20 +1,15 @@
 // This file contains functions to interact with the CryptQNews API, fetching the latest news articles based on user input.
 
-use reqwest::Error;
-use serde::Deserialize;
+use crate::api::{url, FetchError, Fetched, HttpClient};
+use crate::config::SourceConfig;
+use crate::models::news::NewsArticle;
 
-#[derive(Deserialize, Debug)]
-struct NewsArticle {
-    title: String,
-    source: String,
-    date: String,
-    summary: String,
-    link: String,
+// `modified` is false when the feed hasn't changed since the last fetch.
+pub async fn fetch_latest_news(
+    client: &HttpClient,
+    source: &SourceConfig,
+    crypto: &str,
+) -> Result<Fetched<Vec<NewsArticle>>, FetchError> {
+    let url = url(&source.base_url, &["news"], &[("crypto", crypto)])?;
+    client.get_json(source, url.as_str()).await
 }
-
-pub async fn fetch_latest_news(crypto: &str) -> Result<Vec<NewsArticle>, Error> {
-    let url = format!("https://api.cryptqnews.com/v1/news?crypto={}", crypto);
-    let response = reqwest::get(&url).await?;
-    let articles: Vec<NewsArticle> = response.json().await?;
-    Ok(articles)
-}
\ No newline at end of file
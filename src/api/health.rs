@@ -0,0 +1,225 @@
+// Per-source health as seen by the HTTP client: the last successful fetch, the last error, the
+// rate-limit budget the upstream advertises and a circuit breaker.
+//
+// After `FAILURE_THRESHOLD` consecutive failures the circuit opens and fetches from the source fail
+// straight away. Once `OPEN_FOR` has passed it is half-open: requests go out again, the first
+// success closes it and a failure opens it for another `OPEN_FOR`.
+
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+
+const FAILURE_THRESHOLD: u32 = 5;
+const OPEN_FOR: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    HalfOpen,
+    Open,
+}
+
+impl CircuitState {
+    // The value of the `upstream_circuit_state` gauge.
+    pub fn level(self) -> i64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::HalfOpen => 1,
+            CircuitState::Open => 2,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SourceError {
+    pub at: i64,
+    pub message: String,
+}
+
+// Taken from the most recent response that carried rate-limit headers.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    // Seconds until the budget resets, as sent by the upstream.
+    pub reset: Option<u64>,
+    pub seen_at: i64,
+}
+
+impl RateLimit {
+    // `X-RateLimit-*` as most APIs send it, or the unprefixed `RateLimit-*` of the IETF draft.
+    pub fn parse(headers: &HeaderMap) -> Option<Self> {
+        let value = |name: &str| {
+            [format!("x-ratelimit-{}", name), format!("ratelimit-{}", name)]
+                .iter()
+                .find_map(|header| headers.get(header.as_str()))
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+        };
+        let rate_limit = RateLimit {
+            limit: value("limit"),
+            remaining: value("remaining"),
+            reset: value("reset"),
+            seen_at: Utc::now().timestamp(),
+        };
+        if rate_limit.limit.is_none() && rate_limit.remaining.is_none() {
+            return None;
+        }
+        Some(rate_limit)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SourceHealth {
+    pub last_success: Option<i64>,
+    pub last_error: Option<SourceError>,
+    pub consecutive_failures: u32,
+    pub circuit: CircuitState,
+    // When an open circuit lets requests through again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<i64>,
+    pub rate_limit: Option<RateLimit>,
+    #[serde(skip)]
+    opened_at: Option<Instant>,
+}
+
+impl Default for SourceHealth {
+    fn default() -> Self {
+        SourceHealth {
+            last_success: None,
+            last_error: None,
+            consecutive_failures: 0,
+            circuit: CircuitState::Closed,
+            retry_at: None,
+            rate_limit: None,
+            opened_at: None,
+        }
+    }
+}
+
+impl SourceHealth {
+    // Moves an open circuit to half-open once `OPEN_FOR` has passed.
+    pub fn refresh(&mut self) {
+        if self.circuit == CircuitState::Open && self.opened_at.is_some_and(|at| at.elapsed() >= OPEN_FOR) {
+            self.circuit = CircuitState::HalfOpen;
+            self.retry_at = None;
+        }
+    }
+
+    // Whether a request to the source may go out.
+    pub fn admit(&mut self) -> bool {
+        self.refresh();
+        self.circuit != CircuitState::Open
+    }
+
+    pub fn succeeded(&mut self) {
+        self.last_success = Some(Utc::now().timestamp());
+        self.consecutive_failures = 0;
+        self.circuit = CircuitState::Closed;
+        self.retry_at = None;
+        self.opened_at = None;
+    }
+
+    pub fn failed(&mut self, message: String) {
+        let now = Utc::now().timestamp();
+        self.last_error = Some(SourceError { at: now, message });
+        self.consecutive_failures += 1;
+        if self.circuit == CircuitState::HalfOpen || self.consecutive_failures >= FAILURE_THRESHOLD {
+            self.circuit = CircuitState::Open;
+            self.opened_at = Some(Instant::now());
+            self.retry_at = Some(now + OPEN_FOR.as_secs() as i64);
+        }
+    }
+
+    // Closes the circuit by hand, e.g. once an operator has fixed the source. The last success and
+    // error are kept.
+    pub fn reset(&mut self) {
+        self.consecutive_failures = 0;
+        self.circuit = CircuitState::Closed;
+        self.retry_at = None;
+        self.opened_at = None;
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.circuit != CircuitState::Open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn the_circuit_opens_after_repeated_failures_and_closes_on_success() {
+        let mut health = SourceHealth::default();
+        for _ in 1..FAILURE_THRESHOLD {
+            health.failed("timeout".to_string());
+        }
+        assert_eq!(health.circuit, CircuitState::Closed);
+        assert!(health.admit());
+
+        health.failed("timeout".to_string());
+        assert_eq!(health.circuit, CircuitState::Open);
+        assert!(!health.admit());
+        assert!(!health.is_healthy());
+        assert_eq!(health.last_error.as_ref().unwrap().message, "timeout");
+        assert!(health.retry_at.is_some());
+
+        // Once OPEN_FOR has passed one request goes out; its failure opens the circuit again.
+        health.opened_at = Some(Instant::now() - OPEN_FOR);
+        assert!(health.admit());
+        assert_eq!(health.circuit, CircuitState::HalfOpen);
+        health.failed("timeout".to_string());
+        assert_eq!(health.circuit, CircuitState::Open);
+
+        health.opened_at = Some(Instant::now() - OPEN_FOR);
+        assert!(health.admit());
+        health.succeeded();
+        assert_eq!(health.circuit, CircuitState::Closed);
+        assert_eq!(health.consecutive_failures, 0);
+        assert!(health.last_success.is_some());
+        assert!(health.last_error.is_some());
+    }
+
+    #[test]
+    fn resetting_closes_the_circuit_and_keeps_the_history() {
+        let mut health = SourceHealth::default();
+        for _ in 0..FAILURE_THRESHOLD {
+            health.failed("503 Service Unavailable".to_string());
+        }
+        assert!(!health.admit());
+        health.reset();
+        assert!(health.admit());
+        assert_eq!(health.circuit, CircuitState::Closed);
+        assert_eq!(health.retry_at, None);
+        assert!(health.last_error.is_some());
+    }
+
+    #[test]
+    fn rate_limits_are_read_from_either_header_style() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RateLimit::parse(&headers), None);
+        // A reset alone says nothing about the budget.
+        headers.insert("x-ratelimit-reset", HeaderValue::from_static("30"));
+        assert_eq!(RateLimit::parse(&headers), None);
+
+        headers.insert("x-ratelimit-limit", HeaderValue::from_static("100"));
+        headers.insert("ratelimit-remaining", HeaderValue::from_static(" 42 "));
+        let rate_limit = RateLimit::parse(&headers).unwrap();
+        assert_eq!(
+            (rate_limit.limit, rate_limit.remaining, rate_limit.reset),
+            (Some(100), Some(42), Some(30))
+        );
+
+        // The prefixed header wins, and unparsable values are left out.
+        headers.insert("ratelimit-limit", HeaderValue::from_static("5"));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static("many"));
+        let rate_limit = RateLimit::parse(&headers).unwrap();
+        assert_eq!((rate_limit.limit, rate_limit.remaining), (Some(100), None));
+    }
+}
@@ -0,0 +1,491 @@
+// The HTTP client shared by every source: one connection pool, timeouts, compression, a
+// User-Agent and an optional proxy, plus each source's API key and headers.
+//
+// Responses carrying an ETag, Last-Modified or max-age are kept per URL and per the headers the
+// source sends (see `cache_key`). Later requests for the URL are conditional, and a 304 reuses the kept body. Until `Cache-Control: max-age` has passed
+// the upstream isn't asked at all, which makes max-age the minimum poll interval for every URL.
+//
+// Every fetch also updates the source's health (see `health`), and a source whose circuit is
+// open isn't asked until it has had time to recover.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::header::{HeaderMap, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, RequestBuilder, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::api::{RateLimit, SourceHealth};
+use crate::config::{HttpConfig, SourceConfig};
+use crate::services::metrics::Metrics;
+
+#[derive(Debug)]
+pub enum FetchError {
+    Http(reqwest::Error),
+    Decode(serde_json::Error),
+    // A body that isn't the feed it should be, e.g. broken RSS.
+    Feed(String),
+    // A base URL that can't take a path, e.g. `mailto:`.
+    Url(String),
+    CircuitOpen,
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Http(e) => write!(f, "{}", e),
+            FetchError::Decode(e) => write!(f, "invalid response body: {}", e),
+            FetchError::Feed(e) => write!(f, "invalid feed: {}", e),
+            FetchError::Url(e) => write!(f, "invalid URL: {}", e),
+            FetchError::CircuitOpen => write!(f, "source is unavailable after repeated failures"),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> Self {
+        FetchError::Decode(e)
+    }
+}
+
+// `base_url` with `segments` appended to its path and `query` added, every value percent-encoded,
+// so a symbol or id from a request can't change the path or add parameters.
+pub fn url(base_url: &str, segments: &[&str], query: &[(&str, &str)]) -> Result<Url, FetchError> {
+    let mut url = Url::parse(base_url).map_err(|e| FetchError::Url(format!("{}: {}", base_url, e)))?;
+    url.path_segments_mut()
+        .map_err(|_| FetchError::Url(format!("{} can't have a path", base_url)))?
+        .pop_if_empty()
+        .extend(segments);
+    if !query.is_empty() {
+        url.query_pairs_mut().extend_pairs(query);
+    }
+    Ok(url)
+}
+
+// `modified` is false when the body is the one kept from an earlier response, i.e. the upstream
+// answered 304 or max-age hadn't passed yet: there are no new items.
+pub struct Fetched<T> {
+    pub value: T,
+    pub modified: bool,
+}
+
+// At most this many upstream responses are kept; past it the least recently used one goes.
+const MAX_CACHED_RESPONSES: usize = 1000;
+// A kept response no fetch has used for this long is dropped, e.g. the chart for a symbol
+// requested once.
+const CACHED_RESPONSE_IDLE: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct CachedResponse {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    fresh_until: Option<Instant>,
+    body: Arc<Vec<u8>>,
+    used: Instant,
+}
+
+impl CachedResponse {
+    fn is_fresh(&self) -> bool {
+        self.fresh_until.is_some_and(|until| Instant::now() < until)
+    }
+}
+
+// Keeps `response` under `key`, first dropping idle responses and, when the cache is still full,
+// the least recently used one.
+fn keep(cache: &mut HashMap<String, CachedResponse>, key: &str, response: CachedResponse) {
+    if !cache.contains_key(key) && cache.len() >= MAX_CACHED_RESPONSES {
+        cache.retain(|_, cached| cached.used.elapsed() < CACHED_RESPONSE_IDLE);
+        while cache.len() >= MAX_CACHED_RESPONSES {
+            let Some(oldest) = cache.iter().min_by_key(|(_, cached)| cached.used).map(|(key, _)| key.clone()) else {
+                break;
+            };
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(key.to_string(), response);
+}
+
+// Kept responses are per URL and per the source's headers, so two sources with different API keys
+// or headers never share a body. The key itself is only kept as a hash.
+fn cache_key(source: &SourceConfig, url: &str) -> String {
+    let mut hasher = Sha256::new();
+    if let (Some(api_key), Some(header)) = (&source.api_key, &source.api_key_header) {
+        hasher.update(format!("{}\0{}\0", header.to_lowercase(), api_key));
+    }
+    for (name, value) in &source.headers {
+        hasher.update(format!("{}\0{}\0", name.to_lowercase(), value));
+    }
+    format!("{} {:x}", url, hasher.finalize())
+}
+
+#[derive(Default)]
+struct CacheControl {
+    no_store: bool,
+    // `no-cache` counts as zero: keep the body but always revalidate.
+    max_age: Option<u64>,
+}
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut cache_control = CacheControl::default();
+        for directive in headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+        {
+            let directive = directive.trim().to_lowercase();
+            if directive == "no-store" {
+                cache_control.no_store = true;
+            } else if directive == "no-cache" {
+                cache_control.max_age = Some(0);
+            } else if let Some(seconds) = directive.strip_prefix("max-age=") {
+                if cache_control.max_age.is_none() {
+                    cache_control.max_age = seconds.trim_matches('"').parse().ok();
+                }
+            }
+        }
+        cache_control
+    }
+
+    fn fresh_until(&self) -> Option<Instant> {
+        self.max_age.map(|seconds| Instant::now() + Duration::from_secs(seconds))
+    }
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    cache: Arc<Mutex<HashMap<String, CachedResponse>>>,
+    health: Arc<Mutex<HashMap<String, SourceHealth>>>,
+    metrics: Metrics,
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig, metrics: Metrics) -> Result<Self, reqwest::Error> {
+        let mut builder = Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent(config.user_agent.as_str())
+            .gzip(true)
+            .brotli(true);
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(HttpClient {
+            client: builder.build()?,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            health: Arc::new(Mutex::new(HashMap::new())),
+            metrics,
+        })
+    }
+
+    // A GET to `url` carrying the source's API key and extra headers. Header names and values
+    // were validated when the config was loaded.
+    fn get(&self, source: &SourceConfig, url: &str) -> RequestBuilder {
+        let mut request = self.client.get(url);
+        if let (Some(api_key), Some(header)) = (&source.api_key, &source.api_key_header) {
+            request = request.header(header.as_str(), api_key.as_str());
+        }
+        for (name, value) in &source.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        request
+    }
+
+    // The source's health as of now; a source that hasn't been fetched from yet is healthy.
+    pub fn health(&self, source: &str) -> SourceHealth {
+        self.update_health(source, |health| health.clone())
+    }
+
+    pub fn reset_health(&self, source: &str) {
+        self.update_health(source, SourceHealth::reset);
+    }
+
+    fn update_health<R>(&self, source: &str, update: impl FnOnce(&mut SourceHealth) -> R) -> R {
+        let mut sources = self.health.lock().unwrap();
+        let health = sources.entry(source.to_string()).or_default();
+        health.refresh();
+        let result = update(health);
+        self.metrics.circuit_state(source, health.circuit.level());
+        result
+    }
+
+    // A POST of `payload` as JSON, e.g. to a notification webhook. It isn't a source, so there is
+    // no caching or health tracking, but the connect and request timeouts apply.
+    pub async fn post_json<T: Serialize>(&self, url: &str, payload: &T) -> Result<(), reqwest::Error> {
+        self.client.post(url).json(payload).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, source: &SourceConfig, url: &str) -> Result<Fetched<T>, FetchError> {
+        self.get_decoded(source, url, |body| Ok(serde_json::from_slice(body)?)).await
+    }
+
+    // A GET whose body is turned into `T` by `decode`; a body it rejects counts as a failed fetch.
+    #[tracing::instrument(name = "fetch", skip_all, fields(source = %source.name, url = %url))]
+    pub async fn get_decoded<T>(
+        &self,
+        source: &SourceConfig,
+        url: &str,
+        decode: impl Fn(&[u8]) -> Result<T, FetchError>,
+    ) -> Result<Fetched<T>, FetchError> {
+        // Within max-age the upstream isn't asked, so the source's health is left as it was.
+        if let Some(body) = self.fresh(&cache_key(source, url)) {
+            self.metrics.observe_upstream(&source.name, "fresh", None);
+            return Ok(Fetched {
+                value: decode(&body)?,
+                modified: false,
+            });
+        }
+        if !self.update_health(&source.name, SourceHealth::admit) {
+            self.metrics.upstream_error(&source.name, "circuit_open");
+            tracing::debug!("circuit open, not fetching");
+            return Err(FetchError::CircuitOpen);
+        }
+        let started = Instant::now();
+        let result = self.fetch(source, url, &decode).await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(fetched) => {
+                self.update_health(&source.name, SourceHealth::succeeded);
+                tracing::debug!(modified = fetched.modified, elapsed_ms, "fetched");
+            }
+            Err(e) => {
+                let kind = match e {
+                    FetchError::Http(e) if e.is_status() => "status",
+                    FetchError::Http(_) => "http",
+                    FetchError::Decode(_) | FetchError::Feed(_) => "decode",
+                    FetchError::Url(_) => "http",
+                    FetchError::CircuitOpen => "circuit_open",
+                };
+                self.update_health(&source.name, |health| health.failed(e.to_string()));
+                self.metrics.upstream_error(&source.name, kind);
+                tracing::warn!(kind, error = %e, elapsed_ms, "fetch failed");
+            }
+        }
+        result
+    }
+
+    // The body kept under `key` while its max-age hasn't passed.
+    fn fresh(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        let mut cache = self.cache.lock().unwrap();
+        let cached = cache.get_mut(key).filter(|cached| cached.is_fresh())?;
+        cached.used = Instant::now();
+        Some(cached.body.clone())
+    }
+
+    async fn fetch<T>(
+        &self,
+        source: &SourceConfig,
+        url: &str,
+        decode: &impl Fn(&[u8]) -> Result<T, FetchError>,
+    ) -> Result<Fetched<T>, FetchError> {
+        let key = cache_key(source, url);
+        let mut request = self.get(source, url);
+        let kept = {
+            let mut cache = self.cache.lock().unwrap();
+            match cache.get_mut(&key) {
+                Some(cached) => {
+                    cached.used = Instant::now();
+                    if let Some(etag) = &cached.etag {
+                        request = request.header(IF_NONE_MATCH, etag.as_str());
+                    }
+                    if let Some(last_modified) = &cached.last_modified {
+                        request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+                    }
+                    Some(cached.body.clone())
+                }
+                None => None,
+            }
+        };
+
+        let started = Instant::now();
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                self.metrics
+                    .observe_upstream(&source.name, "error", Some(started.elapsed()));
+                return Err(e.into());
+            }
+        };
+        let outcome = match response.status() {
+            StatusCode::NOT_MODIFIED => "not_modified",
+            status if status.is_success() => "ok",
+            _ => "error",
+        };
+        self.metrics
+            .observe_upstream(&source.name, outcome, Some(started.elapsed()));
+        if let Some(rate_limit) = RateLimit::parse(response.headers()) {
+            self.update_health(&source.name, |health| health.rate_limit = Some(rate_limit));
+        }
+        let cache_control = CacheControl::parse(response.headers());
+        let fresh_until = cache_control.fresh_until();
+        if let (StatusCode::NOT_MODIFIED, Some(body)) = (response.status(), kept) {
+            if let Some(cached) = self.cache.lock().unwrap().get_mut(&key) {
+                cached.fresh_until = fresh_until;
+            }
+            return Ok(Fetched {
+                value: decode(&body)?,
+                modified: false,
+            });
+        }
+
+        let response = response.error_for_status()?;
+        let etag = header(response.headers(), ETAG);
+        let last_modified = header(response.headers(), LAST_MODIFIED);
+        let body = response.bytes().await?;
+        let value = decode(&body)?;
+
+        let mut cache = self.cache.lock().unwrap();
+        if !cache_control.no_store && (etag.is_some() || last_modified.is_some() || fresh_until.is_some()) {
+            keep(
+                &mut cache,
+                &key,
+                CachedResponse {
+                    etag,
+                    last_modified,
+                    fresh_until,
+                    body: Arc::new(body.to_vec()),
+                    used: Instant::now(),
+                },
+            );
+        } else {
+            cache.remove(&key);
+        }
+        Ok(Fetched { value, modified: true })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn url_encodes_values_from_requests() {
+        let chart = url(
+            "https://api.example.com/api/v3/",
+            &["coins", "../../admin", "market_chart"],
+            &[("ids", "btc&page=2")],
+        )
+        .unwrap();
+        assert_eq!(
+            chart.as_str(),
+            "https://api.example.com/api/v3/coins/..%2F..%2Fadmin/market_chart?ids=btc%26page%3D2"
+        );
+        let news = url("http://127.0.0.1:8080", &["news"], &[("crypto", "eth")]).unwrap();
+        assert_eq!(news.as_str(), "http://127.0.0.1:8080/news?crypto=eth");
+        assert!(matches!(url("mailto:ops@example.com", &["news"], &[]), Err(FetchError::Url(_))));
+    }
+
+    #[test]
+    fn cache_evicts_idle_then_least_recently_used_responses() {
+        let response = |idle_secs: u64| CachedResponse {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            fresh_until: None,
+            body: Arc::new(Vec::new()),
+            used: Instant::now() - Duration::from_secs(idle_secs),
+        };
+        let mut cache = HashMap::new();
+        for i in 0..MAX_CACHED_RESPONSES {
+            cache.insert(format!("https://example.com/{}", i), response(MAX_CACHED_RESPONSES as u64 - i as u64));
+        }
+        cache.insert("https://example.com/0".to_string(), response(CACHED_RESPONSE_IDLE.as_secs() + 1));
+        cache.insert("https://example.com/1".to_string(), response(CACHED_RESPONSE_IDLE.as_secs() + 1));
+
+        keep(&mut cache, "https://example.com/new", response(0));
+        assert_eq!(cache.len(), MAX_CACHED_RESPONSES - 1);
+        assert!(!cache.contains_key("https://example.com/0"));
+        assert!(!cache.contains_key("https://example.com/1"));
+
+        keep(&mut cache, "https://example.com/newer", response(0));
+        keep(&mut cache, "https://example.com/newest", response(0));
+        assert_eq!(cache.len(), MAX_CACHED_RESPONSES);
+        assert!(!cache.contains_key("https://example.com/2"));
+        assert!(cache.contains_key("https://example.com/3"));
+        assert!(cache.contains_key("https://example.com/newest"));
+
+        // Replacing a kept URL doesn't evict anything.
+        keep(&mut cache, "https://example.com/3", response(0));
+        assert_eq!(cache.len(), MAX_CACHED_RESPONSES);
+    }
+
+    #[actix_web::test]
+    async fn responses_are_kept_per_api_key_and_headers() {
+        // Answers with the API key it was sent, fresh for a minute.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let read = stream.read(&mut request).await.unwrap_or(0);
+                counted.fetch_add(1, Ordering::SeqCst);
+                let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+                let api_key = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("x-api-key: "))
+                    .unwrap_or("none")
+                    .trim()
+                    .to_string();
+                let body = format!("\"{}\"", api_key);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nCache-Control: max-age=60\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let config = Config::parse("", std::iter::empty()).unwrap();
+        let client = HttpClient::new(&config.http, Metrics::new()).unwrap();
+        let mut first = config.sources["coingecko"].clone();
+        first.base_url = base_url.clone();
+        first.api_key = Some("first".to_string());
+        first.api_key_header = Some("X-Api-Key".to_string());
+        let mut second = first.clone();
+        second.api_key = Some("second".to_string());
+        let mut with_header = first.clone();
+        with_header.headers.insert("Accept-Language".to_string(), "de".to_string());
+
+        let fetched: Fetched<String> = client.get_json(&first, &base_url).await.unwrap();
+        assert_eq!((fetched.value.as_str(), fetched.modified), ("first", true));
+        let fetched: Fetched<String> = client.get_json(&second, &base_url).await.unwrap();
+        assert_eq!((fetched.value.as_str(), fetched.modified), ("second", true));
+        let fetched: Fetched<String> = client.get_json(&with_header, &base_url).await.unwrap();
+        assert!(fetched.modified);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // The same key and headers reuse the kept body without asking again.
+        let fetched: Fetched<String> = client.get_json(&first, &base_url).await.unwrap();
+        assert_eq!((fetched.value.as_str(), fetched.modified), ("first", false));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // The API key is only kept hashed.
+        assert!(client.cache.lock().unwrap().keys().all(|key| !key.contains("first")));
+    }
+}
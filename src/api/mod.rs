@@ -1,5 +1,11 @@
 mod cryptonews;
 mod coingecko;
+mod feeds;
+mod health;
+mod http;
 
 pub use cryptonews::*;
-pub use coingecko::*;
\ No newline at end of file
+pub use coingecko::*;
+pub use feeds::*;
+pub use health::*;
+pub use http::*;
\ No newline at end of file
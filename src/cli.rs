@@ -0,0 +1,112 @@
+// Administration commands, run instead of the server when the binary is given arguments. They use
+// the database from the same config as the server, so the first admin key can be created before
+// the server is reachable.
+
+use chrono::{TimeZone, Utc};
+
+use crate::config::Config;
+use crate::services::apikeys::{ApiKey, ApiKeys, NewApiKey};
+use crate::services::db;
+
+const USAGE: &str = "usage:
+    crypto-news-aggregator keys create <name> [--admin] [--rate-limit <per minute>] [--daily-quota <requests>]
+    crypto-news-aggregator keys list
+    crypto-news-aggregator keys revoke <id>";
+
+enum Command {
+    Create(NewApiKey),
+    List,
+    Revoke(i64),
+}
+
+fn parse(args: &[String]) -> Result<Command, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["keys", "create", name, options @ ..] => {
+            let mut new = NewApiKey {
+                name: name.to_string(),
+                ..NewApiKey::default()
+            };
+            let mut options = options.iter();
+            while let Some(option) = options.next() {
+                let mut value = || {
+                    options
+                        .next()
+                        .and_then(|value| value.parse::<u32>().ok())
+                        .ok_or_else(|| format!("{} needs a number\n{}", option, USAGE))
+                };
+                match *option {
+                    "--admin" => new.admin = true,
+                    "--rate-limit" => new.rate_limit = Some(value()?),
+                    "--daily-quota" => new.daily_quota = Some(value()?.into()),
+                    _ => return Err(format!("unknown option {}\n{}", option, USAGE)),
+                }
+            }
+            new.validate()?;
+            Ok(Command::Create(new))
+        }
+        ["keys", "list"] => Ok(Command::List),
+        ["keys", "revoke", id] => id
+            .parse()
+            .map(Command::Revoke)
+            .map_err(|_| format!("invalid key id {:?}\n{}", id, USAGE)),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn date(timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+        .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn limit<T: ToString>(limit: Option<T>) -> String {
+    limit.map(|limit| limit.to_string()).unwrap_or_else(|| "default".to_string())
+}
+
+fn print_key(key: &ApiKey) {
+    println!(
+        "{:>4}  {:<12}  {:<20}  {:<5}  {:>8}  {:>9}  {:>7}  {:>8}  {:<16}  {}",
+        key.id,
+        key.prefix,
+        key.name,
+        if key.admin { "yes" } else { "no" },
+        limit(key.rate_limit),
+        limit(key.daily_quota),
+        key.requests_today,
+        key.requests_total,
+        date(key.last_used_at),
+        if key.revoked_at.is_some() { "revoked" } else { "active" },
+    );
+}
+
+pub async fn run(args: &[String], config: &Config) -> Result<(), String> {
+    let command = parse(args)?;
+    let pool = db::connect(&config.storage.database_url)
+        .await
+        .map_err(|e| e.to_string())?;
+    let keys = ApiKeys::new(pool.clone());
+    let result = match command {
+        Command::Create(new) => keys.create(&new).await.map(|(key, record)| {
+            println!("created API key {} ({}); it is shown only once:", record.id, record.name);
+            println!("{}", key);
+        }),
+        Command::List => keys.list().await.map(|keys| {
+            println!(
+                "{:>4}  {:<12}  {:<20}  {:<5}  {:>8}  {:>9}  {:>7}  {:>8}  {:<16}  status",
+                "id", "prefix", "name", "admin", "per min", "per day", "today", "total", "last used"
+            );
+            keys.iter().for_each(print_key);
+        }),
+        Command::Revoke(id) => keys.revoke(id).await.map(|revoked| {
+            if revoked {
+                println!("revoked API key {}", id);
+            } else {
+                println!("no active API key {}", id);
+            }
+        }),
+    };
+    pool.close().await;
+    result.map_err(|e| e.to_string())
+}
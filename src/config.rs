@@ -0,0 +1,650 @@
+// Typed configuration loaded from a TOML file, with `.env` and environment overrides.
+//
+// Any key can be overridden with a `CNA_` variable where `__` separates path segments, e.g.
+// `CNA_SERVER__BIND=0.0.0.0:8000` or `CNA_SOURCES__COINGECKO__API_KEY=...`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use tracing::level_filters::LevelFilter;
+
+use crate::models::watchlist::Watchlist;
+use crate::services::alerts::AlertRule;
+
+const ENV_PREFIX: &str = "CNA_";
+
+// The sources a config can set up, with their default base URL and API key header; a config only
+// needs to name them to change settings.
+const DEFAULT_SOURCES: &[(&str, &str, &str)] = &[
+    ("cryptqnews", "https://api.cryptqnews.com/v1", "X-API-Key"),
+    ("coingecko", "https://api.coingecko.com/api/v3", "x-cg-demo-api-key"),
+];
+
+// For managed sources added without an `api_key_header`.
+pub const DEFAULT_API_KEY_HEADER: &str = "X-API-Key";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub logging: LoggingConfig,
+    pub http: HttpConfig,
+    pub storage: StorageConfig,
+    pub cache: CacheConfig,
+    pub sources: BTreeMap<String, SourceConfig>,
+    pub smtp: Option<SmtpConfig>,
+    pub digest: DigestConfig,
+    pub alerts: AlertsConfig,
+    pub auth: AuthConfig,
+    pub searches: SearchesConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    // How long shutdown waits for in-flight requests, and then for running background jobs.
+    pub shutdown_timeout_secs: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    // trace, debug, info, warn, error or off; `RUST_LOG` replaces all of `[logging]` levels when set.
+    pub level: String,
+    pub format: LogFormat,
+    // Per-module levels, e.g. `"crypto_news_aggregator::api" = "debug"`.
+    pub modules: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// Shared by every outgoing request to the sources.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub connect_timeout_secs: u64,
+    // Whole request, including reading the body.
+    pub timeout_secs: u64,
+    pub user_agent: String,
+    // e.g. `http://proxy.internal:3128`; also honours HTTP_PROXY/HTTPS_PROXY when unset.
+    pub proxy: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub database_url: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub ttl_secs: u64,
+    // Where the quote cache is saved every `snapshot_interval_secs` and on shutdown, and restored
+    // from on startup. No snapshots when unset.
+    pub snapshot_path: Option<String>,
+    pub snapshot_interval_secs: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceConfig {
+    // The key under `[sources]`, filled in when the config is loaded.
+    #[serde(skip)]
+    pub name: String,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    // Header the API key is sent in.
+    pub api_key_header: Option<String>,
+    // Extra headers sent with every request to this source.
+    pub headers: BTreeMap<String, String>,
+    pub interval_secs: u64,
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    // Off for local SMTP sinks that only speak plain SMTP.
+    #[serde(default)]
+    pub tls: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default = "default_smtp_from")]
+    pub from: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestConfig {
+    pub recipients: Vec<String>,
+    pub watchlists: Vec<Watchlist>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertsConfig {
+    // e.g. `BTC>5%/1h`
+    pub rules: Vec<String>,
+    pub recipients: Vec<String>,
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // Require an API key or user access token on every route but `/`, `/healthz`, `/readyz`,
+    // `/metrics` and `/auth/*`. `/admin` routes always need an admin key.
+    pub enabled: bool,
+    // For keys created without their own limits. No daily quota when unset.
+    pub rate_limit_per_minute: u32,
+    pub daily_quota: Option<u64>,
+    // Signs user access and refresh tokens. User accounts are unavailable while it is unset.
+    pub jwt_secret: Option<String>,
+    pub access_token_secs: u64,
+    pub refresh_token_secs: u64,
+    // Whether anyone may create an account with `POST /auth/register`.
+    pub registration: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchesConfig {
+    pub max_per_user: usize,
+    // Whether users may have matches posted to their own webhook URLs, which the server will call.
+    pub webhooks: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: "127.0.0.1:8000".to_string(),
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            enabled: false,
+            rate_limit_per_minute: 60,
+            daily_quota: None,
+            jwt_secret: None,
+            access_token_secs: 15 * 60,
+            refresh_token_secs: 30 * 24 * 60 * 60,
+            registration: true,
+        }
+    }
+}
+
+impl Default for SearchesConfig {
+    fn default() -> Self {
+        SearchesConfig {
+            max_per_user: 20,
+            webhooks: false,
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            modules: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout_secs: 10,
+            timeout_secs: 30,
+            user_agent: format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            proxy: None,
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            database_url: "sqlite://news.db".to_string(),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            ttl_secs: 60,
+            snapshot_path: None,
+            snapshot_interval_secs: 60,
+        }
+    }
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        SourceConfig {
+            name: String::new(),
+            base_url: String::new(),
+            api_key: None,
+            api_key_header: None,
+            headers: BTreeMap::new(),
+            interval_secs: 300,
+            enabled: true,
+        }
+    }
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+fn default_smtp_from() -> String {
+    "news@localhost".to_string()
+}
+
+// Points at the offending key, e.g. `sources.coingecko.base_url: must start with http:// or https://`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        ConfigError {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "invalid config: {}", self.message)
+        } else {
+            write!(f, "invalid config at `{}`: {}", self.key, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Environment values are read as TOML when they parse (numbers, booleans, arrays) and as strings otherwise.
+fn env_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+// Keys whose values are never logged; source headers often carry credentials too.
+const SECRET_KEYS: &[&str] = &["api_key", "password", "jwt_secret"];
+
+fn describe_value(key: &str, value: &toml::Value) -> String {
+    if SECRET_KEYS.iter().any(|secret| key.ends_with(secret)) || key.contains(".headers.") {
+        "<redacted>".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+// Appends `key: old -> new` lines for every leaf that differs between two tables.
+fn diff_values(prefix: &str, old: &toml::Value, new: &toml::Value, changes: &mut Vec<String>) {
+    match (old, new) {
+        (toml::Value::Table(old), toml::Value::Table(new)) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                let empty = toml::Value::Table(toml::Table::new());
+                match (old.get(key), new.get(key)) {
+                    (Some(old), Some(new)) => diff_values(&path, old, new, changes),
+                    // New tables are listed key by key so their secrets are redacted too.
+                    (None, Some(new)) if new.is_table() => diff_values(&path, &empty, new, changes),
+                    (None, Some(new)) => changes.push(format!("{}: added {}", path, describe_value(&path, new))),
+                    (Some(_), None) => changes.push(format!("{}: removed", path)),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if old != new => changes.push(format!(
+            "{}: {} -> {}",
+            prefix,
+            describe_value(prefix, old),
+            describe_value(prefix, new)
+        )),
+        _ => {}
+    }
+}
+
+fn set_path(root: &mut toml::Table, path: &[String], value: toml::Value) -> Result<(), ConfigError> {
+    let (last, parents) = path.split_last().expect("override paths are never empty");
+    let mut table = root;
+    for (depth, segment) in parents.iter().enumerate() {
+        let entry = table
+            .entry(segment.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        table = match entry {
+            toml::Value::Table(table) => table,
+            _ => return Err(ConfigError::new(path[..=depth].join("."), "is not a table")),
+        };
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+impl Config {
+    // Reads `path` (a missing file means all defaults), then applies `.env` and environment overrides.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        dotenv::dotenv().ok();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(ConfigError::new("", format!("cannot read {}: {}", path.display(), e))),
+        };
+        Config::parse(&text, std::env::vars())
+    }
+
+    pub fn parse(text: &str, env: impl Iterator<Item = (String, String)>) -> Result<Config, ConfigError> {
+        let mut root: toml::Table = toml::from_str(text).map_err(|e| ConfigError::new("", e.to_string()))?;
+
+        let mut overrides = Vec::new();
+        for (name, raw) in env {
+            let path = match name.strip_prefix(ENV_PREFIX) {
+                Some(path) if !path.is_empty() => path,
+                _ => continue,
+            };
+            let path: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+            set_path(&mut root, &path, env_value(&raw))?;
+            overrides.push((path, raw));
+        }
+
+        // An override like `API_KEY=12345` reads as a number; when a string was expected, retry it as one.
+        let mut config = loop {
+            match serde_path_to_error::deserialize::<_, Config>(toml::Value::Table(root.clone())) {
+                Ok(config) => break config,
+                Err(e) => {
+                    let key = e.path().to_string();
+                    let retry = overrides
+                        .iter()
+                        .position(|(path, _)| path.join(".") == key)
+                        .map(|index| overrides.remove(index));
+                    match retry {
+                        Some((path, raw)) => set_path(&mut root, &path, toml::Value::String(raw))?,
+                        None => return Err(ConfigError::new(key, e.into_inner().message())),
+                    }
+                }
+            }
+        };
+
+        for watchlist in config.digest.watchlists.iter_mut() {
+            watchlist.symbols = watchlist.symbols.iter().map(|symbol| symbol.to_uppercase()).collect();
+        }
+        for (name, base_url, api_key_header) in DEFAULT_SOURCES {
+            let source = config.sources.entry(name.to_string()).or_default();
+            if source.base_url.is_empty() {
+                source.base_url = base_url.to_string();
+            }
+            if source.api_key_header.is_none() {
+                source.api_key_header = Some(api_key_header.to_string());
+            }
+        }
+        for (name, source) in config.sources.iter_mut() {
+            source.name = name.clone();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.server.bind.parse::<std::net::SocketAddr>().is_err() {
+            return Err(ConfigError::new("server.bind", "must be an address like 127.0.0.1:8000"));
+        }
+        if LevelFilter::from_str(&self.logging.level).is_err() {
+            return Err(ConfigError::new("logging.level", "must be trace, debug, info, warn, error or off"));
+        }
+        for (module, level) in &self.logging.modules {
+            if LevelFilter::from_str(level).is_err() {
+                return Err(ConfigError::new(
+                    format!("logging.modules.{}", module),
+                    "must be trace, debug, info, warn, error or off",
+                ));
+            }
+        }
+        if self.http.connect_timeout_secs == 0 {
+            return Err(ConfigError::new("http.connect_timeout_secs", "must be greater than 0"));
+        }
+        if self.http.timeout_secs == 0 {
+            return Err(ConfigError::new("http.timeout_secs", "must be greater than 0"));
+        }
+        if HeaderValue::from_str(&self.http.user_agent).is_err() {
+            return Err(ConfigError::new("http.user_agent", "must be a valid header value"));
+        }
+        if let Some(proxy) = &self.http.proxy {
+            if let Err(e) = reqwest::Proxy::all(proxy) {
+                return Err(ConfigError::new("http.proxy", e.to_string()));
+            }
+        }
+        if !self.storage.database_url.starts_with("sqlite:") {
+            return Err(ConfigError::new("storage.database_url", "must be a sqlite: URL"));
+        }
+        if self.cache.ttl_secs == 0 {
+            return Err(ConfigError::new("cache.ttl_secs", "must be greater than 0"));
+        }
+        if self.cache.snapshot_interval_secs == 0 {
+            return Err(ConfigError::new("cache.snapshot_interval_secs", "must be greater than 0"));
+        }
+        for (name, source) in &self.sources {
+            if !DEFAULT_SOURCES.iter().any(|(known, _, _)| known == name) {
+                return Err(ConfigError::new(
+                    format!("sources.{}", name),
+                    "is not a known source; use cryptqnews or coingecko, or add feeds through /admin/sources",
+                ));
+            }
+            let key = |field: &str| format!("sources.{}.{}", name, field);
+            if !source.base_url.starts_with("http://") && !source.base_url.starts_with("https://") {
+                return Err(ConfigError::new(key("base_url"), "must start with http:// or https://"));
+            }
+            if source.interval_secs == 0 {
+                return Err(ConfigError::new(key("interval_secs"), "must be greater than 0"));
+            }
+            if let Some(header) = &source.api_key_header {
+                if HeaderName::from_bytes(header.as_bytes()).is_err() {
+                    return Err(ConfigError::new(key("api_key_header"), "must be a valid header name"));
+                }
+            }
+            if let Some(api_key) = &source.api_key {
+                if HeaderValue::from_str(api_key).is_err() {
+                    return Err(ConfigError::new(key("api_key"), "must be a valid header value"));
+                }
+            }
+            for (name, value) in &source.headers {
+                let key = key(&format!("headers.{}", name));
+                if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    return Err(ConfigError::new(key, "is not a valid header name"));
+                }
+                if HeaderValue::from_str(value).is_err() {
+                    return Err(ConfigError::new(key, "must be a valid header value"));
+                }
+            }
+        }
+        if let Some(smtp) = &self.smtp {
+            if smtp.host.is_empty() {
+                return Err(ConfigError::new("smtp.host", "must not be empty"));
+            }
+            if smtp.from.parse::<lettre::message::Mailbox>().is_err() {
+                return Err(ConfigError::new("smtp.from", "must be an email address"));
+            }
+        }
+        for (index, watchlist) in self.digest.watchlists.iter().enumerate() {
+            if watchlist.name.is_empty() {
+                return Err(ConfigError::new(format!("digest.watchlists[{}].name", index), "must not be empty"));
+            }
+            if watchlist.symbols.is_empty() {
+                return Err(ConfigError::new(
+                    format!("digest.watchlists[{}].symbols", index),
+                    "must list at least one symbol",
+                ));
+            }
+        }
+        for (index, rule) in self.alerts.rules.iter().enumerate() {
+            AlertRule::parse(rule).map_err(|e| ConfigError::new(format!("alerts.rules[{}]", index), e))?;
+        }
+        if let Some(url) = &self.alerts.webhook_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(ConfigError::new("alerts.webhook_url", "must start with http:// or https://"));
+            }
+        }
+        if self.auth.rate_limit_per_minute == 0 {
+            return Err(ConfigError::new("auth.rate_limit_per_minute", "must be greater than 0"));
+        }
+        if self.auth.daily_quota == Some(0) {
+            return Err(ConfigError::new("auth.daily_quota", "must be greater than 0, or left out for no quota"));
+        }
+        if self.auth.jwt_secret.as_ref().is_some_and(|secret| secret.len() < 32) {
+            return Err(ConfigError::new("auth.jwt_secret", "must be at least 32 characters"));
+        }
+        if self.auth.access_token_secs == 0 {
+            return Err(ConfigError::new("auth.access_token_secs", "must be greater than 0"));
+        }
+        if self.auth.refresh_token_secs <= self.auth.access_token_secs {
+            return Err(ConfigError::new("auth.refresh_token_secs", "must be longer than auth.access_token_secs"));
+        }
+        if self.searches.max_per_user == 0 {
+            return Err(ConfigError::new("searches.max_per_user", "must be greater than 0"));
+        }
+        Ok(())
+    }
+
+    // Enabled sources only.
+    pub fn source(&self, name: &str) -> Option<&SourceConfig> {
+        self.sources.get(name).filter(|source| source.enabled)
+    }
+
+    // Human-readable list of the settings that differ from `previous`, with secrets redacted.
+    pub fn diff(&self, previous: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+        if let (Ok(old), Ok(new)) = (toml::Value::try_from(previous), toml::Value::try_from(self)) {
+            diff_values("", &old, &new, &mut changes);
+        }
+        changes
+    }
+
+    // Settings that are only read at startup.
+    pub fn needs_restart(&self, previous: &Config) -> bool {
+        self.server != previous.server
+            || self.logging != previous.logging
+            || self.http != previous.http
+            || self.storage != previous.storage
+            || self.cache != previous.cache
+            || self.smtp != previous.smtp
+            || self.digest.recipients != previous.digest.recipients
+    }
+
+    pub fn alert_rules(&self) -> Vec<AlertRule> {
+        // Already validated in `parse`.
+        self.alerts.rules.iter().filter_map(|rule| AlertRule::parse(rule).ok()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn error_key(text: &str, vars: &[(&str, &str)]) -> String {
+        Config::parse(text, env(vars)).unwrap_err().key
+    }
+
+    #[test]
+    fn env_overrides_replace_file_values() {
+        let config = Config::parse(
+            "[server]\nbind = \"127.0.0.1:8000\"\n",
+            env(&[
+                ("CNA_SERVER__BIND", "0.0.0.0:9000"),
+                ("CNA_SOURCES__COINGECKO__ENABLED", "false"),
+                ("OTHER_SERVER__BIND", "0.0.0.0:1"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.server.bind, "0.0.0.0:9000");
+        assert!(!config.sources["coingecko"].enabled);
+        assert!(config.source("coingecko").is_none());
+    }
+
+    #[test]
+    fn env_values_are_read_as_the_type_the_key_expects() {
+        let config = Config::parse(
+            "",
+            env(&[
+                ("CNA_SOURCES__COINGECKO__INTERVAL_SECS", "120"),
+                ("CNA_SOURCES__COINGECKO__API_KEY", "12345"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.sources["coingecko"].interval_secs, 120);
+        assert_eq!(config.sources["coingecko"].api_key.as_deref(), Some("12345"));
+
+        assert_eq!(error_key("", &[("CNA_CACHE__TTL_SECS", "soon")]), "cache.ttl_secs");
+    }
+
+    #[test]
+    fn validation_errors_name_the_key() {
+        assert_eq!(error_key("[server]\nbind = \"nowhere\"\n", &[]), "server.bind");
+        assert_eq!(error_key("[cache]\nttl_secs = 0\n", &[]), "cache.ttl_secs");
+        assert_eq!(
+            error_key("[sources.coingecko]\nbase_url = \"ftp://example.com\"\n", &[]),
+            "sources.coingecko.base_url"
+        );
+        assert_eq!(
+            error_key("[sources.cryptqnews.headers]\n\"bad header\" = \"x\"\n", &[]),
+            "sources.cryptqnews.headers.bad header"
+        );
+        assert_eq!(error_key("[sources.newsapi]\nbase_url = \"https://example.com\"\n", &[]), "sources.newsapi");
+        assert_eq!(error_key("[logging]\nlevel = \"loud\"\n", &[]), "logging.level");
+    }
+
+    #[test]
+    fn diffs_redact_secrets() {
+        let previous = Config::parse("[sources.coingecko]\napi_key = \"old-secret\"\n", std::iter::empty()).unwrap();
+        let config = Config::parse(
+            "[sources.coingecko]\napi_key = \"new-secret\"\ninterval_secs = 600\n\
+             [sources.coingecko.headers]\nAuthorization = \"Bearer token-secret\"\n",
+            std::iter::empty(),
+        )
+        .unwrap();
+        let changes = config.diff(&previous);
+        assert!(changes.contains(&"sources.coingecko.api_key: <redacted> -> <redacted>".to_string()));
+        assert!(changes.contains(&"sources.coingecko.headers.Authorization: added <redacted>".to_string()));
+        assert!(changes.contains(&"sources.coingecko.interval_secs: 300 -> 600".to_string()));
+        assert!(changes.iter().all(|change| !change.contains("secret")));
+    }
+}
@@ -1,39 +1,332 @@
 // main.rs
-use actix_web::{web, App, HttpServer, Responder, HttpResponse};
-use std::sync::Mutex;
-use crate::api::cryptonews::fetch_news;
-use crate::models::news::NewsRequest;
-
-#[macro_use]
-extern crate serde_derive;
+use actix_web::dev::Service;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{middleware, web, App, HttpServer};
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::Instrument;
+use crate::api::HttpClient;
+use crate::config::Config;
+use crate::services::alerts::Alerts;
+use crate::services::apikeys::ApiKeys;
+use crate::services::archive::Archive;
+use crate::services::article_states::ArticleStates;
+use crate::services::cache::{self, Cache};
+use crate::services::db;
+use crate::services::digest::{self, Period};
+use crate::services::ingest::Ingest;
+use crate::services::logging;
+use crate::services::mailer::Mailer;
+use crate::services::metrics::Metrics;
+use crate::services::notify::{self, Notifier};
+use crate::services::prices::{self, Prices};
+use crate::services::reload;
+use crate::services::searches::SavedSearches;
+use crate::services::shutdown::Jobs;
+use crate::services::sources::{self, Fetcher, Sources};
+use crate::services::stories;
+use crate::services::users::Users;
+use crate::services::watchlists::Watchlists;
 
 mod api;
+mod cli;
+mod config;
 mod models;
+mod routes;
 mod services;
-mod web;
 
 struct AppState {
-    // Add any shared state here, e.g., cache
+    archive: Archive,
+    prices: Prices,
+    alerts: Alerts,
+    api_keys: ApiKeys,
+    users: Users,
+    watchlists: Watchlists,
+    article_states: ArticleStates,
+    searches: SavedSearches,
+    ingest: Ingest,
+    sources: Sources,
+    fetcher: Fetcher,
+    http: HttpClient,
+    metrics: Metrics,
+    cache: Arc<Mutex<Cache>>,
+    // Replaced on every successful reload; see `services::reload`.
+    config: watch::Receiver<Arc<Config>>,
+}
+
+// The state `main` builds, over a fresh database in the temp directory and without background
+// jobs, for handler tests.
+#[cfg(test)]
+async fn test_state(config: Config) -> web::Data<AppState> {
+    let path = env::temp_dir().join(format!("cna-test-{}.db", uuid::Uuid::new_v4().simple()));
+    let pool = db::connect(&format!("sqlite://{}", path.display())).await.unwrap();
+    let metrics = Metrics::new();
+    let http = HttpClient::new(&config.http, metrics.clone()).unwrap();
+    let archive = Archive::new(pool.clone());
+    let notifier = Notifier::new(pool.clone(), None, http.clone(), metrics.clone());
+    let searches = SavedSearches::new(pool.clone(), notifier.clone());
+    let ingest = Ingest::new(archive.clone(), metrics.clone(), searches.clone());
+    let sources = Sources::new(pool.clone());
+    let (_, config) = watch::channel(Arc::new(config));
+    web::Data::new(AppState {
+        archive,
+        prices: Prices::new(pool.clone()),
+        alerts: Alerts::new(pool.clone(), notifier),
+        api_keys: ApiKeys::new(pool.clone()),
+        users: Users::new(pool.clone()),
+        watchlists: Watchlists::new(pool.clone()),
+        article_states: ArticleStates::new(pool.clone()),
+        searches,
+        fetcher: Fetcher::new(http.clone(), sources.clone(), ingest.clone()),
+        ingest,
+        sources,
+        http,
+        metrics,
+        cache: Arc::new(Mutex::new(Cache::new(Duration::from_secs(60)))),
+        config,
+    })
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let state = web::Data::new(Mutex::new(AppState {}));
+    let config_path = env::var("CONFIG_FILE").unwrap_or_else(|_| "config.toml".to_string());
+    let config = Config::load(Path::new(&config_path))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = cli::run(&args, &config).await {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+    logging::init(&config.logging);
+    let (config_sender, config_receiver) = watch::channel(Arc::new(config.clone()));
+    tokio::spawn(reload::run(PathBuf::from(&config_path), config_sender));
+
+    let metrics = Metrics::new();
+    let http = HttpClient::new(&config.http, metrics.clone()).map_err(io::Error::other)?;
+    let pool = db::connect(&config.storage.database_url)
+        .await
+        .map_err(io::Error::other)?;
+    let archive = Archive::new(pool.clone());
+    let prices = Prices::new(pool.clone());
+
+    let mailer = match &config.smtp {
+        Some(smtp) => Some(Mailer::from_config(smtp).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?),
+        None => None,
+    };
+    let mut jobs = Jobs::new();
+    if let Some(mailer) = mailer.clone() {
+        if !config.digest.recipients.is_empty() {
+            for period in [Period::Daily, Period::Weekly] {
+                jobs.spawn(
+                    "digest",
+                    digest::run_schedule(
+                        pool.clone(),
+                        archive.clone(),
+                        mailer.clone(),
+                        config_receiver.clone(),
+                        config.digest.recipients.clone(),
+                        period,
+                        jobs.shutdown(),
+                    ),
+                );
+            }
+        }
+    }
+
+    let notifier = Notifier::new(pool.clone(), mailer, http.clone(), metrics.clone());
+    jobs.spawn("notifications", notify::run_deliveries(notifier.clone(), jobs.shutdown()));
+
+    let searches = SavedSearches::new(pool.clone(), notifier.clone());
+    searches.reload().await.map_err(io::Error::other)?;
+
+    let alerts = Alerts::new(pool.clone(), notifier);
+
+    let ingest = Ingest::new(archive.clone(), metrics.clone(), searches.clone());
+    let sources = Sources::new(pool.clone());
+    let fetcher = Fetcher::new(http.clone(), sources.clone(), ingest.clone());
+    jobs.spawn(
+        "sources",
+        sources::run_schedule(fetcher.clone(), config_receiver.clone(), jobs.shutdown()),
+    );
+
+    let cache = Arc::new(Mutex::new(Cache::new(Duration::from_secs(config.cache.ttl_secs))));
+    let snapshot_path = config.cache.snapshot_path.as_ref().map(PathBuf::from);
+    if let Some(path) = &snapshot_path {
+        let restored = cache.lock().unwrap().load(path);
+        match restored {
+            Ok(entries) => tracing::info!(entries, path = %path.display(), "restored cache snapshot"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "failed to restore cache snapshot"),
+        }
+        jobs.spawn(
+            "cache_snapshot",
+            cache::run_snapshots(
+                cache.clone(),
+                path.clone(),
+                Duration::from_secs(config.cache.snapshot_interval_secs),
+                jobs.shutdown(),
+            ),
+        );
+    }
+
+    jobs.spawn("stories", stories::run_schedule(archive.clone(), jobs.shutdown()));
+    jobs.spawn(
+        "prices",
+        prices::run_schedule(
+            http.clone(),
+            config_receiver.clone(),
+            archive.clone(),
+            prices.clone(),
+            alerts.clone(),
+            jobs.shutdown(),
+        ),
+    );
+
+    let bind = config.server.bind.clone();
+    let shutdown_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+    let state = web::Data::new(AppState {
+        archive,
+        prices,
+        alerts,
+        api_keys: ApiKeys::new(pool.clone()),
+        users: Users::new(pool.clone()),
+        watchlists: Watchlists::new(pool.clone()),
+        article_states: ArticleStates::new(pool.clone()),
+        searches,
+        ingest,
+        sources,
+        fetcher,
+        http,
+        metrics: metrics.clone(),
+        cache: cache.clone(),
+        config: config_receiver,
+    });
 
     HttpServer::new(move || {
+        let metrics = metrics.clone();
         App::new()
             .app_data(state.clone())
-            .route("/", web::get().to(web::index))
-            .route("/news", web::post().to(get_news))
+            // Innermost, so denied requests are still counted and logged.
+            .wrap(middleware::from_fn(routes::keys::authenticate))
+            // Routing happens inside, so the matched pattern is read from the response's request.
+            .wrap_fn(move |req, srv| {
+                let metrics = metrics.clone();
+                let method = req.method().to_string();
+                let started = Instant::now();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await?;
+                    let route = response.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+                    metrics.observe_request(&route, &method, response.status().as_u16(), started.elapsed());
+                    Ok(response)
+                }
+            })
+            // Outermost, so everything a request does, including source fetches, runs in its span.
+            .wrap_fn(|req, srv| {
+                let header = req.headers().get(logging::REQUEST_ID_HEADER);
+                let request_id = logging::request_id(header.and_then(|value| value.to_str().ok()));
+                let span = tracing::info_span!(
+                    "request",
+                    request_id = %request_id,
+                    method = %req.method(),
+                    path = %logging::loggable_path(req.path(), req.match_info().as_str()),
+                );
+                let started = Instant::now();
+                let response = span.in_scope(|| srv.call(req));
+                async move {
+                    let mut response = response.await?;
+                    tracing::info!(
+                        status = response.status().as_u16(),
+                        elapsed_ms = started.elapsed().as_millis() as u64,
+                        "request completed"
+                    );
+                    if let Ok(value) = HeaderValue::from_str(&request_id) {
+                        response.headers_mut().insert(HeaderName::from_static(logging::REQUEST_ID_HEADER), value);
+                    }
+                    Ok(response)
+                }
+                .instrument(span)
+            })
+            .route("/", web::get().to(routes::index::index))
+            .route("/static/{file}", web::get().to(routes::index::asset))
+            .route("/news", web::post().to(routes::news::get_news))
+            .route("/news", web::get().to(routes::news::query_news))
+            .route("/digest/{watchlist}", web::get().to(routes::digest::get_digest))
+            .route("/sentiment/{symbol}", web::get().to(routes::sentiment::get_sentiment))
+            .route("/trending", web::get().to(routes::trending::get_trending))
+            .route("/stories", web::get().to(routes::stories::get_stories))
+            .route("/stories/{id}", web::get().to(routes::stories::get_story))
+            .route("/impact/{symbol}", web::get().to(routes::impact::get_impact))
+            .route("/alerts", web::get().to(routes::alerts::get_alerts))
+            .route("/market", web::get().to(routes::market::get_quotes))
+            .route("/market/price", web::get().to(routes::market::get_price))
+            .route("/market/coins", web::get().to(routes::market::get_coins))
+            .route("/market/{id}/chart", web::get().to(routes::market::get_chart))
+            .route("/metrics", web::get().to(routes::metrics::get_metrics))
+            .route("/healthz", web::get().to(routes::health::get_healthz))
+            .route("/readyz", web::get().to(routes::health::get_readyz))
+            .route("/sources", web::get().to(routes::health::get_sources))
+            .route("/auth/register", web::post().to(routes::auth::register))
+            .route("/auth/login", web::post().to(routes::auth::login))
+            .route("/auth/refresh", web::post().to(routes::auth::refresh))
+            .route("/auth/logout", web::post().to(routes::auth::logout))
+            .route("/me", web::get().to(routes::auth::get_me))
+            .route("/me/languages", web::put().to(routes::auth::put_languages))
+            .route("/me/watchlists", web::get().to(routes::feed::get_watchlists))
+            .route("/me/watchlists/{name}", web::put().to(routes::feed::put_watchlist))
+            .route("/me/watchlists/{name}", web::delete().to(routes::feed::delete_watchlist))
+            .route("/me/feed", web::get().to(routes::feed::get_feed))
+            .route("/me/feed/read", web::post().to(routes::feed::mark_read))
+            .route("/me/unread", web::get().to(routes::feed::get_unread))
+            .route("/me/bookmarks", web::get().to(routes::feed::get_bookmarks))
+            .route("/me/searches", web::get().to(routes::searches::get_searches))
+            .route("/me/searches/{name}", web::put().to(routes::searches::put_search))
+            .route("/me/searches/{name}", web::delete().to(routes::searches::delete_search))
+            .route("/me/searches/{name}/articles", web::get().to(routes::searches::get_search_articles))
+            .route("/me/articles/{id}/read", web::put().to(routes::feed::put_read))
+            .route("/me/articles/{id}/read", web::delete().to(routes::feed::delete_read))
+            .route("/me/articles/{id}/bookmark", web::put().to(routes::feed::put_bookmark))
+            .route("/me/articles/{id}/bookmark", web::delete().to(routes::feed::delete_bookmark))
+            .route("/me/feed-token", web::post().to(routes::feed::create_feed_token))
+            .route("/me/feed-token", web::delete().to(routes::feed::delete_feed_token))
+            .route("/feeds/{token}", web::get().to(routes::feed::get_rss))
+            .route("/admin/keys", web::post().to(routes::keys::create_key))
+            .route("/admin/keys", web::get().to(routes::keys::list_keys))
+            .route("/admin/keys/{id}", web::get().to(routes::keys::get_key))
+            .route("/admin/keys/{id}", web::delete().to(routes::keys::revoke_key))
+            .route("/admin/sources", web::post().to(routes::sources::create_source))
+            .route("/admin/sources", web::get().to(routes::sources::list_sources))
+            .route("/admin/sources/{name}", web::get().to(routes::sources::get_source))
+            .route("/admin/sources/{name}", web::patch().to(routes::sources::update_source))
+            .route("/admin/sources/{name}", web::delete().to(routes::sources::delete_source))
+            .route("/admin/sources/{name}/fetch", web::post().to(routes::sources::fetch_source))
+            .route("/admin/sources/{name}/log", web::get().to(routes::sources::get_source_log))
+            .route("/admin/sources/{name}/reset", web::post().to(routes::sources::reset_source))
+            .route("/admin/fetch-log", web::get().to(routes::sources::get_fetch_log))
     })
-    .bind("127.0.0.1:8080")?
+    .bind(bind)?
+    .shutdown_timeout(shutdown_timeout.as_secs())
     .run()
-    .await
-}
+    .await?;
 
-async fn get_news(req: web::Json<NewsRequest>) -> impl Responder {
-    match fetch_news(&req.symbol).await {
-        Ok(news) => HttpResponse::Ok().json(news),
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    // The server returns once SIGTERM or SIGINT has stopped it accepting connections and the
+    // in-flight requests have finished, or `shutdown_timeout` has passed.
+    tracing::info!("server stopped, waiting for background jobs");
+    jobs.drain(shutdown_timeout).await;
+    if let Some(path) = &snapshot_path {
+        match cache::save(&cache, path).await {
+            Ok(entries) => tracing::info!(entries, path = %path.display(), "saved cache snapshot"),
+            Err(e) => tracing::error!(path = %path.display(), error = %e, "failed to save cache snapshot"),
+        }
     }
-}
\ No newline at end of file
+    pool.close().await;
+    tracing::info!("shutdown complete");
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::CoinMarket;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarketQuote {
+    pub symbol: String,
+    pub id: String,
+    pub name: String,
+    pub price: Option<f64>,
+    pub change_24h: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub last_updated: Option<String>,
+}
+
+impl MarketQuote {
+    pub fn from_market(market: &CoinMarket) -> Self {
+        MarketQuote {
+            symbol: market.symbol.to_uppercase(),
+            id: market.id.clone(),
+            name: market.name.clone(),
+            price: market.current_price,
+            change_24h: market.price_change_percentage_24h,
+            market_cap: market.market_cap,
+            last_updated: market.last_updated.clone(),
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod market;
+pub mod news;
+pub mod watchlist;
@@ -1,9 +1,23 @@
-struct NewsArticle {
-    title: String,
-    source: String,
-    date: String,
-    summary: String,
-    link: String,
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::market::MarketQuote;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewsArticle {
+    pub title: String,
+    pub source: String,
+    pub date: String,
+    #[serde(default)]
+    pub summary: String,
+    pub link: String,
+    #[serde(default)]
+    pub sentiment: Option<f64>,
+    #[serde(default)]
+    pub lang: Option<String>,
+    // Full body text, when the source provides one; only used to build a summary.
+    #[serde(default, skip_serializing)]
+    pub content: Option<String>,
 }
 
 impl NewsArticle {
@@ -14,6 +28,39 @@ impl NewsArticle {
             date,
             summary,
             link,
+            sentiment: None,
+            lang: None,
+            content: None,
         }
     }
-}
\ No newline at end of file
+
+    // Upstream dates are RFC 3339 or RFC 2822 depending on the source.
+    pub fn published_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.date)
+            .or_else(|_| DateTime::parse_from_rfc2822(&self.date))
+            .ok()
+            .map(|date| date.with_timezone(&Utc))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewsRequest {
+    pub symbol: String,
+    // One of positive, neutral or negative.
+    pub sentiment: Option<String>,
+    pub min_sentiment: Option<f64>,
+    pub max_sentiment: Option<f64>,
+    pub summary_length: Option<usize>,
+    // Comma-separated language codes, or `all`.
+    pub lang: Option<String>,
+    // Respond with `NewsResponse` instead of the bare list of articles.
+    #[serde(default)]
+    pub market: bool,
+}
+
+#[derive(Serialize)]
+pub struct NewsResponse {
+    pub articles: Vec<NewsArticle>,
+    // Current quotes for the requested symbol; empty when market data is unavailable.
+    pub market: Vec<MarketQuote>,
+}
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Watchlist {
+    pub name: String,
+    pub symbols: Vec<String>,
+}
@@ -0,0 +1,28 @@
+use actix_web::{web, HttpRequest, Responder};
+use serde::Deserialize;
+
+use crate::routes::{cached_json, internal_error};
+use crate::AppState;
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct AlertsQuery {
+    limit: Option<i64>,
+}
+
+// GET /alerts?limit=20
+pub async fn get_alerts(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AlertsQuery>,
+) -> impl Responder {
+    match state.alerts.recent(&state.archive, query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)).await {
+        Ok(events) => {
+            let last_modified = events.iter().map(|event| event.triggered_at).max();
+            cached_json(&req, &state, last_modified, &events)
+        }
+        Err(e) => internal_error(e),
+    }
+}
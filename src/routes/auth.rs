@@ -0,0 +1,157 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::header::{AUTHORIZATION, RETRY_AFTER};
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+
+use crate::routes::{internal_error, json_error};
+use crate::services::users::{verify_access, AuthError};
+use crate::AppState;
+
+// The signed-in user, from an `Authorization: Bearer` access token. Handlers that take one answer
+// 401 to requests without a valid token.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthUser {
+    pub id: i64,
+}
+
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    Some(token.trim()).filter(|token| scheme.eq_ignore_ascii_case("bearer") && !token.is_empty())
+}
+
+fn auth_error(e: AuthError) -> HttpResponse {
+    match e {
+        AuthError::Disabled => json_error(
+            HttpResponse::ServiceUnavailable(),
+            "accounts_disabled",
+            "user accounts are disabled on this server",
+        ),
+        AuthError::Invalid(message) => json_error(HttpResponse::BadRequest(), "invalid_request", message),
+        AuthError::EmailTaken => json_error(HttpResponse::Conflict(), "email_taken", e.to_string()),
+        AuthError::InvalidCredentials => json_error(HttpResponse::Unauthorized(), "invalid_credentials", e.to_string()),
+        AuthError::InvalidToken => json_error(HttpResponse::Unauthorized(), "invalid_token", e.to_string()),
+        AuthError::TooManyAttempts(retry_after) => {
+            let mut response = HttpResponse::TooManyRequests();
+            response.insert_header((RETRY_AFTER, retry_after.to_string()));
+            json_error(response, "too_many_attempts", e.to_string())
+        }
+        AuthError::Internal(_) | AuthError::Storage(_) => internal_error(e),
+    }
+}
+
+fn rejected(response: HttpResponse) -> actix_web::Error {
+    InternalError::from_response("unauthorized", response).into()
+}
+
+fn authenticate(req: &HttpRequest) -> Result<AuthUser, actix_web::Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>() else {
+        return Err(rejected(internal_error("application state is missing")));
+    };
+    let Some(token) = bearer_token(req) else {
+        return Err(rejected(json_error(
+            HttpResponse::Unauthorized(),
+            "missing_token",
+            "sign in and send the access token as `Authorization: Bearer <token>`",
+        )));
+    };
+    let config = state.config.borrow().clone();
+    let id = verify_access(&config.auth, token).map_err(|e| rejected(auth_error(e)))?;
+    Ok(AuthUser { id })
+}
+
+impl FromRequest for AuthUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticate(req))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    email: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+// The address attempts are counted against: the peer's IP. Forwarding headers are ignored since any
+// client can set them.
+fn client_address(req: &HttpRequest) -> String {
+    req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default()
+}
+
+// POST /auth/register {"email": "ana@example.com", "password": "..."}
+pub async fn register(state: web::Data<AppState>, req: HttpRequest, body: web::Json<Credentials>) -> impl Responder {
+    let config = state.config.borrow().clone();
+    if !config.auth.registration {
+        return json_error(
+            HttpResponse::Forbidden(),
+            "registration_closed",
+            "this server doesn't accept new accounts",
+        );
+    }
+    match state.users.register(&config.auth, &client_address(&req), &body.email, &body.password).await {
+        Ok(session) => HttpResponse::Created().json(session),
+        Err(e) => auth_error(e),
+    }
+}
+
+// POST /auth/login {"email": "ana@example.com", "password": "..."}
+pub async fn login(state: web::Data<AppState>, req: HttpRequest, body: web::Json<Credentials>) -> impl Responder {
+    let config = state.config.borrow().clone();
+    match state.users.login(&config.auth, &client_address(&req), &body.email, &body.password).await {
+        Ok(session) => HttpResponse::Ok().json(session),
+        Err(e) => auth_error(e),
+    }
+}
+
+// POST /auth/refresh {"refresh_token": "..."}
+pub async fn refresh(state: web::Data<AppState>, body: web::Json<RefreshRequest>) -> impl Responder {
+    let config = state.config.borrow().clone();
+    match state.users.refresh(&config.auth, &body.refresh_token).await {
+        Ok(session) => HttpResponse::Ok().json(session),
+        Err(e) => auth_error(e),
+    }
+}
+
+// POST /auth/logout {"refresh_token": "..."}; the access token stays valid until it expires.
+pub async fn logout(state: web::Data<AppState>, body: web::Json<RefreshRequest>) -> impl Responder {
+    let config = state.config.borrow().clone();
+    match state.users.logout(&config.auth, &body.refresh_token).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => auth_error(e),
+    }
+}
+
+// GET /me
+pub async fn get_me(state: web::Data<AppState>, user: AuthUser) -> impl Responder {
+    match state.users.get(user.id).await {
+        Ok(Some(user)) => HttpResponse::Ok().json(user),
+        Ok(None) => auth_error(AuthError::InvalidToken),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LanguagesUpdate {
+    languages: Vec<String>,
+}
+
+// PUT /me/languages {"languages": ["en", "es"]}; an empty list removes the preference.
+pub async fn put_languages(state: web::Data<AppState>, user: AuthUser, update: web::Json<LanguagesUpdate>) -> impl Responder {
+    match state.users.set_languages(user.id, &update.languages).await {
+        Ok(Ok(Some(user))) => HttpResponse::Ok().json(user),
+        Ok(Ok(None)) => auth_error(AuthError::InvalidToken),
+        Ok(Err(message)) => json_error(HttpResponse::BadRequest(), "invalid_languages", message),
+        Err(e) => internal_error(e),
+    }
+}
@@ -0,0 +1,79 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::routes::{cached, cached_json, internal_error, language_filter};
+use crate::services::digest::{self, Period};
+use crate::services::language;
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub struct DigestQuery {
+    period: Option<String>,
+    format: Option<String>,
+    summary_length: Option<usize>,
+    lang: Option<String>,
+}
+
+// GET /digest/{watchlist}?period=daily|weekly&format=markdown|html|text|json&summary_length=200&lang=en
+pub async fn get_digest(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    name: web::Path<String>,
+    query: web::Query<DigestQuery>,
+) -> impl Responder {
+    let config = state.config.borrow().clone();
+    let watchlist = match config.digest.watchlists.iter().find(|w| w.name == *name) {
+        Some(watchlist) => watchlist,
+        None => return HttpResponse::NotFound().body(format!("unknown watchlist {}", name)),
+    };
+    let period = match Period::parse(query.period.as_deref().unwrap_or("daily")) {
+        Some(period) => period,
+        None => return HttpResponse::BadRequest().body("period must be daily or weekly"),
+    };
+
+    let now = Utc::now().timestamp();
+    let languages = match language_filter(&state, &req, None, query.lang.as_deref()).await {
+        Ok(languages) => languages,
+        Err(response) => return response,
+    };
+    let articles = match state.archive.since(now - period.seconds()).await {
+        Ok(mut articles) => {
+            articles.retain(|archived| {
+                watchlist.symbols.contains(&archived.symbol)
+                    && language::matches(&languages, archived.article.lang.as_deref())
+            });
+            articles
+        }
+        Err(e) => return internal_error(e),
+    };
+    let summary_chars = query.summary_length.unwrap_or(digest::SUMMARY_CHARS);
+    let digest = digest::build(watchlist, &articles, period, now, summary_chars);
+    let last_modified = articles.iter().map(|archived| archived.published_at).max();
+
+    match query.format.as_deref().unwrap_or("markdown") {
+        "markdown" | "md" => cached(
+            &req,
+            &state,
+            last_modified,
+            "text/markdown; charset=utf-8",
+            digest.to_markdown().into_bytes(),
+        ),
+        "html" => cached(
+            &req,
+            &state,
+            last_modified,
+            "text/html; charset=utf-8",
+            digest.to_html().into_bytes(),
+        ),
+        "text" | "txt" => cached(
+            &req,
+            &state,
+            last_modified,
+            "text/plain; charset=utf-8",
+            digest.to_text().into_bytes(),
+        ),
+        "json" => cached_json(&req, &state, last_modified, &digest),
+        other => HttpResponse::BadRequest().body(format!("unsupported format {}", other)),
+    }
+}
@@ -0,0 +1,454 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::routes::auth::AuthUser;
+use crate::routes::{cached, cached_json, internal_error, json_error, language_filter, private};
+use crate::services::archive::{FeedArticle, FeedFilter};
+use crate::services::article_states::Mark;
+use crate::services::summarize;
+use crate::services::text::escape_html;
+use crate::services::watchlists::WatchlistUpdate;
+use crate::AppState;
+
+const SUMMARY_CHARS: usize = 280;
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+// The filters of `/news`, plus paging.
+#[derive(Deserialize, Debug, Default)]
+pub struct FeedQuery {
+    // Only this watchlist instead of all of them.
+    watchlist: Option<String>,
+    sentiment: Option<String>,
+    min_sentiment: Option<f64>,
+    max_sentiment: Option<f64>,
+    summary_length: Option<usize>,
+    lang: Option<String>,
+    // Only articles the user hasn't read.
+    unread: Option<bool>,
+    limit: Option<i64>,
+    // The `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+// The articles to act on: those of one watchlist or all of them, or of a single symbol.
+#[derive(Deserialize, Debug, Default)]
+pub struct Scope {
+    watchlist: Option<String>,
+    symbol: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MarkRead {
+    // Unix seconds; articles published at or before it. Now when left out.
+    up_to: Option<i64>,
+    #[serde(flatten)]
+    scope: Scope,
+}
+
+#[derive(Serialize)]
+struct Marked {
+    marked: u64,
+}
+
+// Which of the user's articles a page lists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    Watchlists,
+    Bookmarks,
+    SavedSearch(i64),
+}
+
+#[derive(Serialize)]
+pub struct FeedPage {
+    articles: Vec<FeedArticle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FeedToken {
+    // Shown only in this response.
+    token: String,
+    url: String,
+}
+
+// Cursors are `<published_at>.<id>` of the last article on the page.
+fn parse_cursor(cursor: &str) -> Option<(i64, i64)> {
+    let (published_at, id) = cursor.split_once('.')?;
+    Some((published_at.parse().ok()?, id.parse().ok()?))
+}
+
+fn base_url(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    format!("{}://{}", info.scheme(), info.host())
+}
+
+// The symbols and sources of the user's watchlists, or only of the named one.
+async fn watched(state: &AppState, user_id: i64, watchlist: Option<&str>) -> Result<(Vec<String>, Vec<String>), HttpResponse> {
+    let mut watchlists = state.watchlists.list(user_id).await.map_err(internal_error)?;
+    if let Some(name) = watchlist {
+        watchlists.retain(|watchlist| watchlist.name == name.trim());
+        if watchlists.is_empty() {
+            return Err(json_error(HttpResponse::NotFound(), "not_found", format!("no watchlist {:?}", name)));
+        }
+    }
+    let (mut symbols, mut sources) = (Vec::new(), Vec::new());
+    for watchlist in watchlists {
+        symbols.extend(watchlist.symbols);
+        sources.extend(watchlist.sources);
+    }
+    symbols.sort();
+    symbols.dedup();
+    sources.sort();
+    sources.dedup();
+    Ok((symbols, sources))
+}
+
+async fn scoped(state: &AppState, user_id: i64, scope: &Scope) -> Result<(Vec<String>, Vec<String>), HttpResponse> {
+    match scope.symbol.as_deref() {
+        Some(symbol) => Ok((vec![symbol.trim().to_uppercase()], Vec::new())),
+        None => watched(state, user_id, scope.watchlist.as_deref()).await,
+    }
+}
+
+pub async fn feed(
+    state: &AppState,
+    http: &HttpRequest,
+    user_id: i64,
+    query: &FeedQuery,
+    selection: Selection,
+) -> Result<FeedPage, HttpResponse> {
+    let after = match query.cursor.as_deref() {
+        Some(cursor) => match parse_cursor(cursor) {
+            Some(after) => Some(after),
+            None => return Err(json_error(HttpResponse::BadRequest(), "invalid_cursor", format!("invalid cursor {:?}", cursor))),
+        },
+        None => None,
+    };
+    let (symbols, sources) = match selection {
+        Selection::Watchlists => watched(state, user_id, query.watchlist.as_deref()).await?,
+        Selection::Bookmarks | Selection::SavedSearch(_) => (Vec::new(), Vec::new()),
+    };
+    if selection == Selection::Watchlists && symbols.is_empty() && sources.is_empty() {
+        return Ok(FeedPage {
+            articles: Vec::new(),
+            next_cursor: None,
+        });
+    }
+    let filter = FeedFilter {
+        symbols,
+        sources,
+        user_id: Some(user_id),
+        unread: query.unread.unwrap_or(false),
+        bookmarked: selection == Selection::Bookmarks,
+        saved_search: match selection {
+            Selection::SavedSearch(id) => Some(id),
+            Selection::Watchlists | Selection::Bookmarks => None,
+        },
+        languages: language_filter(state, http, Some(user_id), query.lang.as_deref()).await?,
+        sentiment: query.sentiment.clone(),
+        min_sentiment: query.min_sentiment,
+        max_sentiment: query.max_sentiment,
+        after,
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    };
+
+    let mut articles = state.archive.feed(&filter).await.map_err(internal_error)?;
+    let summary_chars = query.summary_length.unwrap_or(SUMMARY_CHARS);
+    for feed_article in articles.iter_mut() {
+        feed_article.article.summary = summarize::summarize_article(&feed_article.article.summary, None, summary_chars);
+    }
+    let next_cursor = articles
+        .last()
+        .filter(|_| articles.len() as i64 == filter.limit)
+        .map(|last| format!("{}.{}", last.published_at, last.id));
+    Ok(FeedPage { articles, next_cursor })
+}
+
+// The newest article, or the user's last change to read state or bookmarks if that is later.
+pub async fn last_modified(state: &AppState, user_id: i64, page: &FeedPage) -> Result<Option<i64>, HttpResponse> {
+    let changed = state.article_states.last_change(user_id).await.map_err(internal_error)?;
+    Ok(page.articles.iter().map(|article| article.published_at).chain(changed).max())
+}
+
+fn rss(page: &FeedPage, link: &str) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n<channel>\n");
+    out.push_str("<title>Crypto news: your watchlists</title>\n");
+    out.push_str(&format!("<link>{}</link>\n", escape_html(link)));
+    out.push_str("<description>Articles for the symbols and sources on your watchlists</description>\n");
+    for item in &page.articles {
+        let article = &item.article;
+        out.push_str("<item>\n");
+        out.push_str(&format!("<title>{}</title>\n", escape_html(&article.title)));
+        out.push_str(&format!("<link>{}</link>\n", escape_html(&article.link)));
+        out.push_str(&format!("<guid isPermaLink=\"true\">{}</guid>\n", escape_html(&article.link)));
+        if let Some(published_at) = Utc.timestamp_opt(item.published_at, 0).single() {
+            out.push_str(&format!("<pubDate>{}</pubDate>\n", published_at.to_rfc2822()));
+        }
+        out.push_str(&format!("<description>{}</description>\n", escape_html(&article.summary)));
+        for symbol in &item.symbols {
+            out.push_str(&format!("<category>{}</category>\n", escape_html(symbol)));
+        }
+        out.push_str("</item>\n");
+    }
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+// GET /me/watchlists
+pub async fn get_watchlists(state: web::Data<AppState>, user: AuthUser) -> impl Responder {
+    match state.watchlists.list(user.id).await {
+        Ok(watchlists) => HttpResponse::Ok().json(watchlists),
+        Err(e) => internal_error(e),
+    }
+}
+
+// PUT /me/watchlists/{name} {"symbols": ["BTC", "ETH"], "sources": ["CoinDesk"]}
+pub async fn put_watchlist(
+    state: web::Data<AppState>,
+    user: AuthUser,
+    name: web::Path<String>,
+    update: web::Json<WatchlistUpdate>,
+) -> impl Responder {
+    match state.watchlists.put(user.id, &name, &update).await {
+        Ok(Ok(watchlist)) => HttpResponse::Ok().json(watchlist),
+        Ok(Err(message)) => json_error(HttpResponse::BadRequest(), "invalid_watchlist", message),
+        Err(e) => internal_error(e),
+    }
+}
+
+// DELETE /me/watchlists/{name}
+pub async fn delete_watchlist(state: web::Data<AppState>, user: AuthUser, name: web::Path<String>) -> impl Responder {
+    match state.watchlists.delete(user.id, &name).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => json_error(HttpResponse::NotFound(), "not_found", format!("no watchlist {:?}", name.as_str())),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /me/feed?watchlist=majors&sentiment=positive&limit=50&cursor=...
+pub async fn get_feed(
+    state: web::Data<AppState>,
+    http: HttpRequest,
+    user: AuthUser,
+    query: web::Query<FeedQuery>,
+) -> HttpResponse {
+    let page = match feed(&state, &http, user.id, &query, Selection::Watchlists).await {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    match last_modified(&state, user.id, &page).await {
+        Ok(last_modified) => private(cached_json(&http, &state, last_modified, &page)),
+        Err(response) => response,
+    }
+}
+
+// GET /me/bookmarks?limit=50&cursor=..., with the filters of /me/feed
+pub async fn get_bookmarks(
+    state: web::Data<AppState>,
+    http: HttpRequest,
+    user: AuthUser,
+    query: web::Query<FeedQuery>,
+) -> HttpResponse {
+    let page = match feed(&state, &http, user.id, &query, Selection::Bookmarks).await {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    match last_modified(&state, user.id, &page).await {
+        Ok(last_modified) => private(cached_json(&http, &state, last_modified, &page)),
+        Err(response) => response,
+    }
+}
+
+async fn mark(state: &AppState, user_id: i64, id: i64, mark: Mark, on: bool) -> HttpResponse {
+    let article = match state.archive.get(id).await {
+        Ok(Some(article)) => article,
+        Ok(None) => return json_error(HttpResponse::NotFound(), "not_found", format!("no article {}", id)),
+        Err(e) => return internal_error(e),
+    };
+    match state.article_states.set(user_id, &article.article.link, mark, on).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => internal_error(e),
+    }
+}
+
+// PUT /me/articles/{id}/read
+pub async fn put_read(state: web::Data<AppState>, user: AuthUser, id: web::Path<i64>) -> HttpResponse {
+    mark(&state, user.id, *id, Mark::Read, true).await
+}
+
+// DELETE /me/articles/{id}/read
+pub async fn delete_read(state: web::Data<AppState>, user: AuthUser, id: web::Path<i64>) -> HttpResponse {
+    mark(&state, user.id, *id, Mark::Read, false).await
+}
+
+// PUT /me/articles/{id}/bookmark
+pub async fn put_bookmark(state: web::Data<AppState>, user: AuthUser, id: web::Path<i64>) -> HttpResponse {
+    mark(&state, user.id, *id, Mark::Bookmark, true).await
+}
+
+// DELETE /me/articles/{id}/bookmark
+pub async fn delete_bookmark(state: web::Data<AppState>, user: AuthUser, id: web::Path<i64>) -> HttpResponse {
+    mark(&state, user.id, *id, Mark::Bookmark, false).await
+}
+
+// POST /me/feed/read {"up_to": 1792368000, "watchlist": "majors"} marks everything up to then read.
+pub async fn mark_read(state: web::Data<AppState>, user: AuthUser, body: web::Json<MarkRead>) -> HttpResponse {
+    let (symbols, sources) = match scoped(&state, user.id, &body.scope).await {
+        Ok(watched) => watched,
+        Err(response) => return response,
+    };
+    let up_to = body.up_to.unwrap_or_else(|| Utc::now().timestamp());
+    match state.article_states.mark_read_up_to(user.id, &symbols, &sources, up_to).await {
+        Ok(marked) => HttpResponse::Ok().json(Marked { marked }),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /me/unread?watchlist=majors, unread article counts per symbol
+pub async fn get_unread(state: web::Data<AppState>, user: AuthUser, scope: web::Query<Scope>) -> HttpResponse {
+    let (symbols, sources) = match scoped(&state, user.id, &scope).await {
+        Ok(watched) => watched,
+        Err(response) => return response,
+    };
+    match state.article_states.unread_counts(user.id, &symbols, &sources).await {
+        Ok(counts) => HttpResponse::Ok().json(counts),
+        Err(e) => internal_error(e),
+    }
+}
+
+// POST /me/feed-token issues the secret for the RSS feed URL, replacing any earlier one.
+pub async fn create_feed_token(state: web::Data<AppState>, http: HttpRequest, user: AuthUser) -> impl Responder {
+    match state.users.rotate_feed_token(user.id).await {
+        Ok(token) => {
+            let url = format!("{}/feeds/{}", base_url(&http), token);
+            HttpResponse::Created().json(FeedToken { token, url })
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+// DELETE /me/feed-token turns the RSS feed URL off.
+pub async fn delete_feed_token(state: web::Data<AppState>, user: AuthUser) -> impl Responder {
+    match state.users.clear_feed_token(user.id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /feeds/{token}, the personal feed as RSS 2.0 for feed readers; takes the same parameters as
+// /me/feed except `cursor`.
+pub async fn get_rss(
+    state: web::Data<AppState>,
+    http: HttpRequest,
+    token: web::Path<String>,
+    query: web::Query<FeedQuery>,
+) -> HttpResponse {
+    let user_id = match state.users.by_feed_token(&token).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return json_error(HttpResponse::NotFound(), "not_found", "unknown feed"),
+        Err(e) => return internal_error(e),
+    };
+    let query = FeedQuery {
+        cursor: None,
+        ..query.into_inner()
+    };
+    let page = match feed(&state, &http, user_id, &query, Selection::Watchlists).await {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let body = rss(&page, &format!("{}/me/feed", base_url(&http)));
+    match last_modified(&state, user_id, &page).await {
+        Ok(last_modified) => private(cached(&http, &state, last_modified, "application/rss+xml; charset=utf-8", body.into_bytes())),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use chrono::Duration;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::config::Config;
+    use crate::models::news::NewsArticle;
+
+    #[actix_web::test]
+    async fn read_marks_and_bookmarks_filter_the_feed() {
+        let config = Config::parse("[auth]\njwt_secret = \"0123456789abcdef0123456789abcdef-test\"", std::iter::empty()).unwrap();
+        let state = crate::test_state(config.clone()).await;
+        let session = state
+            .users
+            .register(&config.auth, "203.0.113.1", "ana@example.com", "password123")
+            .await
+            .unwrap();
+        let now = Utc::now();
+        for (link, hours) in [("https://example.com/1", 2), ("https://example.com/2", 1)] {
+            let article = NewsArticle::new(
+                "Bitcoin rallies".to_string(),
+                "Wire".to_string(),
+                (now - Duration::hours(hours)).to_rfc3339(),
+                String::new(),
+                link.to_string(),
+            );
+            state.archive.insert("wire", "BTC", &article).await.unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/me/watchlists/{name}", web::put().to(put_watchlist))
+                .route("/me/feed", web::get().to(get_feed))
+                .route("/me/feed/read", web::post().to(mark_read))
+                .route("/me/unread", web::get().to(get_unread))
+                .route("/me/bookmarks", web::get().to(get_bookmarks))
+                .route("/me/articles/{id}/read", web::put().to(put_read))
+                .route("/me/articles/{id}/bookmark", web::put().to(put_bookmark)),
+        )
+        .await;
+        let authorization = format!("Bearer {}", session.access_token);
+        let call = |request: test::TestRequest| {
+            let request = request.insert_header(("Authorization", authorization.as_str())).to_request();
+            test::call_service(&app, request)
+        };
+        let links = |page: &Value| -> Vec<String> {
+            page["articles"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|article| article["link"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let response = call(test::TestRequest::put().uri("/me/watchlists/majors").set_json(json!({"symbols": ["btc"]}))).await;
+        assert_eq!(response.status(), 200);
+        let page: Value = test::read_body_json(call(test::TestRequest::get().uri("/me/feed")).await).await;
+        assert_eq!(links(&page), ["https://example.com/2", "https://example.com/1"]);
+        let newest = page["articles"][0]["id"].as_i64().unwrap();
+
+        let uri = format!("/me/articles/{}/read", newest);
+        assert_eq!(call(test::TestRequest::put().uri(&uri)).await.status(), 204);
+        let uri = format!("/me/articles/{}/bookmark", newest);
+        assert_eq!(call(test::TestRequest::put().uri(&uri)).await.status(), 204);
+        assert_eq!(call(test::TestRequest::put().uri("/me/articles/999/read")).await.status(), 404);
+
+        let page: Value = test::read_body_json(call(test::TestRequest::get().uri("/me/feed")).await).await;
+        assert_eq!(page["articles"][0]["read"], true);
+        assert_eq!(page["articles"][0]["bookmarked"], true);
+        let page: Value = test::read_body_json(call(test::TestRequest::get().uri("/me/feed?unread=true")).await).await;
+        assert_eq!(links(&page), ["https://example.com/1"]);
+        let page: Value = test::read_body_json(call(test::TestRequest::get().uri("/me/bookmarks")).await).await;
+        assert_eq!(links(&page), ["https://example.com/2"]);
+
+        let counts: Value = test::read_body_json(call(test::TestRequest::get().uri("/me/unread?watchlist=majors")).await).await;
+        assert_eq!(counts, json!({"total": 1, "symbols": {"BTC": 1}}));
+        let response = call(test::TestRequest::post().uri("/me/feed/read").set_json(json!({"watchlist": "majors"}))).await;
+        assert_eq!(test::read_body_json::<Value, _>(response).await, json!({"marked": 1}));
+        let counts: Value = test::read_body_json(call(test::TestRequest::get().uri("/me/unread")).await).await;
+        assert_eq!(counts["total"], 0);
+        let response = call(test::TestRequest::post().uri("/me/feed/read").set_json(json!({"watchlist": "alts"}))).await;
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -0,0 +1,192 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+
+use crate::api::SourceHealth;
+use crate::routes::internal_error;
+use crate::services::archive::ArticleCounts;
+use crate::AppState;
+
+#[derive(Serialize)]
+struct Check {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn from_result<E: std::fmt::Display>(result: Result<(), E>) -> Self {
+        match result {
+            Ok(()) => Check { ok: true, error: None },
+            Err(e) => Check {
+                ok: false,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    storage: Check,
+    cache: Check,
+    sources: Check,
+}
+
+#[derive(Serialize)]
+struct SourceStatus {
+    name: String,
+    // Added through the admin API rather than the config.
+    managed: bool,
+    enabled: bool,
+    base_url: String,
+    interval_secs: u64,
+    #[serde(flatten)]
+    health: SourceHealth,
+    articles: ArticleCounts,
+}
+
+// Probes and status must always reach the server.
+fn no_store() -> CacheControl {
+    CacheControl(vec![CacheDirective::NoStore])
+}
+
+// GET /healthz: the process is up and serving requests.
+pub async fn get_healthz() -> impl Responder {
+    HttpResponse::Ok().insert_header(no_store()).body("ok")
+}
+
+// GET /readyz: storage answers, the quote cache is usable and at least one enabled source's
+// circuit is not open, configured or managed. 503 with the failing checks otherwise.
+pub async fn get_readyz(state: web::Data<AppState>) -> impl Responder {
+    let storage = Check::from_result(state.archive.ping().await);
+    let cache = Check::from_result(state.cache.lock().map(|_| ()).map_err(|_| "cache lock is poisoned"));
+    let config = state.config.borrow().clone();
+    let managed = state.sources.list().await.unwrap_or_default();
+    let healthy = config
+        .sources
+        .values()
+        .map(|source| (&source.name, source.enabled))
+        .chain(managed.iter().map(|source| (&source.name, source.enabled)))
+        .any(|(name, enabled)| enabled && state.http.health(name).is_healthy());
+    let sources = Check::from_result(if healthy { Ok(()) } else { Err("no enabled source is healthy") });
+
+    let ready = storage.ok && cache.ok && sources.ok;
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    response.insert_header(no_store()).json(Readiness {
+        ready,
+        storage,
+        cache,
+        sources,
+    })
+}
+
+// GET /sources: every configured and managed source, including disabled ones, with its fetch
+// health and the articles archived from it.
+pub async fn get_sources(state: web::Data<AppState>) -> impl Responder {
+    let mut counts = match state.archive.counts_by_origin().await {
+        Ok(counts) => counts,
+        Err(e) => return internal_error(e),
+    };
+    let managed = match state.sources.list().await {
+        Ok(managed) => managed,
+        Err(e) => return internal_error(e),
+    };
+    let config = state.config.borrow().clone();
+    let sources: Vec<SourceStatus> = config
+        .sources
+        .values()
+        .map(|source| (source.clone(), false))
+        .chain(managed.iter().map(|source| (source.config(), true)))
+        .map(|(source, managed)| SourceStatus {
+            health: state.http.health(&source.name),
+            articles: counts.remove(&source.name).unwrap_or_default(),
+            name: source.name,
+            managed,
+            enabled: source.enabled,
+            base_url: source.base_url,
+            interval_secs: source.interval_secs,
+        })
+        .collect();
+    HttpResponse::Ok().insert_header(no_store()).json(sources)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use chrono::{Duration, Utc};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::models::news::NewsArticle;
+
+    async fn get(state: &web::Data<AppState>, uri: &str) -> (u16, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/readyz", web::get().to(get_readyz))
+                .route("/sources", web::get().to(get_sources)),
+        )
+        .await;
+        let response = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(response.headers().get("cache-control").unwrap(), "no-store");
+        let status = response.status().as_u16();
+        (status, test::read_body_json(response).await)
+    }
+
+    #[actix_web::test]
+    async fn ready_while_storage_answers_and_a_source_is_enabled() {
+        let state = crate::test_state(Config::parse("", std::iter::empty()).unwrap()).await;
+        let (status, body) = get(&state, "/readyz").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["storage"]["ok"], true);
+        assert_eq!(body["sources"]["ok"], true);
+
+        let config = "[sources.cryptqnews]\nenabled = false\n[sources.coingecko]\nenabled = false\n";
+        let state = crate::test_state(Config::parse(config, std::iter::empty()).unwrap()).await;
+        let (status, body) = get(&state, "/readyz").await;
+        assert_eq!(status, 503);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["storage"]["ok"], true);
+        assert_eq!(body["sources"]["error"], "no enabled source is healthy");
+    }
+
+    #[actix_web::test]
+    async fn sources_list_disabled_ones_with_their_health() {
+        let config = "[sources.coingecko]\nenabled = false\n";
+        let state = crate::test_state(Config::parse(config, std::iter::empty()).unwrap()).await;
+        let now = Utc::now();
+        for (link, published) in [("https://example.com/1", now), ("https://example.com/2", now - Duration::days(3))] {
+            let article = NewsArticle::new(
+                "Bitcoin rallies".to_string(),
+                "Example".to_string(),
+                published.to_rfc3339(),
+                String::new(),
+                link.to_string(),
+            );
+            assert!(state.archive.insert("cryptqnews", "BTC", &article).await.unwrap());
+        }
+        let (status, body) = get(&state, "/sources").await;
+        assert_eq!(status, 200);
+        let sources = body.as_array().unwrap();
+        assert_eq!(sources.len(), 2);
+        let coingecko = sources.iter().find(|source| source["name"] == "coingecko").unwrap();
+        assert_eq!(coingecko["enabled"], false);
+        assert_eq!(coingecko["managed"], false);
+        assert_eq!(coingecko["circuit"], "closed");
+        assert_eq!(coingecko["last_success"], Value::Null);
+        assert_eq!(coingecko["articles"]["total"], 0);
+        let cryptqnews = sources.iter().find(|source| source["name"] == "cryptqnews").unwrap();
+        assert_eq!(cryptqnews["enabled"], true);
+        assert_eq!(cryptqnews["articles"]["total"], 2);
+        assert_eq!(cryptqnews["articles"]["last_24h"], 1);
+        assert!(cryptqnews["articles"]["last_fetched_at"].as_i64().unwrap() >= now.timestamp());
+    }
+}
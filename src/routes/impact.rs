@@ -0,0 +1,51 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::services::digest::Period;
+use crate::services::prices;
+use crate::routes::{cached_json, internal_error, language_filter};
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub struct ImpactQuery {
+    period: Option<String>,
+    horizon: Option<String>,
+    limit: Option<i64>,
+    lang: Option<String>,
+}
+
+// GET /impact/{symbol}?period=daily|weekly&horizon=15m|1h|4h|24h&limit=10&lang=en
+pub async fn get_impact(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    symbol: web::Path<String>,
+    query: web::Query<ImpactQuery>,
+) -> impl Responder {
+    let period = match Period::parse(query.period.as_deref().unwrap_or("weekly")) {
+        Some(period) => period,
+        None => return HttpResponse::BadRequest().body("period must be daily or weekly"),
+    };
+    let horizon = match prices::parse_horizon(query.horizon.as_deref().unwrap_or("1h")) {
+        Some(horizon) if horizon > 0 => horizon,
+        _ => return HttpResponse::BadRequest().body("horizon must be one of 15m, 1h, 4h or 24h"),
+    };
+
+    let languages = match language_filter(&state, &req, None, query.lang.as_deref()).await {
+        Ok(languages) => languages,
+        Err(response) => return response,
+    };
+
+    let since = Utc::now().timestamp() - period.seconds();
+    match state
+        .prices
+        .highest_impact(&symbol, since, horizon, languages.as_deref(), query.limit.unwrap_or(10))
+        .await
+    {
+        Ok(articles) => {
+            let last_modified = articles.iter().map(|impact| impact.archived.published_at).max();
+            cached_json(&req, &state, last_modified, &articles)
+        }
+        Err(e) => internal_error(e),
+    }
+}
@@ -0,0 +1,20 @@
+use actix_web::{web, HttpResponse, Responder};
+
+// The search page and its assets, built into the binary from src/web.
+const INDEX_HTML: &str = include_str!("../web/templates/index.html");
+const STYLES_CSS: &str = include_str!("../web/static/styles.css");
+const SCRIPT_JS: &str = include_str!("../web/static/script.js");
+
+// GET /
+pub async fn index() -> impl Responder {
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(INDEX_HTML)
+}
+
+// GET /static/{file}
+pub async fn asset(file: web::Path<String>) -> impl Responder {
+    match file.as_str() {
+        "styles.css" => HttpResponse::Ok().content_type("text/css; charset=utf-8").body(STYLES_CSS),
+        "script.js" => HttpResponse::Ok().content_type("text/javascript; charset=utf-8").body(SCRIPT_JS),
+        _ => HttpResponse::NotFound().finish(),
+    }
+}
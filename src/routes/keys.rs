@@ -0,0 +1,192 @@
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpResponse, Responder};
+use serde::Serialize;
+
+use crate::routes::{auth, internal_error, json_error};
+use crate::services::apikeys::{ApiKey, Denied, NewApiKey, API_KEY_HEADER};
+use crate::services::users::verify_access;
+use crate::AppState;
+
+// Never need a key: the index, the health probes, metrics scraping, signing in and RSS feeds,
+// whose URLs carry their own token.
+const PUBLIC_PATHS: &[&str] = &["/", "/healthz", "/readyz", "/metrics"];
+const PUBLIC_PREFIXES: &[&str] = &["/auth/", "/feeds/", "/static/"];
+
+fn denied(denied: &Denied) -> HttpResponse {
+    match denied {
+        Denied::Missing => json_error(
+            HttpResponse::Unauthorized(),
+            "missing_api_key",
+            "this endpoint needs an API key in the X-API-Key header",
+        ),
+        Denied::Invalid => json_error(
+            HttpResponse::Unauthorized(),
+            "invalid_api_key",
+            "the API key is unknown or has been revoked",
+        ),
+        Denied::RateLimited { limit, retry_after } => {
+            let mut response = HttpResponse::TooManyRequests();
+            response.insert_header((RETRY_AFTER, retry_after.to_string()));
+            json_error(
+                response,
+                "rate_limited",
+                format!("rate limit of {} requests per minute exceeded, retry in {}s", limit, retry_after),
+            )
+        }
+        Denied::QuotaExceeded { quota, retry_after } => {
+            let mut response = HttpResponse::TooManyRequests();
+            response.insert_header((RETRY_AFTER, retry_after.to_string()));
+            json_error(
+                response,
+                "quota_exceeded",
+                format!("daily quota of {} requests used up, it resets at 00:00 UTC", quota),
+            )
+        }
+    }
+}
+
+// Checks the API key on every request when `auth.enabled` is set, and on `/admin` routes always.
+// The key's record is left in the request extensions for handlers. Outside `/admin`, a user's
+// access token is accepted instead of a key.
+pub async fn authenticate(req: ServiceRequest, next: Next<BoxBody>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+    let config = state.config.borrow().clone();
+    // The path as the router will match it, percent-decoded, so `/%61dmin/keys` counts as `/admin/keys`.
+    let path = req.match_info().as_str().to_string();
+    let admin = path == "/admin" || path.starts_with("/admin/");
+    let public = PUBLIC_PATHS.contains(&path.as_str()) || PUBLIC_PREFIXES.iter().any(|prefix| path.starts_with(prefix));
+    let signed_in = || auth::bearer_token(req.request()).is_some_and(|token| verify_access(&config.auth, token).is_ok());
+    if !admin && (!config.auth.enabled || public || signed_in()) {
+        return next.call(req).await;
+    }
+
+    let key = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let grant = match state.api_keys.authorize(key.as_deref(), &config.auth).await {
+        Ok(Ok(grant)) => grant,
+        Ok(Err(reason)) => {
+            tracing::debug!(reason = ?reason, "request denied");
+            return Ok(req.into_response(denied(&reason)));
+        }
+        Err(e) => return Ok(req.into_response(internal_error(e))),
+    };
+    if admin && !grant.key.admin {
+        let response = json_error(
+            HttpResponse::Forbidden(),
+            "admin_required",
+            "this endpoint needs an admin API key",
+        );
+        return Ok(req.into_response(response));
+    }
+    tracing::debug!(api_key = grant.key.id, name = %grant.key.name, "request authorized");
+    req.extensions_mut().insert(grant.key);
+
+    let mut response = next.call(req).await?;
+    let headers = response.headers_mut();
+    for (name, value) in [("x-ratelimit-limit", grant.rate_limit), ("x-ratelimit-remaining", grant.remaining)] {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+    Ok(response)
+}
+
+#[derive(Serialize)]
+struct CreatedKey {
+    // Shown only in this response.
+    key: String,
+    #[serde(flatten)]
+    record: ApiKey,
+}
+
+// POST /admin/keys {"name": "dashboard", "rate_limit": 120, "daily_quota": 50000}
+pub async fn create_key(state: web::Data<AppState>, new: web::Json<NewApiKey>) -> impl Responder {
+    if let Err(message) = new.validate() {
+        return json_error(HttpResponse::BadRequest(), "invalid_key", message);
+    }
+    match state.api_keys.create(&new).await {
+        Ok((key, record)) => HttpResponse::Created().json(CreatedKey { key, record }),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /admin/keys
+pub async fn list_keys(state: web::Data<AppState>) -> impl Responder {
+    match state.api_keys.list().await {
+        Ok(keys) => HttpResponse::Ok().json(keys),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /admin/keys/{id}
+pub async fn get_key(state: web::Data<AppState>, id: web::Path<i64>) -> impl Responder {
+    match state.api_keys.get(*id).await {
+        Ok(Some(key)) => HttpResponse::Ok().json(key),
+        Ok(None) => json_error(HttpResponse::NotFound(), "not_found", format!("no API key {}", id)),
+        Err(e) => internal_error(e),
+    }
+}
+
+// DELETE /admin/keys/{id} revokes the key; its record and usage are kept.
+pub async fn revoke_key(state: web::Data<AppState>, id: web::Path<i64>) -> impl Responder {
+    match state.api_keys.revoke(*id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => json_error(
+            HttpResponse::NotFound(),
+            "not_found",
+            format!("no active API key {}", id),
+        ),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{middleware, test, web, App};
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[actix_web::test]
+    async fn admin_routes_are_checked_on_the_decoded_path() {
+        let config = Config::parse("", std::iter::empty()).unwrap();
+        let state = crate::test_state(config).await;
+        let new = NewApiKey {
+            name: "reader".to_string(),
+            ..NewApiKey::default()
+        };
+        let (key, _) = state.api_keys.create(&new).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .wrap(middleware::from_fn(authenticate))
+                .route("/admin/keys", web::post().to(create_key)),
+        )
+        .await;
+
+        for path in ["/admin/keys", "/%61dmin/keys", "/%61%64%6D%69%6E/%6Beys"] {
+            let request = test::TestRequest::post()
+                .uri(path)
+                .set_json(json!({"name": "minted", "admin": true}))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), 401, "{} without a key", path);
+
+            let request = test::TestRequest::post()
+                .uri(path)
+                .insert_header((API_KEY_HEADER, key.as_str()))
+                .set_json(json!({"name": "minted", "admin": true}))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), 403, "{} with an ordinary key", path);
+        }
+        assert_eq!(state.api_keys.list().await.unwrap().len(), 1);
+    }
+}
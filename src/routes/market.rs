@@ -0,0 +1,96 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+
+use crate::api::{fetch_coin_list, fetch_market_chart, fetch_simple_price};
+use crate::routes::{bad_gateway, cached_json};
+use crate::services::market::{self, VS_CURRENCY};
+use crate::AppState;
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// Returned when the CoinGecko source is disabled in the config.
+fn disabled() -> HttpResponse {
+    HttpResponse::ServiceUnavailable().body("market data source is disabled")
+}
+
+#[derive(Deserialize)]
+pub struct QuotesQuery {
+    symbols: String,
+}
+
+// GET /market?symbols=BTC,ETH
+pub async fn get_quotes(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<QuotesQuery>,
+) -> HttpResponse {
+    let config = state.config.borrow().clone();
+    let Some(source) = config.source("coingecko") else {
+        return disabled();
+    };
+    match market::quotes(&state.http, source, &state.cache, &split_list(&query.symbols)).await {
+        Ok(quotes) => cached_json(&req, &state, None, &quotes),
+        Err(e) => bad_gateway(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PriceQuery {
+    ids: String,
+}
+
+// GET /market/price?ids=bitcoin,ethereum
+pub async fn get_price(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<PriceQuery>,
+) -> HttpResponse {
+    let config = state.config.borrow().clone();
+    let Some(source) = config.source("coingecko") else {
+        return disabled();
+    };
+    match fetch_simple_price(&state.http, source, &split_list(&query.ids), VS_CURRENCY).await {
+        Ok(prices) => cached_json(&req, &state, None, &prices),
+        Err(e) => bad_gateway(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChartQuery {
+    days: Option<u32>,
+}
+
+// GET /market/{id}/chart?days=7
+pub async fn get_chart(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    query: web::Query<ChartQuery>,
+) -> HttpResponse {
+    let config = state.config.borrow().clone();
+    let Some(source) = config.source("coingecko") else {
+        return disabled();
+    };
+    match fetch_market_chart(&state.http, source, &id, VS_CURRENCY, query.days.unwrap_or(7)).await {
+        Ok(chart) => cached_json(&req, &state, None, &chart),
+        Err(e) => bad_gateway(e),
+    }
+}
+
+// GET /market/coins
+pub async fn get_coins(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let config = state.config.borrow().clone();
+    let Some(source) = config.source("coingecko") else {
+        return disabled();
+    };
+    match fetch_coin_list(&state.http, source).await {
+        Ok(coins) => cached_json(&req, &state, None, &coins),
+        Err(e) => bad_gateway(e),
+    }
+}
@@ -0,0 +1,14 @@
+use actix_web::{web, HttpResponse, Responder};
+
+use crate::routes::internal_error;
+use crate::AppState;
+
+// GET /metrics
+pub async fn get_metrics(state: web::Data<AppState>) -> impl Responder {
+    match state.metrics.render(&state.cache) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(body),
+        Err(e) => internal_error(e),
+    }
+}
\ No newline at end of file
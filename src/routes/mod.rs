@@ -0,0 +1,242 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use actix_web::http::header::{self, CacheControl, CacheDirective, EntityTag, IfModifiedSince, IfNoneMatch};
+use actix_web::http::Method;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::services::language;
+use crate::services::users::verify_access;
+use crate::AppState;
+
+pub mod alerts;
+pub mod auth;
+pub mod digest;
+pub mod feed;
+pub mod health;
+pub mod impact;
+pub mod index;
+pub mod keys;
+pub mod market;
+pub mod metrics;
+pub mod news;
+pub mod searches;
+pub mod sentiment;
+pub mod sources;
+pub mod stories;
+pub mod trending;
+
+// Storage and other internal failures; logged within the request's span.
+pub fn internal_error(e: impl fmt::Display) -> HttpResponse {
+    tracing::error!(error = %e, "request failed");
+    HttpResponse::InternalServerError().body(e.to_string())
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+// Errors callers are expected to handle, as `{"error": code, "message": text}`.
+pub fn json_error(mut response: HttpResponseBuilder, error: &'static str, message: impl Into<String>) -> HttpResponse {
+    response.json(ErrorBody {
+        error,
+        message: message.into(),
+    })
+}
+
+// Upstream source failures.
+pub fn bad_gateway(e: impl fmt::Display) -> HttpResponse {
+    tracing::warn!(error = %e, "upstream request failed");
+    HttpResponse::BadGateway().body(e.to_string())
+}
+
+// Language filter for a request: the `lang` parameter, else the languages stored for `user_id` or
+// the user whose access token was sent, else the caller's Accept-Language.
+pub async fn language_filter(
+    state: &AppState,
+    req: &HttpRequest,
+    user_id: Option<i64>,
+    lang: Option<&str>,
+) -> Result<Option<Vec<String>>, HttpResponse> {
+    let accept_language = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+    if lang.is_some() {
+        return Ok(language::filter(lang, None, accept_language));
+    }
+    let user_id = user_id.or_else(|| {
+        let config = state.config.borrow();
+        auth::bearer_token(req).and_then(|token| verify_access(&config.auth, token).ok())
+    });
+    let stored = match user_id {
+        Some(user_id) => state.users.languages(user_id).await.map_err(internal_error)?,
+        None => None,
+    };
+    Ok(language::filter(None, stored.as_deref(), accept_language))
+}
+
+// A strong ETag over the exact response bytes.
+fn entity_tag(body: &[u8]) -> EntityTag {
+    let digest = Sha256::digest(body);
+    EntityTag::new_strong(format!("{:x}", digest)[..32].to_string())
+}
+
+// If-None-Match wins over If-Modified-Since, as in RFC 9110. Only GET and HEAD are answered with 304.
+fn not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: Option<SystemTime>) -> bool {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return false;
+    }
+    if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+    match (req.get_header::<IfModifiedSince>(), last_modified) {
+        (Some(IfModifiedSince(since)), Some(last_modified)) => last_modified <= SystemTime::from(since),
+        _ => false,
+    }
+}
+
+// Serves `body` with a content ETag, Last-Modified (unix seconds, usually the newest article) and
+// a Cache-Control max-age of the cache TTL, or a bodiless 304 when the client's copy is current.
+pub fn cached(
+    req: &HttpRequest,
+    state: &AppState,
+    last_modified: Option<i64>,
+    content_type: &str,
+    body: Vec<u8>,
+) -> HttpResponse {
+    let etag = entity_tag(&body);
+    // HTTP dates have one-second resolution.
+    let last_modified = last_modified
+        .and_then(|seconds| u64::try_from(seconds).ok())
+        .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+    let max_age = state.config.borrow().cache.ttl_secs;
+
+    let not_modified = not_modified(req, &etag, last_modified);
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(header::ETag(etag))
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(max_age as u32),
+        ]))
+        // Results depend on the language preference when no `lang` is given: the signed-in user's
+        // stored one or Accept-Language.
+        .insert_header((header::VARY, "Accept-Language, Authorization"));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(header::LastModified(last_modified.into()));
+    }
+    if not_modified {
+        return response.finish();
+    }
+    response.content_type(content_type).body(body)
+}
+
+// For responses that differ per user, on top of `cached`: shared caches must not keep them, and
+// the user's own client revalidates every time, which the ETag makes cheap.
+pub fn private(mut response: HttpResponse) -> HttpResponse {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("private, no-cache"),
+    );
+    headers.append(header::VARY, header::HeaderValue::from_static("Authorization"));
+    response
+}
+
+pub fn cached_json<T: Serialize>(req: &HttpRequest, state: &AppState, last_modified: Option<i64>, value: &T) -> HttpResponse {
+    match serde_json::to_vec(value) {
+        Ok(body) => cached(req, state, last_modified, "application/json", body),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::MessageBody;
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+
+    const LAST_MODIFIED: i64 = 1_700_000_000;
+
+    fn respond(state: &AppState, request: TestRequest) -> HttpResponse {
+        cached_json(&request.to_http_request(), state, Some(LAST_MODIFIED), &json!(["article"]))
+    }
+
+    fn etag(response: &HttpResponse) -> String {
+        response.headers().get(header::ETAG).unwrap().to_str().unwrap().to_string()
+    }
+
+    fn http_date(seconds: i64) -> String {
+        header::HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds as u64)).to_string()
+    }
+
+    #[actix_web::test]
+    async fn etags_answer_matching_requests_with_not_modified() {
+        let state = crate::test_state(Config::parse("", std::iter::empty()).unwrap()).await;
+        let fresh = respond(&state, TestRequest::get());
+        assert_eq!(fresh.status(), 200);
+        let tag = etag(&fresh);
+        assert!(tag.starts_with('"'), "{} should be a strong tag", tag);
+        assert_eq!(fresh.headers().get(header::LAST_MODIFIED).unwrap(), &http_date(LAST_MODIFIED));
+
+        let matching = respond(&state, TestRequest::get().insert_header((header::IF_NONE_MATCH, tag.as_str())));
+        assert_eq!(matching.status(), 304);
+        assert_eq!(etag(&matching), tag);
+        assert_eq!(matching.into_body().size(), actix_web::body::BodySize::Sized(0));
+
+        // Weak comparison: the same tag marked weak still matches.
+        let weak = format!("W/{}", tag);
+        let response = respond(&state, TestRequest::get().insert_header((header::IF_NONE_MATCH, weak.as_str())));
+        assert_eq!(response.status(), 304);
+
+        let listed = format!("\"0000\", {}", tag);
+        let response = respond(&state, TestRequest::get().insert_header((header::IF_NONE_MATCH, listed.as_str())));
+        assert_eq!(response.status(), 304);
+
+        let response = respond(&state, TestRequest::get().insert_header((header::IF_NONE_MATCH, "*")));
+        assert_eq!(response.status(), 304);
+
+        // Only reads are answered with 304.
+        let response = respond(&state, TestRequest::post().insert_header((header::IF_NONE_MATCH, tag.as_str())));
+        assert_eq!(response.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn stale_validators_get_the_full_response() {
+        let state = crate::test_state(Config::parse("", std::iter::empty()).unwrap()).await;
+        let tag = etag(&respond(&state, TestRequest::get()));
+
+        let stale = respond(&state, TestRequest::get().insert_header((header::IF_NONE_MATCH, "\"0000\"")));
+        assert_eq!(stale.status(), 200);
+        assert_eq!(etag(&stale), tag);
+        assert_eq!(stale.into_body().try_into_bytes().unwrap(), &b"[\"article\"]"[..]);
+
+        let since = http_date(LAST_MODIFIED);
+        let response = respond(&state, TestRequest::get().insert_header((header::IF_MODIFIED_SINCE, since.as_str())));
+        assert_eq!(response.status(), 304);
+        let before = http_date(LAST_MODIFIED - 1);
+        let response = respond(&state, TestRequest::get().insert_header((header::IF_MODIFIED_SINCE, before.as_str())));
+        assert_eq!(response.status(), 200);
+
+        // A stale ETag wins over a current date.
+        let request = TestRequest::get()
+            .insert_header((header::IF_NONE_MATCH, "\"0000\""))
+            .insert_header((header::IF_MODIFIED_SINCE, since.as_str()));
+        assert_eq!(respond(&state, request).status(), 200);
+    }
+}
@@ -0,0 +1,93 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::api::fetch_latest_news;
+use crate::models::news::{NewsArticle, NewsRequest, NewsResponse};
+use crate::routes::{bad_gateway, cached_json, language_filter};
+use crate::services::{ingest, language, market, sentiment, summarize};
+use crate::AppState;
+
+const SUMMARY_CHARS: usize = 280;
+
+fn matches_sentiment(article: &NewsArticle, req: &NewsRequest) -> bool {
+    let score = article.sentiment.unwrap_or(0.0);
+    req.sentiment.as_deref().is_none_or(|label| sentiment::label(score) == label)
+        && req.min_sentiment.is_none_or(|min| score >= min)
+        && req.max_sentiment.is_none_or(|max| score <= max)
+}
+
+async fn news(state: &AppState, http: &HttpRequest, req: &NewsRequest) -> Result<NewsResponse, HttpResponse> {
+    let languages = language_filter(state, http, None, req.lang.as_deref()).await?;
+    let config = state.config.borrow().clone();
+    let source = match config.source("cryptqnews") {
+        Some(source) => source,
+        None => return Err(HttpResponse::ServiceUnavailable().body("news source is disabled")),
+    };
+    let fetched = match fetch_latest_news(&state.http, source, &req.symbol).await {
+        Ok(fetched) => fetched,
+        Err(e) => return Err(bad_gateway(e)),
+    };
+    let mut news = fetched.value;
+    news.iter_mut().for_each(ingest::prepare);
+    // An unchanged feed has nothing new to archive.
+    if fetched.modified {
+        state.ingest.store(&source.name, &req.symbol, &news, &config).await;
+    }
+    news.retain(|article| matches_sentiment(article, req) && language::matches(&languages, article.lang.as_deref()));
+    let summary_chars = req.summary_length.unwrap_or(SUMMARY_CHARS);
+    for article in news.iter_mut() {
+        article.summary = summarize::summarize_article(&article.summary, None, summary_chars);
+    }
+    let symbols = std::slice::from_ref(&req.symbol);
+    let market = match config.source("coingecko") {
+        Some(source) if req.market => match market::quotes(&state.http, source, &state.cache, symbols).await {
+            Ok(quotes) => quotes,
+            Err(e) => {
+                tracing::warn!(symbol = %req.symbol, error = %e, "failed to fetch market quotes");
+                Vec::new()
+            }
+        },
+        _ => Vec::new(),
+    };
+    Ok(NewsResponse { articles: news, market })
+}
+
+// A plain list of articles, or with `market` the articles and quotes.
+fn body(req: &NewsRequest, response: NewsResponse) -> serde_json::Value {
+    let value = if req.market {
+        serde_json::to_value(response)
+    } else {
+        serde_json::to_value(response.articles)
+    };
+    value.unwrap_or_default()
+}
+
+pub async fn get_news(
+    state: web::Data<AppState>,
+    http: HttpRequest,
+    req: web::Json<NewsRequest>,
+) -> HttpResponse {
+    match news(&state, &http, &req).await {
+        Ok(response) => HttpResponse::Ok().json(body(&req, response)),
+        Err(response) => response,
+    }
+}
+
+// GET /news?symbol=BTC&sentiment=positive&lang=en, the cacheable form of POST /news.
+pub async fn query_news(
+    state: web::Data<AppState>,
+    http: HttpRequest,
+    req: web::Query<NewsRequest>,
+) -> HttpResponse {
+    match news(&state, &http, &req).await {
+        Ok(response) => {
+            let last_modified = response
+                .articles
+                .iter()
+                .filter_map(|article| article.published_at())
+                .map(|published_at| published_at.timestamp())
+                .max();
+            cached_json(&http, &state, last_modified, &body(&req, response))
+        }
+        Err(response) => response,
+    }
+}
@@ -0,0 +1,62 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+
+use crate::routes::auth::AuthUser;
+use crate::routes::feed::{self, FeedQuery, Selection};
+use crate::routes::{cached_json, internal_error, json_error, private};
+use crate::services::searches::SearchUpdate;
+use crate::AppState;
+
+// GET /me/searches, with each search's unread count
+pub async fn get_searches(state: web::Data<AppState>, user: AuthUser) -> impl Responder {
+    match state.searches.list(user.id).await {
+        Ok(searches) => HttpResponse::Ok().json(searches),
+        Err(e) => internal_error(e),
+    }
+}
+
+// PUT /me/searches/{name} {"query": "etf \"spot bitcoin\"", "symbols": ["BTC"], "email": true}
+pub async fn put_search(
+    state: web::Data<AppState>,
+    user: AuthUser,
+    name: web::Path<String>,
+    update: web::Json<SearchUpdate>,
+) -> impl Responder {
+    let config = state.config.borrow().clone();
+    match state.searches.put(user.id, &name, &update, &config.searches).await {
+        Ok(Ok(search)) => HttpResponse::Ok().json(search),
+        Ok(Err(message)) => json_error(HttpResponse::BadRequest(), "invalid_search", message),
+        Err(e) => internal_error(e),
+    }
+}
+
+// DELETE /me/searches/{name}
+pub async fn delete_search(state: web::Data<AppState>, user: AuthUser, name: web::Path<String>) -> impl Responder {
+    match state.searches.delete(user.id, &name).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => json_error(HttpResponse::NotFound(), "not_found", format!("no saved search {:?}", name.as_str())),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /me/searches/{name}/articles?unread=true, the search's matches with the parameters of /me/feed
+pub async fn get_search_articles(
+    state: web::Data<AppState>,
+    http: HttpRequest,
+    user: AuthUser,
+    name: web::Path<String>,
+    query: web::Query<FeedQuery>,
+) -> HttpResponse {
+    let search = match state.searches.get(user.id, &name).await {
+        Ok(Some(search)) => search,
+        Ok(None) => return json_error(HttpResponse::NotFound(), "not_found", format!("no saved search {:?}", name.as_str())),
+        Err(e) => return internal_error(e),
+    };
+    let page = match feed::feed(&state, &http, user.id, &query, Selection::SavedSearch(search.id)).await {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    match feed::last_modified(&state, user.id, &page).await {
+        Ok(last_modified) => private(cached_json(&http, &state, last_modified, &page)),
+        Err(response) => response,
+    }
+}
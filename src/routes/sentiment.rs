@@ -0,0 +1,43 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::services::digest::Period;
+use crate::routes::{cached_json, internal_error, language_filter};
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub struct SentimentQuery {
+    period: Option<String>,
+    bucket: Option<String>,
+    lang: Option<String>,
+}
+
+// GET /sentiment/{symbol}?period=daily|weekly&bucket=hour|day&lang=en
+pub async fn get_sentiment(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    symbol: web::Path<String>,
+    query: web::Query<SentimentQuery>,
+) -> impl Responder {
+    let period = match Period::parse(query.period.as_deref().unwrap_or("weekly")) {
+        Some(period) => period,
+        None => return HttpResponse::BadRequest().body("period must be daily or weekly"),
+    };
+    let bucket = match query.bucket.as_deref().unwrap_or("hour") {
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        _ => return HttpResponse::BadRequest().body("bucket must be hour or day"),
+    };
+
+    let languages = match language_filter(&state, &req, None, query.lang.as_deref()).await {
+        Ok(languages) => languages,
+        Err(response) => return response,
+    };
+
+    let since = Utc::now().timestamp() - period.seconds();
+    match state.archive.sentiment_series(&symbol, since, bucket, languages.as_deref()).await {
+        Ok(series) => cached_json(&req, &state, None, &series),
+        Err(e) => internal_error(e),
+    }
+}
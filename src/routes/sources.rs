@@ -0,0 +1,228 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+use crate::api::SourceHealth;
+use crate::routes::{internal_error, json_error};
+use crate::services::sources::{normalize_name, ManagedSource, NewSource, SourcePatch};
+use crate::AppState;
+
+const DEFAULT_LOG_LIMIT: i64 = 50;
+const MAX_LOG_LIMIT: i64 = 500;
+
+#[derive(Serialize)]
+struct SourceDetails {
+    #[serde(flatten)]
+    source: ManagedSource,
+    health: SourceHealth,
+}
+
+#[derive(Deserialize)]
+pub struct LogQuery {
+    #[serde(default)]
+    errors: bool,
+    limit: Option<i64>,
+}
+
+impl LogQuery {
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LOG_LIMIT).clamp(1, MAX_LOG_LIMIT)
+    }
+}
+
+fn not_found(name: &str) -> HttpResponse {
+    json_error(HttpResponse::NotFound(), "not_found", format!("no managed source {:?}", name))
+}
+
+fn details(state: &AppState, source: ManagedSource) -> SourceDetails {
+    let health = state.http.health(&source.name);
+    SourceDetails { source, health }
+}
+
+// POST /admin/sources {"name": "coindesk", "kind": "rss", "url": "https://...", "symbols": ["BTC", "ETH"]}
+pub async fn create_source(state: web::Data<AppState>, new: web::Json<NewSource>) -> impl Responder {
+    let config = state.config.borrow().clone();
+    let mut new = new.into_inner();
+    if let Err(message) = new.validate(&config) {
+        return json_error(HttpResponse::BadRequest(), "invalid_source", message);
+    }
+    match state.sources.create(&new).await {
+        Ok(Some(source)) => HttpResponse::Created().json(details(&state, source)),
+        Ok(None) => json_error(
+            HttpResponse::Conflict(),
+            "source_exists",
+            format!("a source named {:?} already exists", new.name),
+        ),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /admin/sources, with each source's fetch health
+pub async fn list_sources(state: web::Data<AppState>) -> impl Responder {
+    match state.sources.list().await {
+        Ok(sources) => {
+            let sources: Vec<SourceDetails> = sources.into_iter().map(|source| details(&state, source)).collect();
+            HttpResponse::Ok().json(sources)
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /admin/sources/{name}
+pub async fn get_source(state: web::Data<AppState>, name: web::Path<String>) -> impl Responder {
+    let name = normalize_name(&name);
+    match state.sources.get(&name).await {
+        Ok(Some(source)) => HttpResponse::Ok().json(details(&state, source)),
+        Ok(None) => not_found(&name),
+        Err(e) => internal_error(e),
+    }
+}
+
+// PATCH /admin/sources/{name} {"enabled": false}; any field of the POST body but the name
+pub async fn update_source(
+    state: web::Data<AppState>,
+    name: web::Path<String>,
+    patch: web::Json<SourcePatch>,
+) -> HttpResponse {
+    let name = normalize_name(&name);
+    let source = match state.sources.get(&name).await {
+        Ok(Some(source)) => source,
+        Ok(None) => return not_found(&name),
+        Err(e) => return internal_error(e),
+    };
+    let config = state.config.borrow().clone();
+    let mut updated = patch.into_inner().apply(&source);
+    if let Err(message) = updated.validate(&config) {
+        return json_error(HttpResponse::BadRequest(), "invalid_source", message);
+    }
+    match state.sources.update(&updated).await {
+        Ok(Some(source)) => HttpResponse::Ok().json(details(&state, source)),
+        Ok(None) => not_found(&name),
+        Err(e) => internal_error(e),
+    }
+}
+
+// DELETE /admin/sources/{name}; the articles archived from it are kept.
+pub async fn delete_source(state: web::Data<AppState>, name: web::Path<String>) -> impl Responder {
+    let name = normalize_name(&name);
+    match state.sources.delete(&name).await {
+        Ok(true) => {
+            state.http.reset_health(&name);
+            HttpResponse::NoContent().finish()
+        }
+        Ok(false) => not_found(&name),
+        Err(e) => internal_error(e),
+    }
+}
+
+// POST /admin/sources/{name}/fetch fetches the source now and returns the fetch log entry. An
+// open circuit isn't bypassed; reset it first.
+pub async fn fetch_source(state: web::Data<AppState>, name: web::Path<String>) -> HttpResponse {
+    let name = normalize_name(&name);
+    let source = match state.sources.get(&name).await {
+        Ok(Some(source)) => source,
+        Ok(None) => return not_found(&name),
+        Err(e) => return internal_error(e),
+    };
+    if !source.enabled {
+        return json_error(
+            HttpResponse::Conflict(),
+            "source_disabled",
+            format!("{} is disabled", source.name),
+        );
+    }
+    let config = state.config.borrow().clone();
+    match state.fetcher.fetch(&source, true, &config).await {
+        Ok(entry) => HttpResponse::Ok().json(entry),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /admin/sources/{name}/log?errors=true&limit=50, newest first
+pub async fn get_source_log(state: web::Data<AppState>, name: web::Path<String>, query: web::Query<LogQuery>) -> HttpResponse {
+    let name = normalize_name(&name);
+    match state.sources.get(&name).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found(&name),
+        Err(e) => return internal_error(e),
+    }
+    match state.sources.log(Some(&name), query.errors, query.limit()).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /admin/fetch-log?errors=true&limit=50, across all managed sources
+pub async fn get_fetch_log(state: web::Data<AppState>, query: web::Query<LogQuery>) -> impl Responder {
+    match state.sources.log(None, query.errors, query.limit()).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => internal_error(e),
+    }
+}
+
+// POST /admin/sources/{name}/reset closes the source's circuit and forgets its failures. Works for
+// configured sources too, whose names are used as written in the config.
+pub async fn reset_source(state: web::Data<AppState>, name: web::Path<String>) -> HttpResponse {
+    let configured = state.config.borrow().sources.contains_key(name.trim());
+    let name = if configured {
+        name.trim().to_string()
+    } else {
+        let name = normalize_name(&name);
+        match state.sources.get(&name).await {
+            Ok(Some(_)) => name,
+            Ok(None) => return json_error(HttpResponse::NotFound(), "not_found", format!("no source {:?}", name)),
+            Err(e) => return internal_error(e),
+        }
+    };
+    state.http.reset_health(&name);
+    tracing::info!(source = %name, "circuit reset");
+    HttpResponse::NoContent().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[actix_web::test]
+    async fn names_are_looked_up_as_they_are_stored() {
+        let config = Config::parse("", std::iter::empty()).unwrap();
+        let state = crate::test_state(config).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/admin/sources", web::post().to(create_source))
+                .route("/admin/sources/{name}", web::get().to(get_source))
+                .route("/admin/sources/{name}", web::patch().to(update_source))
+                .route("/admin/sources/{name}", web::delete().to(delete_source)),
+        )
+        .await;
+
+        let request = test::TestRequest::post()
+            .uri("/admin/sources")
+            .set_json(json!({"name": " Wire ", "kind": "rss", "url": "https://example.com/feed.xml", "symbols": ["btc"]}))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 201);
+        let request = test::TestRequest::get().uri("/admin/sources/WIRE").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 200);
+
+        let request = test::TestRequest::patch()
+            .uri("/admin/sources/Wire")
+            .set_json(json!({"api_key_header": "X-Token"}))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 200);
+        assert_eq!(state.sources.get("wire").await.unwrap().unwrap().api_key_header.as_deref(), Some("X-Token"));
+        let request = test::TestRequest::patch()
+            .uri("/admin/sources/Wire")
+            .set_json(json!({"api_key_header": ""}))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 200);
+        assert_eq!(state.sources.get("wire").await.unwrap().unwrap().api_key_header, None);
+
+        let request = test::TestRequest::delete().uri("/admin/sources/Wire").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 204);
+        assert!(state.sources.get("wire").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,104 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::routes::{cached_json, internal_error, language_filter};
+use crate::services::archive::{ArchivedArticle, StorySummary};
+use crate::services::{language, summarize};
+use crate::AppState;
+
+const SUMMARY_CHARS: usize = 400;
+const DEFAULT_HOURS: i64 = 24;
+// Stories of the last 30 days at most.
+const MAX_HOURS: i64 = 30 * 24;
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct StoriesQuery {
+    hours: Option<i64>,
+    symbol: Option<String>,
+    limit: Option<i64>,
+    lang: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct StoryQuery {
+    summary_length: Option<usize>,
+    lang: Option<String>,
+}
+
+#[derive(Serialize)]
+struct StoryDetail {
+    #[serde(flatten)]
+    summary: StorySummary,
+    // Extracted from the summaries of every source covering the story.
+    text_summary: String,
+    timeline: Vec<ArchivedArticle>,
+}
+
+// GET /stories?hours=24&symbol=BTC&limit=50&lang=en
+pub async fn get_stories(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<StoriesQuery>,
+) -> impl Responder {
+    let hours = query.hours.unwrap_or(DEFAULT_HOURS).clamp(1, MAX_HOURS);
+    let since = Utc::now().timestamp() - hours * 60 * 60;
+    let languages = match language_filter(&state, &req, None, query.lang.as_deref()).await {
+        Ok(languages) => languages,
+        Err(response) => return response,
+    };
+    match state
+        .archive
+        .stories(since, query.symbol.as_deref(), languages.as_deref(), query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+        .await
+    {
+        Ok(stories) => {
+            let last_modified = stories.iter().map(|story| story.last_seen).max();
+            cached_json(&req, &state, last_modified, &stories)
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+// GET /stories/{id}?summary_length=400&lang=en
+pub async fn get_story(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    id: web::Path<i64>,
+    query: web::Query<StoryQuery>,
+) -> impl Responder {
+    let languages = match language_filter(&state, &req, None, query.lang.as_deref()).await {
+        Ok(languages) => languages,
+        Err(response) => return response,
+    };
+    let summary = match state.archive.story(*id).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) => return HttpResponse::NotFound().body(format!("unknown story {}", id)),
+        Err(e) => return internal_error(e),
+    };
+    match state.archive.story_articles(*id).await {
+        Ok(mut timeline) => {
+            // The archive keeps one row per symbol; the timeline lists each article once.
+            let mut seen = std::collections::HashSet::new();
+            timeline.retain(|archived| {
+                language::matches(&languages, archived.article.lang.as_deref())
+                    && seen.insert(archived.article.link.clone())
+            });
+            let summaries: Vec<&str> = timeline
+                .iter()
+                .map(|archived| archived.article.summary.as_str())
+                .collect();
+            let text_summary = summarize::summarize(&summaries, query.summary_length.unwrap_or(SUMMARY_CHARS));
+            let last_modified = Some(summary.last_seen);
+            let detail = StoryDetail {
+                summary,
+                text_summary,
+                timeline,
+            };
+            cached_json(&req, &state, last_modified, &detail)
+        }
+        Err(e) => internal_error(e),
+    }
+}
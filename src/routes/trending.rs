@@ -0,0 +1,70 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::routes::{cached_json, internal_error, language_filter};
+use crate::services::language;
+use crate::services::trending::{self, Window};
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub struct TrendingQuery {
+    window: Option<String>,
+    limit: Option<usize>,
+    lang: Option<String>,
+}
+
+// GET /trending?window=hour|day&limit=20&lang=en
+pub async fn get_trending(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<TrendingQuery>,
+) -> impl Responder {
+    let window = match Window::parse(query.window.as_deref().unwrap_or("hour")) {
+        Some(window) => window,
+        None => return HttpResponse::BadRequest().body("window must be hour or day"),
+    };
+
+    let now = Utc::now().timestamp();
+    let windows = window.baseline_windows() + 1;
+    let current_since = now - window.seconds();
+    let languages = match language_filter(&state, &req, None, query.lang.as_deref()).await {
+        Ok(languages) => languages,
+        Err(response) => return response,
+    };
+    let counts = match state
+        .archive
+        .mention_counts(now, window.seconds(), windows, languages.as_deref())
+        .await
+    {
+        Ok(counts) => counts,
+        Err(e) => return internal_error(e),
+    };
+    let current = match state.archive.since(current_since).await {
+        Ok(mut current) => {
+            current.retain(|archived| {
+                archived.published_at > current_since
+                    && archived.published_at <= now
+                    && language::matches(&languages, archived.article.lang.as_deref())
+            });
+            current
+        }
+        Err(e) => return internal_error(e),
+    };
+    let earlier = match state
+        .archive
+        .titles(now - window.seconds() * windows, current_since, languages.as_deref())
+        .await
+    {
+        Ok(earlier) => earlier,
+        Err(e) => return internal_error(e),
+    };
+
+    let last_modified = current
+        .iter()
+        .map(|archived| archived.published_at)
+        .chain(earlier.iter().map(|(_, published_at)| *published_at))
+        .max();
+    let trending = trending::compute(&counts, &current, &earlier, window, now, query.limit.unwrap_or(20));
+    cached_json(&req, &state, last_modified, &trending)
+}
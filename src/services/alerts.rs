@@ -0,0 +1,262 @@
+// Price-move alert rules, evaluated against stored price snapshots after every poll. A triggered
+// alert is sent together with the most relevant recent articles about the symbol.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+use crate::config::Config;
+use crate::services::archive::{Archive, ArchivedArticle};
+use crate::services::notify::{Notification, Notifier};
+use crate::services::prices::{self, Prices};
+use crate::services::text::escape_html;
+
+const ARTICLES_PER_ALERT: usize = 5;
+
+// Articles this far back before the move window may still explain it.
+const ARTICLE_LOOKBACK: i64 = 6 * 60 * 60;
+
+// Price polls that may fail in a row before a rule can't be evaluated.
+const MISSED_POLLS: i64 = 1;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub symbol: String,
+    // Absolute percent change that triggers the alert.
+    pub threshold: f64,
+    pub window: i64,
+}
+
+impl AlertRule {
+    // Parses `BTC>5%/1h`.
+    pub fn parse(rule: &str) -> Result<AlertRule, String> {
+        let invalid = || format!("invalid alert rule {:?}, expected e.g. BTC>5%/1h", rule);
+        let (symbol, rest) = rule.trim().split_once('>').ok_or_else(invalid)?;
+        let (threshold, window) = rest.split_once('/').ok_or_else(invalid)?;
+        let threshold: f64 = threshold.trim().trim_end_matches('%').parse().map_err(|_| invalid())?;
+        let window = prices::parse_duration(window).ok_or_else(invalid)?;
+        if symbol.trim().is_empty() || threshold <= 0.0 || window <= 0 {
+            return Err(invalid());
+        }
+        Ok(AlertRule {
+            symbol: symbol.trim().to_uppercase(),
+            threshold,
+            window,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} moves more than {}% in {}m", self.symbol, self.threshold, self.window / 60)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AlertEvent {
+    pub id: i64,
+    pub rule: String,
+    pub symbol: String,
+    pub change: f64,
+    pub price: f64,
+    pub triggered_at: i64,
+    pub articles: Vec<ArchivedArticle>,
+}
+
+impl AlertEvent {
+    fn subject(&self) -> String {
+        format!("{} {:+.2}% ({})", self.symbol, self.change, self.rule)
+    }
+
+    fn to_text(&self) -> String {
+        let mut out = format!("{}\nPrice: {}\n\nRecent news:\n", self.subject(), self.price);
+        for archived in &self.articles {
+            out.push_str(&format!(
+                "* {} ({})\n  {}\n",
+                archived.article.title, archived.article.source, archived.article.link
+            ));
+        }
+        out
+    }
+
+    fn to_html(&self) -> String {
+        let mut out = format!(
+            "<h1>{}</h1>\n<p>Price: {}</p>\n<ul>\n",
+            escape_html(&self.subject()),
+            self.price
+        );
+        for archived in &self.articles {
+            out.push_str(&format!(
+                "<li><a href=\"{}\">{}</a> ({})</li>\n",
+                escape_html(&archived.article.link),
+                escape_html(&archived.article.title),
+                escape_html(&archived.article.source)
+            ));
+        }
+        out.push_str("</ul>\n");
+        out
+    }
+}
+
+// Most relevant first: articles inside the move window, then strong sentiment, then recency;
+// one article per story.
+fn relevant_articles(mut articles: Vec<ArchivedArticle>, window_start: i64) -> Vec<ArchivedArticle> {
+    let score = |archived: &ArchivedArticle| {
+        let in_window = if archived.published_at >= window_start { 1.0 } else { 0.0 };
+        in_window + archived.article.sentiment.unwrap_or(0.0).abs()
+    };
+    articles.sort_by(|a, b| {
+        score(b)
+            .total_cmp(&score(a))
+            .then(b.published_at.cmp(&a.published_at))
+    });
+    let mut stories = Vec::new();
+    articles.retain(|archived| match archived.story_id {
+        Some(story_id) if stories.contains(&story_id) => false,
+        Some(story_id) => {
+            stories.push(story_id);
+            true
+        }
+        None => true,
+    });
+    articles.truncate(ARTICLES_PER_ALERT);
+    articles
+}
+
+pub fn symbols(rules: &[AlertRule]) -> Vec<String> {
+    let mut symbols: Vec<String> = rules.iter().map(|rule| rule.symbol.clone()).collect();
+    symbols.sort();
+    symbols.dedup();
+    symbols
+}
+
+// Rules and recipients are taken from the config on every evaluation, so edits apply on reload.
+#[derive(Clone)]
+pub struct Alerts {
+    pool: SqlitePool,
+    notifier: Notifier,
+}
+
+impl Alerts {
+    pub fn new(pool: SqlitePool, notifier: Notifier) -> Self {
+        Alerts { pool, notifier }
+    }
+
+    // A rule doesn't fire again until a full window has passed since it last fired.
+    async fn last_triggered(&self, rule: &str) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar("SELECT MAX(triggered_at) FROM alert_events WHERE rule = ?")
+            .bind(rule)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn record(&self, event: &AlertEvent) -> Result<i64, sqlx::Error> {
+        let article_ids: Vec<String> = event.articles.iter().map(|a| a.id.to_string()).collect();
+        let result = sqlx::query(
+            "INSERT INTO alert_events (rule, symbol, change, price, triggered_at, article_ids)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&event.rule)
+        .bind(&event.symbol)
+        .bind(event.change)
+        .bind(event.price)
+        .bind(event.triggered_at)
+        .bind(article_ids.join(","))
+        .execute(&self.pool)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn recent(&self, archive: &Archive, limit: i64) -> Result<Vec<AlertEvent>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, rule, symbol, change, price, triggered_at, article_ids
+             FROM alert_events ORDER BY triggered_at DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        let article_ids: Vec<Vec<i64>> = rows
+            .iter()
+            .map(|row| {
+                let ids: String = row.get("article_ids");
+                ids.split(',').filter_map(|id| id.parse().ok()).collect()
+            })
+            .collect();
+        let articles = archive.get_many(&article_ids.concat()).await?;
+        Ok(rows
+            .iter()
+            .zip(article_ids)
+            .map(|(row, ids)| {
+                let mut event = event_from_row(row);
+                event.articles = ids.iter().filter_map(|id| articles.get(id).cloned()).collect();
+                event
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(name = "alerts", skip_all)]
+    pub async fn evaluate(&self, archive: &Archive, prices: &Prices, config: &Config) -> Result<(), sqlx::Error> {
+        let now = Utc::now().timestamp();
+        let tolerance = prices::snapshot_tolerance(config, MISSED_POLLS);
+        for rule in &config.alert_rules() {
+            let description = rule.describe();
+            if let Some(last) = self.last_triggered(&description).await? {
+                if now - last < rule.window {
+                    continue;
+                }
+            }
+            let current = prices.price_near(&rule.symbol, now, tolerance).await?;
+            let start = prices.price_near(&rule.symbol, now - rule.window, tolerance).await?;
+            let (current, start) = match (current, start) {
+                (Some(current), Some(start)) if start > 0.0 => (current, start),
+                _ => continue,
+            };
+            let change = (current - start) / start * 100.0;
+            if change.abs() < rule.threshold {
+                continue;
+            }
+
+            let window_start = now - rule.window;
+            let candidates: Vec<ArchivedArticle> = archive
+                .since(window_start - ARTICLE_LOOKBACK)
+                .await?
+                .into_iter()
+                .filter(|archived| archived.symbol == rule.symbol)
+                .collect();
+            let mut event = AlertEvent {
+                id: 0,
+                rule: description,
+                symbol: rule.symbol.clone(),
+                change,
+                price: current,
+                triggered_at: now,
+                articles: relevant_articles(candidates, window_start),
+            };
+            event.id = self.record(&event).await?;
+            tracing::info!(
+                alert = event.id,
+                rule = %event.rule,
+                change = event.change,
+                articles = event.articles.len(),
+                "alert triggered"
+            );
+
+            let mut notification = Notification::new(event.subject(), event.to_text(), event.to_html(), &event);
+            notification.recipients = config.alerts.recipients.clone();
+            notification.webhook_url = config.alerts.webhook_url.clone();
+            self.notifier.queue(&notification).await?;
+        }
+        Ok(())
+    }
+}
+
+fn event_from_row(row: &SqliteRow) -> AlertEvent {
+    AlertEvent {
+        id: row.get("id"),
+        rule: row.get("rule"),
+        symbol: row.get("symbol"),
+        change: row.get("change"),
+        price: row.get("price"),
+        triggered_at: row.get("triggered_at"),
+        articles: Vec::new(),
+    }
+}
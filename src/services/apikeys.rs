@@ -0,0 +1,296 @@
+// API keys: random tokens shown once when created and stored only as SHA-256 hashes. Each key
+// has a per-minute rate limit, counted in memory, and a daily quota, counted in the database per
+// UTC day along with its usage.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::config::AuthConfig;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const KEY_PREFIX: &str = "cna_";
+
+// Characters of a key kept in the clear so listings can tell keys apart.
+const SHOWN_CHARS: usize = 12;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    // The start of the key, e.g. `cna_1a2b3c4d`.
+    pub prefix: String,
+    pub admin: bool,
+    // Requests per minute and per UTC day; `None` uses the `[auth]` defaults.
+    pub rate_limit: Option<u32>,
+    pub daily_quota: Option<u64>,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub requests_today: i64,
+    pub requests_total: i64,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NewApiKey {
+    pub name: String,
+    #[serde(default)]
+    pub admin: bool,
+    pub rate_limit: Option<u32>,
+    pub daily_quota: Option<u64>,
+}
+
+impl NewApiKey {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        if self.rate_limit == Some(0) {
+            return Err("rate_limit must be greater than 0".to_string());
+        }
+        if self.daily_quota == Some(0) {
+            return Err("daily_quota must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+// A request let through, with what is left of the key's minute.
+pub struct Grant {
+    pub key: ApiKey,
+    pub rate_limit: u32,
+    pub remaining: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Denied {
+    Missing,
+    Invalid,
+    RateLimited { limit: u32, retry_after: u64 },
+    QuotaExceeded { quota: u64, retry_after: u64 },
+}
+
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+#[derive(Clone)]
+pub struct ApiKeys {
+    pool: SqlitePool,
+    windows: Arc<Mutex<HashMap<i64, Window>>>,
+}
+
+const KEY_QUERY: &str = "SELECT k.id, k.name, k.prefix, k.admin, k.rate_limit, k.daily_quota, k.created_at,
+        k.revoked_at, k.last_used_at,
+        COALESCE(SUM(CASE WHEN u.day = ?1 THEN u.requests END), 0) AS requests_today,
+        COALESCE(SUM(u.requests), 0) AS requests_total
+    FROM api_keys k LEFT JOIN api_key_usage u ON u.key_id = k.id";
+
+// Also used for other tokens kept only as hashes.
+pub fn hash(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+fn today() -> String {
+    Utc::now().format("%Y-%m-%d").to_string()
+}
+
+fn until_midnight() -> u64 {
+    let now = Utc::now();
+    let midnight = (now + ChronoDuration::days(1)).date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+    (midnight - now).num_seconds().max(1) as u64
+}
+
+impl ApiKeys {
+    pub fn new(pool: SqlitePool) -> Self {
+        ApiKeys {
+            pool,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Returns the key itself, which is not stored and can't be shown again, and its record.
+    pub async fn create(&self, new: &NewApiKey) -> Result<(String, ApiKey), sqlx::Error> {
+        let key = format!("{}{}", KEY_PREFIX, Uuid::new_v4().simple());
+        let id = sqlx::query(
+            "INSERT INTO api_keys (name, prefix, key_hash, admin, rate_limit, daily_quota, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(new.name.trim())
+        .bind(&key[..SHOWN_CHARS])
+        .bind(hash(&key))
+        .bind(new.admin)
+        .bind(new.rate_limit)
+        .bind(new.daily_quota.map(|quota| quota as i64))
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        let record = self.get(id).await?.ok_or(sqlx::Error::RowNotFound)?;
+        Ok((key, record))
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<ApiKey>, sqlx::Error> {
+        let row = sqlx::query(&format!("{} WHERE k.id = ?2 GROUP BY k.id", KEY_QUERY))
+            .bind(today())
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(from_row))
+    }
+
+    pub async fn list(&self) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let rows = sqlx::query(&format!("{} GROUP BY k.id ORDER BY k.id", KEY_QUERY))
+            .bind(today())
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    // Returns false when there is no such key or it was already revoked.
+    pub async fn revoke(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(Utc::now().timestamp())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        self.windows.lock().unwrap().remove(&id);
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Checks `key` and counts the request against its rate limit and quota.
+    pub async fn authorize(&self, key: Option<&str>, defaults: &AuthConfig) -> Result<Result<Grant, Denied>, sqlx::Error> {
+        let Some(key) = key.map(str::trim).filter(|key| !key.is_empty()) else {
+            return Ok(Err(Denied::Missing));
+        };
+        let row = sqlx::query(&format!(
+            "{} WHERE k.key_hash = ?2 AND k.revoked_at IS NULL GROUP BY k.id",
+            KEY_QUERY
+        ))
+        .bind(today())
+        .bind(hash(key))
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(record) = row.as_ref().map(from_row) else {
+            return Ok(Err(Denied::Invalid));
+        };
+
+        let rate_limit = record.rate_limit.unwrap_or(defaults.rate_limit_per_minute);
+        let remaining = {
+            let mut windows = self.windows.lock().unwrap();
+            let window = windows.entry(record.id).or_insert(Window {
+                started: Instant::now(),
+                requests: 0,
+            });
+            if window.started.elapsed() >= RATE_WINDOW {
+                window.started = Instant::now();
+                window.requests = 0;
+            }
+            if window.requests >= rate_limit {
+                let retry_after = RATE_WINDOW.saturating_sub(window.started.elapsed()).as_secs().max(1);
+                return Ok(Err(Denied::RateLimited {
+                    limit: rate_limit,
+                    retry_after,
+                }));
+            }
+            window.requests += 1;
+            rate_limit - window.requests
+        };
+
+        // Checked and counted in one statement, so concurrent requests can't go past the quota.
+        let quota = record.daily_quota.or(defaults.daily_quota);
+        let mut tx = self.pool.begin().await?;
+        let counted = sqlx::query(
+            "INSERT INTO api_key_usage (key_id, day, requests) SELECT ?1, ?2, 1 WHERE ?3 IS NULL OR ?3 > 0
+             ON CONFLICT (key_id, day) DO UPDATE SET requests = requests + 1 WHERE ?3 IS NULL OR requests < ?3",
+        )
+        .bind(record.id)
+        .bind(today())
+        .bind(quota.map(|quota| quota as i64))
+        .execute(&mut tx)
+        .await?;
+        if counted.rows_affected() == 0 {
+            return Ok(Err(Denied::QuotaExceeded {
+                quota: quota.unwrap_or(0),
+                retry_after: until_midnight(),
+            }));
+        }
+        sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE id = ?")
+            .bind(Utc::now().timestamp())
+            .bind(record.id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Ok(Grant {
+            key: record,
+            rate_limit,
+            remaining,
+        }))
+    }
+}
+
+fn from_row(row: &SqliteRow) -> ApiKey {
+    let rate_limit: Option<i64> = row.get("rate_limit");
+    let daily_quota: Option<i64> = row.get("daily_quota");
+    ApiKey {
+        id: row.get("id"),
+        name: row.get("name"),
+        prefix: row.get("prefix"),
+        admin: row.get("admin"),
+        rate_limit: rate_limit.map(|limit| limit as u32),
+        daily_quota: daily_quota.map(|quota| quota as u64),
+        created_at: row.get("created_at"),
+        revoked_at: row.get("revoked_at"),
+        last_used_at: row.get("last_used_at"),
+        requests_today: row.get("requests_today"),
+        requests_total: row.get("requests_total"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[actix_web::test]
+    async fn concurrent_requests_stay_within_the_daily_quota() {
+        let config = Config::parse("", std::iter::empty()).unwrap();
+        let state = crate::test_state(config.clone()).await;
+        let new = NewApiKey {
+            name: "reader".to_string(),
+            rate_limit: Some(1000),
+            daily_quota: Some(5),
+            ..NewApiKey::default()
+        };
+        let (key, _) = state.api_keys.create(&new).await.unwrap();
+
+        let requests: Vec<_> = (0..20)
+            .map(|_| {
+                let (api_keys, key, auth) = (state.api_keys.clone(), key.clone(), config.auth.clone());
+                tokio::spawn(async move { api_keys.authorize(Some(&key), &auth).await.unwrap() })
+            })
+            .collect();
+        let mut granted = 0;
+        for request in requests {
+            match request.await.unwrap() {
+                Ok(_) => granted += 1,
+                Err(denied) => assert!(matches!(denied, Denied::QuotaExceeded { quota: 5, .. })),
+            }
+        }
+        assert_eq!(granted, 5);
+        assert_eq!(state.api_keys.list().await.unwrap()[0].requests_today, 5);
+    }
+}
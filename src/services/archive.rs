@@ -0,0 +1,507 @@
+// Every article we have fetched, kept so digests and other offline jobs don't have to hit upstream.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+use crate::models::news::NewsArticle;
+use crate::services::{language, sentiment};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ArchivedArticle {
+    pub id: i64,
+    pub symbol: String,
+    pub published_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub story_id: Option<i64>,
+    #[serde(flatten)]
+    pub article: NewsArticle,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StorySummary {
+    pub id: i64,
+    pub headline: String,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub articles: i64,
+    pub sources: i64,
+    pub symbols: Vec<String>,
+}
+
+// Articles about `symbol` in one window, counted back from the present: window 0 is the latest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MentionCount {
+    pub symbol: String,
+    pub window: i64,
+    pub articles: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SentimentBucket {
+    pub start: i64,
+    pub average: f64,
+    pub articles: i64,
+    pub positive: i64,
+    pub negative: i64,
+}
+
+// An article as it appears in a personal feed: once per link, with every watched symbol it was
+// archived under.
+#[derive(Serialize, Debug, Clone)]
+pub struct FeedArticle {
+    pub id: i64,
+    pub symbols: Vec<String>,
+    pub published_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub story_id: Option<i64>,
+    // The reader's state, from `article_states`.
+    pub read: bool,
+    pub bookmarked: bool,
+    #[serde(flatten)]
+    pub article: NewsArticle,
+}
+
+// Selects articles whose symbol or publisher is listed; symbols upper case, sources lower case.
+#[derive(Debug, Clone, Default)]
+pub struct FeedFilter {
+    pub symbols: Vec<String>,
+    pub sources: Vec<String>,
+    // Whose read state and bookmarks to use.
+    pub user_id: Option<i64>,
+    pub unread: bool,
+    // Only the user's bookmarks, ignoring `symbols` and `sources`.
+    pub bookmarked: bool,
+    // Only matches of this saved search, ignoring `symbols` and `sources`.
+    pub saved_search: Option<i64>,
+    pub languages: Option<Vec<String>>,
+    // One of positive, neutral or negative.
+    pub sentiment: Option<String>,
+    pub min_sentiment: Option<f64>,
+    pub max_sentiment: Option<f64>,
+    // Paging: only articles after this (published_at, id) in feed order.
+    pub after: Option<(i64, i64)>,
+    pub limit: i64,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ArticleCounts {
+    pub total: i64,
+    pub last_24h: i64,
+    pub last_fetched_at: Option<i64>,
+}
+
+const ARTICLE_COLUMNS: &str =
+    "id, symbol, title, source, date, summary, link, published_at, sentiment, story_id, lang";
+
+const STORY_SUMMARY_QUERY: &str = "SELECT s.id, s.headline, s.first_seen, s.last_seen,
+        COUNT(DISTINCT a.link) AS articles, COUNT(DISTINCT a.source) AS sources,
+        GROUP_CONCAT(DISTINCT a.symbol) AS symbols
+    FROM stories s JOIN articles a ON a.story_id = s.id";
+
+#[derive(Clone)]
+pub struct Archive {
+    pool: SqlitePool,
+}
+
+impl Archive {
+    pub fn new(pool: SqlitePool) -> Self {
+        Archive { pool }
+    }
+
+    // Returns false when the article was already archived for this symbol. `origin` is the name of
+    // the source it was fetched from.
+    pub async fn insert(&self, origin: &str, symbol: &str, article: &NewsArticle) -> Result<bool, sqlx::Error> {
+        let now = Utc::now().timestamp();
+        let published_at = article.published_at().map(|date| date.timestamp()).unwrap_or(now);
+        let sentiment = article
+            .sentiment
+            .unwrap_or_else(|| sentiment::score(&format!("{} {}", article.title, article.summary)));
+        let lang = article
+            .lang
+            .clone()
+            .unwrap_or_else(|| language::detect(&format!("{} {}", article.title, article.summary)).to_string());
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO articles
+                (symbol, title, source, date, summary, link, published_at, fetched_at, sentiment, lang, origin)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(symbol.to_uppercase())
+        .bind(&article.title)
+        .bind(&article.source)
+        .bind(&article.date)
+        .bind(&article.summary)
+        .bind(&article.link)
+        .bind(published_at)
+        .bind(now)
+        .bind(sentiment)
+        .bind(lang)
+        .bind(origin)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Newest first, one entry per link. The links are picked first, each way through its own index,
+    // and only their rows are grouped. Lists are bound as JSON arrays.
+    pub async fn feed(&self, filter: &FeedFilter) -> Result<Vec<FeedArticle>, sqlx::Error> {
+        let list = |items: &[String]| serde_json::to_string(items).unwrap_or_else(|_| "[]".to_string());
+        let (after_published, after_id) = filter.after.unzip();
+        let rows = sqlx::query(
+            "WITH links AS (
+                 SELECT link FROM articles
+                 WHERE NOT ?13 AND ?14 IS NULL AND symbol IN (SELECT value FROM json_each(?1))
+                 UNION
+                 SELECT link FROM articles
+                 WHERE NOT ?13 AND ?14 IS NULL AND lower(source) IN (SELECT value FROM json_each(?2))
+                 UNION
+                 SELECT link FROM article_states WHERE ?13 AND user_id = ?11 AND bookmarked_at IS NOT NULL
+                 UNION
+                 SELECT link FROM saved_search_matches WHERE search_id = ?14
+             )
+             SELECT MIN(a.id) AS id, GROUP_CONCAT(DISTINCT a.symbol) AS symbols, a.title, a.source, a.date, a.summary,
+                    a.link, MAX(a.published_at) AS published_at, a.sentiment, a.story_id, a.lang,
+                    MAX(s.read_at) AS read_at, MAX(s.bookmarked_at) AS bookmarked_at
+             FROM links l
+             JOIN articles a ON a.link = l.link
+             LEFT JOIN article_states s ON s.user_id = ?11 AND s.link = a.link
+             WHERE (?3 IS NULL OR a.lang IN (SELECT value FROM json_each(?3)))
+               AND (?4 IS NULL OR a.sentiment >= ?4)
+               AND (?5 IS NULL OR a.sentiment <= ?5)
+               AND (?6 IS NULL
+                    OR (?6 = 'positive' AND a.sentiment >= ?7)
+                    OR (?6 = 'negative' AND a.sentiment <= -?7)
+                    OR (?6 = 'neutral' AND a.sentiment > -?7 AND a.sentiment < ?7))
+               AND (NOT ?12 OR s.read_at IS NULL)
+             GROUP BY a.link
+             HAVING ?8 IS NULL OR MAX(a.published_at) < ?8 OR (MAX(a.published_at) = ?8 AND MIN(a.id) < ?9)
+             ORDER BY published_at DESC, id DESC
+             LIMIT ?10",
+        )
+        .bind(list(&filter.symbols))
+        .bind(list(&filter.sources))
+        .bind(filter.languages.as_deref().map(list))
+        .bind(filter.min_sentiment)
+        .bind(filter.max_sentiment)
+        .bind(filter.sentiment.as_deref())
+        .bind(sentiment::NEUTRAL_THRESHOLD)
+        .bind(after_published)
+        .bind(after_id)
+        .bind(filter.limit)
+        .bind(filter.user_id)
+        .bind(filter.unread)
+        .bind(filter.bookmarked)
+        .bind(filter.saved_search)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let symbols: String = row.get("symbols");
+                let mut article = NewsArticle::new(
+                    row.get("title"),
+                    row.get("source"),
+                    row.get("date"),
+                    row.get("summary"),
+                    row.get("link"),
+                );
+                article.sentiment = Some(row.get("sentiment"));
+                article.lang = Some(row.get("lang"));
+                FeedArticle {
+                    id: row.get("id"),
+                    symbols: symbols.split(',').map(str::to_string).collect(),
+                    published_at: row.get("published_at"),
+                    story_id: row.get("story_id"),
+                    read: row.get::<Option<i64>, _>("read_at").is_some(),
+                    bookmarked: row.get::<Option<i64>, _>("bookmarked_at").is_some(),
+                    article,
+                }
+            })
+            .collect())
+    }
+
+    pub async fn since(&self, since: i64) -> Result<Vec<ArchivedArticle>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM articles WHERE published_at >= ? ORDER BY published_at DESC",
+            ARTICLE_COLUMNS
+        ))
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<ArchivedArticle>, sqlx::Error> {
+        let row = sqlx::query(&format!("SELECT {} FROM articles WHERE id = ?", ARTICLE_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(from_row))
+    }
+
+    // The articles with these ids that still exist, keyed by id.
+    pub async fn get_many(&self, ids: &[i64]) -> Result<HashMap<i64, ArchivedArticle>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM articles WHERE id IN (SELECT value FROM json_each(?))",
+            ARTICLE_COLUMNS
+        ))
+        .bind(serde_json::to_string(ids).unwrap_or_default())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(from_row).map(|archived| (archived.id, archived)).collect())
+    }
+
+    // Distinct symbols with articles published since `since`.
+    pub async fn symbols_since(&self, since: i64) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar("SELECT DISTINCT symbol FROM articles WHERE published_at >= ? ORDER BY symbol")
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn unclustered(&self) -> Result<Vec<ArchivedArticle>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM articles WHERE story_id IS NULL ORDER BY published_at LIMIT 5000",
+            ARTICLE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    pub async fn clustered_since(&self, since: i64) -> Result<Vec<ArchivedArticle>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM articles WHERE story_id IS NOT NULL AND published_at >= ? ORDER BY published_at",
+            ARTICLE_COLUMNS
+        ))
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    pub async fn create_story(&self, headline: &str, first_seen: i64) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query("INSERT INTO stories (headline, first_seen, last_seen) VALUES (?, ?, ?)")
+            .bind(headline)
+            .bind(first_seen)
+            .bind(first_seen)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn assign_story(&self, article_id: i64, story_id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE articles SET story_id = ? WHERE id = ?")
+            .bind(story_id)
+            .bind(article_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Recomputes the story's time span from its articles and replaces its headline.
+    pub async fn refresh_story(&self, story_id: i64, headline: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE stories SET headline = ?1,
+                first_seen = (SELECT MIN(published_at) FROM articles WHERE story_id = ?2),
+                last_seen = (SELECT MAX(published_at) FROM articles WHERE story_id = ?2)
+             WHERE id = ?2",
+        )
+        .bind(headline)
+        .bind(story_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // Coverage of a story, oldest first.
+    pub async fn story_articles(&self, story_id: i64) -> Result<Vec<ArchivedArticle>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM articles WHERE story_id = ? ORDER BY published_at",
+            ARTICLE_COLUMNS
+        ))
+        .bind(story_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    // Only articles in `languages` (when given) count towards a story.
+    pub async fn stories(
+        &self,
+        since: i64,
+        symbol: Option<&str>,
+        languages: Option<&[String]>,
+        limit: i64,
+    ) -> Result<Vec<StorySummary>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "{} WHERE s.last_seen >= ?1 AND (?4 IS NULL OR instr(?4, ',' || a.lang || ',') > 0)
+             GROUP BY s.id
+             HAVING ?2 IS NULL OR SUM(a.symbol = ?2) > 0
+             ORDER BY s.last_seen DESC LIMIT ?3",
+            STORY_SUMMARY_QUERY
+        ))
+        .bind(since)
+        .bind(symbol.map(str::to_uppercase))
+        .bind(limit)
+        .bind(languages.map(|languages| format!(",{},", languages.join(","))))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(story_from_row).collect())
+    }
+
+    pub async fn story(&self, story_id: i64) -> Result<Option<StorySummary>, sqlx::Error> {
+        let row = sqlx::query(&format!("{} WHERE s.id = ? GROUP BY s.id", STORY_SUMMARY_QUERY))
+            .bind(story_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(story_from_row))
+    }
+
+    // Average sentiment per `bucket` seconds, oldest bucket first; empty buckets are omitted.
+    pub async fn sentiment_series(
+        &self,
+        symbol: &str,
+        since: i64,
+        bucket: i64,
+        languages: Option<&[String]>,
+    ) -> Result<Vec<SentimentBucket>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT (published_at / ?1) * ?1 AS start, AVG(sentiment) AS average, COUNT(*) AS articles,
+                    SUM(sentiment >= ?2) AS positive, SUM(sentiment <= -?2) AS negative
+             FROM articles WHERE symbol = ?3 AND published_at >= ?4
+               AND (?5 IS NULL OR instr(?5, ',' || lang || ',') > 0)
+             GROUP BY start ORDER BY start",
+        )
+        .bind(bucket)
+        .bind(sentiment::NEUTRAL_THRESHOLD)
+        .bind(symbol.to_uppercase())
+        .bind(since)
+        .bind(languages.map(|languages| format!(",{},", languages.join(","))))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| SentimentBucket {
+                start: row.get("start"),
+                average: row.get("average"),
+                articles: row.get("articles"),
+                positive: row.get("positive"),
+                negative: row.get("negative"),
+            })
+            .collect())
+    }
+
+    // Articles per symbol in each of the `windows` periods of `window` seconds ending at `now`.
+    pub async fn mention_counts(
+        &self,
+        now: i64,
+        window: i64,
+        windows: i64,
+        languages: Option<&[String]>,
+    ) -> Result<Vec<MentionCount>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT symbol, (?1 - published_at) / ?2 AS window, COUNT(*) AS articles
+             FROM articles WHERE published_at <= ?1 AND published_at > ?1 - ?2 * ?3
+               AND (?4 IS NULL OR instr(?4, ',' || lang || ',') > 0)
+             GROUP BY symbol, window",
+        )
+        .bind(now)
+        .bind(window)
+        .bind(windows)
+        .bind(languages.map(|languages| format!(",{},", languages.join(","))))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| MentionCount {
+                symbol: row.get("symbol"),
+                window: row.get("window"),
+                articles: row.get("articles"),
+            })
+            .collect())
+    }
+
+    // Title and publication time of each link published after `since` and up to `until`.
+    pub async fn titles(
+        &self,
+        since: i64,
+        until: i64,
+        languages: Option<&[String]>,
+    ) -> Result<Vec<(String, i64)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT MIN(title) AS title, MIN(published_at) AS published_at FROM articles
+             WHERE published_at > ?1 AND published_at <= ?2
+               AND (?3 IS NULL OR instr(?3, ',' || lang || ',') > 0)
+             GROUP BY link",
+        )
+        .bind(since)
+        .bind(until)
+        .bind(languages.map(|languages| format!(",{},", languages.join(","))))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(|row| (row.get("title"), row.get("published_at"))).collect())
+    }
+
+    // Articles stored per source they were fetched from, keyed by source name.
+    pub async fn counts_by_origin(&self) -> Result<HashMap<String, ArticleCounts>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT origin, COUNT(*) AS total, SUM(published_at >= ?) AS last_24h, MAX(fetched_at) AS last_fetched_at
+             FROM articles GROUP BY origin",
+        )
+        .bind(Utc::now().timestamp() - 24 * 3600)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let counts = ArticleCounts {
+                    total: row.get("total"),
+                    last_24h: row.get("last_24h"),
+                    last_fetched_at: row.get("last_fetched_at"),
+                };
+                (row.get("origin"), counts)
+            })
+            .collect())
+    }
+
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+pub fn from_row(row: &SqliteRow) -> ArchivedArticle {
+    let mut article = NewsArticle::new(
+        row.get("title"),
+        row.get("source"),
+        row.get("date"),
+        row.get("summary"),
+        row.get("link"),
+    );
+    article.sentiment = Some(row.get("sentiment"));
+    article.lang = Some(row.get("lang"));
+    ArchivedArticle {
+        id: row.get("id"),
+        symbol: row.get("symbol"),
+        published_at: row.get("published_at"),
+        story_id: row.get("story_id"),
+        article,
+    }
+}
+
+fn story_from_row(row: &SqliteRow) -> StorySummary {
+    let symbols: String = row.get("symbols");
+    StorySummary {
+        id: row.get("id"),
+        headline: row.get("headline"),
+        first_seen: row.get("first_seen"),
+        last_seen: row.get("last_seen"),
+        articles: row.get("articles"),
+        sources: row.get("sources"),
+        symbols: symbols.split(',').map(str::to_string).collect(),
+    }
+}
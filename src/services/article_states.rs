@@ -0,0 +1,253 @@
+// Users' read state and bookmarks on archived articles, kept per link like the personal feed, which
+// lists each link once.
+
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct UnreadCounts {
+    // Distinct articles; one archived under several symbols counts once here and once per symbol.
+    pub total: i64,
+    pub symbols: BTreeMap<String, i64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Mark {
+    Read,
+    Bookmark,
+}
+
+impl Mark {
+    fn column(self) -> &'static str {
+        match self {
+            Mark::Read => "read_at",
+            Mark::Bookmark => "bookmarked_at",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ArticleStates {
+    pool: SqlitePool,
+}
+
+fn list(items: &[String]) -> String {
+    format!(",{},", items.join(","))
+}
+
+impl ArticleStates {
+    pub fn new(pool: SqlitePool) -> Self {
+        ArticleStates { pool }
+    }
+
+    // Sets or clears the mark. Setting it again keeps the time it was first set.
+    pub async fn set(&self, user_id: i64, link: &str, mark: Mark, on: bool) -> Result<(), sqlx::Error> {
+        let now = Utc::now().timestamp();
+        sqlx::query(&format!(
+            "INSERT INTO article_states (user_id, link, {0}, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (user_id, link) DO UPDATE
+             SET {0} = CASE WHEN excluded.{0} IS NULL THEN NULL ELSE COALESCE({0}, excluded.{0}) END,
+                 updated_at = excluded.updated_at",
+            mark.column()
+        ))
+        .bind(user_id)
+        .bind(link)
+        .bind(on.then_some(now))
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // Marks every article matching the symbols or sources and published up to `up_to` as read, and
+    // returns how many weren't already.
+    pub async fn mark_read_up_to(
+        &self,
+        user_id: i64,
+        symbols: &[String],
+        sources: &[String],
+        up_to: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let now = Utc::now().timestamp();
+        let result = sqlx::query(
+            "INSERT INTO article_states (user_id, link, read_at, updated_at)
+             SELECT DISTINCT ?1, link, ?5, ?5 FROM articles
+             WHERE (instr(?2, ',' || symbol || ',') > 0 OR instr(?3, ',' || lower(source) || ',') > 0)
+               AND published_at <= ?4
+             ON CONFLICT (user_id, link) DO UPDATE
+             SET read_at = excluded.read_at, updated_at = excluded.updated_at
+             WHERE read_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(list(symbols))
+        .bind(list(sources))
+        .bind(up_to)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    // Unread articles matching the symbols or sources, per symbol they were archived under.
+    pub async fn unread_counts(
+        &self,
+        user_id: i64,
+        symbols: &[String],
+        sources: &[String],
+    ) -> Result<UnreadCounts, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT a.symbol, COUNT(DISTINCT a.link) AS unread FROM articles a
+             LEFT JOIN article_states s ON s.user_id = ?1 AND s.link = a.link
+             WHERE (instr(?2, ',' || a.symbol || ',') > 0 OR instr(?3, ',' || lower(a.source) || ',') > 0)
+               AND s.read_at IS NULL
+             GROUP BY a.symbol
+             ORDER BY a.symbol",
+        )
+        .bind(user_id)
+        .bind(list(symbols))
+        .bind(list(sources))
+        .fetch_all(&self.pool)
+        .await?;
+        let total = sqlx::query_scalar(
+            "SELECT COUNT(DISTINCT a.link) FROM articles a
+             LEFT JOIN article_states s ON s.user_id = ?1 AND s.link = a.link
+             WHERE (instr(?2, ',' || a.symbol || ',') > 0 OR instr(?3, ',' || lower(a.source) || ',') > 0)
+               AND s.read_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(list(symbols))
+        .bind(list(sources))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(UnreadCounts {
+            total,
+            symbols: rows.iter().map(|row| (row.get("symbol"), row.get("unread"))).collect(),
+        })
+    }
+
+    // When the user last changed any mark, so feed responses can report it as modified.
+    pub async fn last_change(&self, user_id: i64) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar("SELECT MAX(updated_at) FROM article_states WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::models::news::NewsArticle;
+
+    fn article(source: &str, link: &str, published: chrono::DateTime<Utc>) -> NewsArticle {
+        NewsArticle::new(
+            "Bitcoin rallies".to_string(),
+            source.to_string(),
+            published.to_rfc3339(),
+            String::new(),
+            link.to_string(),
+        )
+    }
+
+    #[actix_web::test]
+    async fn unread_counts_follow_read_marks_per_link() {
+        let config = Config::parse("[auth]\njwt_secret = \"0123456789abcdef0123456789abcdef-test\"", std::iter::empty()).unwrap();
+        let state = crate::test_state(config.clone()).await;
+        let user = state
+            .users
+            .register(&config.auth, "203.0.113.1", "ana@example.com", "password123")
+            .await
+            .unwrap()
+            .user;
+        let states = &state.article_states;
+        let now = Utc::now();
+        let older = article("Wire", "https://example.com/1", now - Duration::hours(2));
+        state.archive.insert("wire", "BTC", &older).await.unwrap();
+        state.archive.insert("wire", "ETH", &older).await.unwrap();
+        state
+            .archive
+            .insert("wire", "BTC", &article("Wire", "https://example.com/2", now - Duration::hours(1)))
+            .await
+            .unwrap();
+        state
+            .archive
+            .insert("desk", "SOL", &article("Desk", "https://example.com/3", now))
+            .await
+            .unwrap();
+        let symbols = ["BTC".to_string(), "ETH".to_string()];
+        assert_eq!(states.last_change(user.id).await.unwrap(), None);
+
+        // An article archived under two symbols counts once in the total.
+        let counts = states.unread_counts(user.id, &symbols, &[]).await.unwrap();
+        assert_eq!(counts.total, 2);
+        assert_eq!(counts.symbols, BTreeMap::from([("BTC".to_string(), 2), ("ETH".to_string(), 1)]));
+        let counts = states.unread_counts(user.id, &symbols, &["desk".to_string()]).await.unwrap();
+        assert_eq!(counts.total, 3);
+
+        states.set(user.id, "https://example.com/2", Mark::Read, true).await.unwrap();
+        let counts = states.unread_counts(user.id, &symbols, &[]).await.unwrap();
+        assert_eq!(counts.total, 1);
+        assert!(states.last_change(user.id).await.unwrap().is_some());
+
+        // Up to a time: only the older article, once for both of its symbols.
+        let up_to = (now - Duration::minutes(90)).timestamp();
+        assert_eq!(states.mark_read_up_to(user.id, &symbols, &[], up_to).await.unwrap(), 1);
+        assert_eq!(states.mark_read_up_to(user.id, &symbols, &[], now.timestamp()).await.unwrap(), 0);
+        let counts = states.unread_counts(user.id, &symbols, &["desk".to_string()]).await.unwrap();
+        assert_eq!(counts.total, 1);
+        assert_eq!(counts.symbols, BTreeMap::from([("SOL".to_string(), 1)]));
+
+        states.set(user.id, "https://example.com/2", Mark::Read, false).await.unwrap();
+        assert_eq!(states.unread_counts(user.id, &symbols, &[]).await.unwrap().total, 1);
+    }
+
+    #[actix_web::test]
+    async fn marks_keep_the_time_they_were_first_set() {
+        let config = Config::parse("[auth]\njwt_secret = \"0123456789abcdef0123456789abcdef-test\"", std::iter::empty()).unwrap();
+        let state = crate::test_state(config.clone()).await;
+        let user = state
+            .users
+            .register(&config.auth, "203.0.113.1", "ana@example.com", "password123")
+            .await
+            .unwrap()
+            .user;
+        let states = &state.article_states;
+        let link = "https://example.com/1";
+        let marks = || async {
+            sqlx::query_as::<_, (Option<i64>, Option<i64>)>(
+                "SELECT read_at, bookmarked_at FROM article_states WHERE user_id = ? AND link = ?",
+            )
+            .bind(user.id)
+            .bind(link)
+            .fetch_one(&states.pool)
+            .await
+            .unwrap()
+        };
+
+        states.set(user.id, link, Mark::Bookmark, true).await.unwrap();
+        let (read_at, bookmarked_at) = marks().await;
+        assert_eq!(read_at, None);
+        let bookmarked_at = bookmarked_at.unwrap();
+        sqlx::query("UPDATE article_states SET bookmarked_at = bookmarked_at - 100")
+            .execute(&states.pool)
+            .await
+            .unwrap();
+
+        states.set(user.id, link, Mark::Bookmark, true).await.unwrap();
+        assert_eq!(marks().await.1, Some(bookmarked_at - 100));
+        // The marks are independent.
+        states.set(user.id, link, Mark::Read, true).await.unwrap();
+        assert_eq!(marks().await.1, Some(bookmarked_at - 100));
+        states.set(user.id, link, Mark::Bookmark, false).await.unwrap();
+        let (read_at, bookmarked_at) = marks().await;
+        assert!(read_at.is_some());
+        assert_eq!(bookmarked_at, None);
+    }
+}
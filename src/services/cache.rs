@@ -1,9 +1,42 @@
 use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::services::shutdown::Shutdown;
+
 pub struct Cache {
     data: HashMap<String, (String, Instant)>, // Stores news articles with their expiration time
     ttl: Duration, // Time to live for cached items
+    stats: CacheStats,
+}
+
+// Running totals since startup, exported by `/metrics`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+// Written by `save` so a restart doesn't start cold. Entries carry the wall-clock time they
+// were stored at, since an `Instant` means nothing to another process.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    key: String,
+    value: String,
+    stored_at_ms: i64,
 }
 
 impl Cache {
@@ -11,15 +44,18 @@ impl Cache {
         Cache {
             data: HashMap::new(),
             ttl,
+            stats: CacheStats::default(),
         }
     }
 
-    pub fn get(&self, key: &str) -> Option<&String> {
+    pub fn get(&mut self, key: &str) -> Option<&String> {
         if let Some((value, timestamp)) = self.data.get(key) {
             if timestamp.elapsed() < self.ttl {
+                self.stats.hits += 1;
                 return Some(value);
             }
         }
+        self.stats.misses += 1;
         None
     }
 
@@ -29,6 +65,122 @@ impl Cache {
     }
 
     pub fn clear(&mut self) {
+        let before = self.data.len();
         self.data.retain(|_, (_, timestamp)| timestamp.elapsed() < self.ttl);
+        self.stats.evictions += (before - self.data.len()) as u64;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.data.len(),
+            ..self.stats
+        }
+    }
+
+    // A copy of the live entries for `save`, which writes it once the lock is released.
+    fn snapshot(&self) -> Snapshot {
+        let now_ms = Utc::now().timestamp_millis();
+        let entries = self
+            .data
+            .iter()
+            .filter(|(_, (_, timestamp))| timestamp.elapsed() < self.ttl)
+            .map(|(key, (value, timestamp))| SnapshotEntry {
+                key: key.clone(),
+                value: value.clone(),
+                stored_at_ms: now_ms - timestamp.elapsed().as_millis() as i64,
+            })
+            .collect();
+        Snapshot { entries }
+    }
+
+    // Restores the entries of a snapshot written by `save` that are still within the TTL, each
+    // with the time it has left. Returns the number of entries restored.
+    pub fn load(&mut self, path: &Path) -> io::Result<usize> {
+        let snapshot: Snapshot = serde_json::from_slice(&fs::read(path)?)?;
+        let now_ms = Utc::now().timestamp_millis();
+        let mut restored = 0;
+        for entry in snapshot.entries {
+            let age = Duration::from_millis(now_ms.saturating_sub(entry.stored_at_ms).max(0) as u64);
+            if age >= self.ttl {
+                continue;
+            }
+            if let Some(timestamp) = Instant::now().checked_sub(age) {
+                self.data.insert(entry.key, (entry.value, timestamp));
+                restored += 1;
+            }
+        }
+        Ok(restored)
     }
-}
\ No newline at end of file
+}
+
+// Writes the live entries of `cache` to `path`, through a temporary file so a crash never leaves
+// a truncated snapshot. The lock is only held while the entries are copied; the file is written on
+// the blocking pool. Returns the number of entries written.
+pub async fn save(cache: &Mutex<Cache>, path: &Path) -> io::Result<usize> {
+    let snapshot = cache.lock().unwrap().snapshot();
+    let count = snapshot.entries.len();
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let body = serde_json::to_vec(&snapshot)?;
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, body)?;
+        fs::rename(&temporary, &path)
+    })
+    .await
+    .map_err(io::Error::other)??;
+    Ok(count)
+}
+
+// Saves a snapshot every `interval` until shutdown; the final one is written by `main` once the
+// server has stopped.
+pub async fn run_snapshots(cache: Arc<Mutex<Cache>>, path: PathBuf, interval: Duration, mut shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(interval);
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => return,
+        }
+        match save(&cache, &path).await {
+            Ok(entries) => tracing::debug!(entries, path = %path.display(), "saved cache snapshot"),
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "failed to save cache snapshot"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[actix_web::test]
+    async fn snapshots_restore_live_entries_with_their_age() {
+        let path = env::temp_dir().join(format!("cna-test-{}.json", uuid::Uuid::new_v4().simple()));
+        let mut cache = Cache::new(TTL);
+        cache.set("fresh".to_string(), "1".to_string());
+        let now = Instant::now();
+        cache.data.insert("old".to_string(), ("2".to_string(), now - Duration::from_secs(50)));
+        cache.data.insert("expired".to_string(), ("3".to_string(), now - Duration::from_secs(70)));
+        let cache = Mutex::new(cache);
+
+        assert_eq!(save(&cache, &path).await.unwrap(), 2);
+
+        let mut restored = Cache::new(TTL);
+        assert_eq!(restored.load(&path).unwrap(), 2);
+        assert_eq!(restored.get("fresh").map(String::as_str), Some("1"));
+        assert_eq!(restored.get("old").map(String::as_str), Some("2"));
+        assert_eq!(restored.get("expired"), None);
+        // The entry keeps its age, so it expires when it would have without the restart.
+        assert!(restored.data["old"].1.elapsed() >= Duration::from_secs(50));
+
+        // Entries older than the loading cache's TTL are dropped.
+        let mut shorter = Cache::new(Duration::from_secs(30));
+        assert_eq!(shorter.load(&path).unwrap(), 1);
+        assert_eq!(shorter.get("old"), None);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+// SQLite connection pool and schema migrations.
+
+use std::str::FromStr;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::Executor;
+
+// Applied in order; the index of the last applied entry is kept in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE articles (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        symbol TEXT NOT NULL,
+        title TEXT NOT NULL,
+        source TEXT NOT NULL,
+        date TEXT NOT NULL,
+        summary TEXT NOT NULL,
+        link TEXT NOT NULL,
+        published_at INTEGER NOT NULL,
+        fetched_at INTEGER NOT NULL,
+        UNIQUE (symbol, link)
+    );
+    CREATE INDEX articles_published_at ON articles (published_at);",
+    "ALTER TABLE articles ADD COLUMN sentiment REAL NOT NULL DEFAULT 0;",
+    "CREATE TABLE stories (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        headline TEXT NOT NULL,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL
+    );
+    ALTER TABLE articles ADD COLUMN story_id INTEGER REFERENCES stories (id);
+    CREATE INDEX articles_story_id ON articles (story_id);",
+    "ALTER TABLE articles ADD COLUMN lang TEXT NOT NULL DEFAULT 'und';",
+    "CREATE TABLE price_snapshots (
+        symbol TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        price REAL NOT NULL,
+        PRIMARY KEY (symbol, timestamp)
+    );
+    CREATE TABLE price_moves (
+        article_id INTEGER NOT NULL REFERENCES articles (id),
+        horizon INTEGER NOT NULL,
+        price REAL,
+        PRIMARY KEY (article_id, horizon)
+    );",
+    "CREATE TABLE alert_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        rule TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        change REAL NOT NULL,
+        price REAL NOT NULL,
+        triggered_at INTEGER NOT NULL,
+        article_ids TEXT NOT NULL
+    );
+    CREATE INDEX alert_events_rule ON alert_events (rule, triggered_at);",
+    // The configured source an article was fetched from; `source` is its publisher.
+    "ALTER TABLE articles ADD COLUMN origin TEXT NOT NULL DEFAULT 'cryptqnews';
+    CREATE INDEX articles_origin ON articles (origin, published_at);",
+    "CREATE TABLE api_keys (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        prefix TEXT NOT NULL,
+        key_hash TEXT NOT NULL UNIQUE,
+        admin INTEGER NOT NULL DEFAULT 0,
+        rate_limit INTEGER,
+        daily_quota INTEGER,
+        created_at INTEGER NOT NULL,
+        revoked_at INTEGER,
+        last_used_at INTEGER
+    );
+    CREATE TABLE api_key_usage (
+        key_id INTEGER NOT NULL REFERENCES api_keys (id),
+        day TEXT NOT NULL,
+        requests INTEGER NOT NULL,
+        PRIMARY KEY (key_id, day)
+    );",
+    "CREATE TABLE users (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        email TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        password_hash TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        last_login_at INTEGER
+    );
+    CREATE TABLE refresh_tokens (
+        id TEXT PRIMARY KEY,
+        user_id INTEGER NOT NULL REFERENCES users (id),
+        expires_at INTEGER NOT NULL,
+        revoked_at INTEGER
+    );
+    CREATE INDEX refresh_tokens_user_id ON refresh_tokens (user_id);",
+    // Symbols and sources are stored as comma-separated lists, matched like `lang` filters.
+    "CREATE TABLE watchlists (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL REFERENCES users (id),
+        name TEXT NOT NULL,
+        symbols TEXT NOT NULL,
+        sources TEXT NOT NULL,
+        updated_at INTEGER NOT NULL,
+        UNIQUE (user_id, name)
+    );
+    ALTER TABLE users ADD COLUMN feed_token_hash TEXT;
+    CREATE UNIQUE INDEX users_feed_token_hash ON users (feed_token_hash);
+    CREATE INDEX articles_link ON articles (link);",
+    // Per user and article link, so an article archived under several symbols is read once.
+    "CREATE TABLE article_states (
+        user_id INTEGER NOT NULL REFERENCES users (id),
+        link TEXT NOT NULL,
+        read_at INTEGER,
+        bookmarked_at INTEGER,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (user_id, link)
+    );",
+    // Filters are stored like watchlists; `languages` is empty for any language.
+    "CREATE TABLE saved_searches (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL REFERENCES users (id),
+        name TEXT NOT NULL,
+        query TEXT NOT NULL,
+        symbols TEXT NOT NULL,
+        sources TEXT NOT NULL,
+        languages TEXT NOT NULL,
+        sentiment TEXT,
+        min_sentiment REAL,
+        max_sentiment REAL,
+        email INTEGER NOT NULL DEFAULT 0,
+        webhook_url TEXT,
+        updated_at INTEGER NOT NULL,
+        UNIQUE (user_id, name)
+    );
+    CREATE TABLE saved_search_matches (
+        search_id INTEGER NOT NULL REFERENCES saved_searches (id),
+        link TEXT NOT NULL,
+        matched_at INTEGER NOT NULL,
+        PRIMARY KEY (search_id, link)
+    );",
+    // Sources added through the admin API; those under `[sources]` in the config aren't stored.
+    // `headers` is a JSON object, `mapping` the JSON mapping of `json` sources.
+    "CREATE TABLE sources (
+        name TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        url TEXT NOT NULL,
+        symbols TEXT NOT NULL,
+        require_mention INTEGER NOT NULL DEFAULT 0,
+        api_key TEXT,
+        api_key_header TEXT,
+        headers TEXT NOT NULL,
+        mapping TEXT,
+        interval_secs INTEGER NOT NULL,
+        enabled INTEGER NOT NULL DEFAULT 1,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE fetch_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        source TEXT NOT NULL,
+        manual INTEGER NOT NULL,
+        started_at INTEGER NOT NULL,
+        elapsed_ms INTEGER NOT NULL,
+        fetched INTEGER NOT NULL,
+        stored INTEGER NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX fetch_log_source ON fetch_log (source, id);
+    CREATE INDEX fetch_log_started_at ON fetch_log (started_at);",
+    // Email and webhook notifications waiting to be sent; `recipients` is a JSON array.
+    "CREATE TABLE notifications (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        subject TEXT NOT NULL,
+        text TEXT NOT NULL,
+        html TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        recipients TEXT NOT NULL,
+        webhook_url TEXT,
+        created_at INTEGER NOT NULL
+    );",
+    // Comma-separated, like `lang` filters; NULL when the user hasn't chosen any.
+    "ALTER TABLE users ADD COLUMN languages TEXT;",
+    // When each digest period was last emailed, so a restart doesn't move the schedule.
+    "CREATE TABLE digest_runs (
+        period TEXT PRIMARY KEY,
+        sent_at INTEGER NOT NULL
+    );",
+    // For feeds of watched publishers, which are matched case-insensitively.
+    "CREATE INDEX articles_source ON articles (lower(source));",
+];
+
+pub async fn connect(url: &str) -> Result<SqlitePool, sqlx::Error> {
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+    let pool = SqlitePoolOptions::new().connect_with(options).await?;
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+async fn migrate(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(pool).await?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let mut tx = pool.begin().await?;
+        tx.execute(*migration).await?;
+        tx.execute(format!("PRAGMA user_version = {}", index + 1).as_str()).await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,475 @@
+// Builds daily and weekly digests of the top archived stories for a watchlist.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use tokio::sync::watch;
+
+use crate::config::Config;
+use crate::models::watchlist::Watchlist;
+use crate::services::archive::{Archive, ArchivedArticle};
+use crate::services::mailer::Mailer;
+use crate::services::shutdown::Shutdown;
+use crate::services::summarize;
+use crate::services::text::{escape_html, escape_markdown, escape_markdown_link};
+
+const STORIES_PER_SYMBOL: usize = 5;
+
+pub const SUMMARY_CHARS: usize = 200;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Daily,
+    Weekly,
+}
+
+impl Period {
+    pub fn parse(value: &str) -> Option<Period> {
+        match value.to_lowercase().as_str() {
+            "daily" | "day" => Some(Period::Daily),
+            "weekly" | "week" => Some(Period::Weekly),
+            _ => None,
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Period::Daily => 24 * 60 * 60,
+            Period::Weekly => 7 * 24 * 60 * 60,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Period::Daily => "daily",
+            Period::Weekly => "weekly",
+        }
+    }
+
+    // The first time after `after` (unix seconds) a digest is due: midnight UTC, on Mondays for
+    // weekly ones. The Unix epoch was a Thursday, so weeks are counted from the Monday after it.
+    fn next_run(&self, after: i64) -> i64 {
+        let offset = match self {
+            Period::Daily => 0,
+            Period::Weekly => 4 * 24 * 60 * 60,
+        };
+        ((after - offset).div_euclid(self.seconds()) + 1) * self.seconds() + offset
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Period::Daily => "Daily",
+            Period::Weekly => "Weekly",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DigestStory {
+    pub title: String,
+    pub link: String,
+    pub summary: String,
+    pub sources: Vec<String>,
+    pub published_at: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DigestSection {
+    pub symbol: String,
+    pub stories: Vec<DigestStory>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Digest {
+    pub watchlist: String,
+    pub period: Period,
+    // The newest story's publication time rather than the time of the request, so the same
+    // stories always render the same body (and ETag).
+    pub updated_at: Option<i64>,
+    pub sections: Vec<DigestSection>,
+}
+
+// Articles from different sources with the same headline count as one story.
+fn story_key(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// More sources outweighs freshness: each extra source is worth a full period of age.
+fn score(story: &DigestStory, now: i64, period: Period) -> f64 {
+    let age = (now - story.published_at).max(0) as f64 / period.seconds() as f64;
+    story.sources.len() as f64 + (1.0 - age).max(0.0)
+}
+
+pub fn build(
+    watchlist: &Watchlist,
+    articles: &[ArchivedArticle],
+    period: Period,
+    now: i64,
+    summary_chars: usize,
+) -> Digest {
+    let since = now - period.seconds();
+    let mut sections = Vec::new();
+
+    for symbol in &watchlist.symbols {
+        let mut stories: HashMap<String, (DigestStory, BTreeSet<String>)> = HashMap::new();
+        for archived in articles
+            .iter()
+            .filter(|archived| &archived.symbol == symbol && archived.published_at >= since)
+        {
+            let article = &archived.article;
+            let (story, sources) = stories.entry(story_key(&article.title)).or_insert_with(|| {
+                let story = DigestStory {
+                    title: article.title.clone(),
+                    link: article.link.clone(),
+                    summary: article.summary.clone(),
+                    sources: Vec::new(),
+                    published_at: archived.published_at,
+                };
+                (story, BTreeSet::new())
+            });
+            sources.insert(article.source.clone());
+            if archived.published_at < story.published_at {
+                story.published_at = archived.published_at;
+                story.link = article.link.clone();
+            }
+            if story.summary.is_empty() {
+                story.summary = article.summary.clone();
+            }
+        }
+
+        let mut stories: Vec<DigestStory> = stories
+            .into_values()
+            .map(|(mut story, sources)| {
+                story.sources = sources.into_iter().collect();
+                story.summary = summarize::summarize_article(&story.summary, None, summary_chars);
+                story
+            })
+            .collect();
+        stories.sort_by(|a, b| score(b, now, period).total_cmp(&score(a, now, period)));
+        stories.truncate(STORIES_PER_SYMBOL);
+
+        if !stories.is_empty() {
+            sections.push(DigestSection {
+                symbol: symbol.clone(),
+                stories,
+            });
+        }
+    }
+
+    let updated_at = sections
+        .iter()
+        .flat_map(|section| section.stories.iter().map(|story| story.published_at))
+        .max();
+    Digest {
+        watchlist: watchlist.name.clone(),
+        period,
+        updated_at,
+        sections,
+    }
+}
+
+fn format_time(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+impl Digest {
+    pub fn subject(&self) -> String {
+        format!("{} digest: {}", self.period.title(), self.watchlist)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n", self.subject());
+        if let Some(updated_at) = self.updated_at {
+            out.push_str(&format!("\n_Updated {}_\n", format_time(updated_at)));
+        }
+        if self.sections.is_empty() {
+            out.push_str("\nNo stories for this period.\n");
+        }
+        for section in &self.sections {
+            out.push_str(&format!("\n## {}\n\n", escape_markdown(&section.symbol)));
+            for story in &section.stories {
+                out.push_str(&format!(
+                    "- [{}]({}) — {} ({})\n",
+                    escape_markdown(&story.title),
+                    escape_markdown_link(&story.link),
+                    escape_markdown(&story.sources.join(", ")),
+                    format_time(story.published_at)
+                ));
+                if !story.summary.is_empty() {
+                    out.push_str(&format!("  {}\n", escape_markdown(&story.summary)));
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = format!("<h1>{}</h1>\n", escape_html(&self.subject()));
+        if let Some(updated_at) = self.updated_at {
+            out.push_str(&format!("<p><em>Updated {}</em></p>\n", format_time(updated_at)));
+        }
+        if self.sections.is_empty() {
+            out.push_str("<p>No stories for this period.</p>\n");
+        }
+        for section in &self.sections {
+            out.push_str(&format!("<h2>{}</h2>\n<ul>\n", escape_html(&section.symbol)));
+            for story in &section.stories {
+                out.push_str(&format!(
+                    "<li><a href=\"{}\">{}</a> &mdash; {} ({})",
+                    escape_html(&story.link),
+                    escape_html(&story.title),
+                    escape_html(&story.sources.join(", ")),
+                    format_time(story.published_at)
+                ));
+                if !story.summary.is_empty() {
+                    out.push_str(&format!("<p>{}</p>", escape_html(&story.summary)));
+                }
+                out.push_str("</li>\n");
+            }
+            out.push_str("</ul>\n");
+        }
+        out
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}\n", self.subject());
+        if let Some(updated_at) = self.updated_at {
+            out.push_str(&format!("Updated {}\n", format_time(updated_at)));
+        }
+        if self.sections.is_empty() {
+            out.push_str("\nNo stories for this period.\n");
+        }
+        for section in &self.sections {
+            out.push_str(&format!("\n{}\n{}\n", section.symbol, "-".repeat(section.symbol.len())));
+            for story in &section.stories {
+                out.push_str(&format!(
+                    "* {}\n  {} ({})\n  {}\n",
+                    story.title,
+                    story.sources.join(", "),
+                    format_time(story.published_at),
+                    story.link
+                ));
+            }
+        }
+        out
+    }
+}
+
+// Longest sleep before the wall clock is looked at again, so a clock change can't delay a digest
+// by more than this.
+const MAX_SLEEP: Duration = Duration::from_secs(60 * 60);
+
+// Emails every watchlist's digest to `recipients` whenever one is due (see `Period::next_run`).
+// The last send is stored: a digest that fell due while the server was down goes out at startup,
+// and a restart doesn't move the schedule. Watchlists are read from the current config on each run,
+// so reloaded ones apply to the next digest.
+pub async fn run_schedule(
+    pool: SqlitePool,
+    archive: Archive,
+    mailer: Mailer,
+    config: watch::Receiver<Arc<Config>>,
+    recipients: Vec<String>,
+    period: Period,
+    mut shutdown: Shutdown,
+) {
+    let last_sent = match last_sent(&pool, period).await {
+        Ok(last_sent) => last_sent,
+        Err(e) => {
+            tracing::error!(period = period.name(), error = %e, "failed to read last digest run");
+            None
+        }
+    };
+    let mut due = period.next_run(last_sent.unwrap_or_else(|| Utc::now().timestamp()));
+    loop {
+        let wait = due - Utc::now().timestamp();
+        if wait > 0 {
+            tokio::select! {
+                _ = tokio::time::sleep(MAX_SLEEP.min(Duration::from_secs(wait as u64))) => {}
+                _ = shutdown.requested() => return,
+            }
+            continue;
+        }
+        let watchlists = config.borrow().digest.watchlists.clone();
+        send_digests(&archive, &mailer, &watchlists, &recipients, period).await;
+        let now = Utc::now().timestamp();
+        if let Err(e) = record_sent(&pool, period, now).await {
+            tracing::error!(period = period.name(), error = %e, "failed to record digest run");
+        }
+        due = period.next_run(now);
+    }
+}
+
+async fn last_sent(pool: &SqlitePool, period: Period) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar("SELECT sent_at FROM digest_runs WHERE period = ?")
+        .bind(period.name())
+        .fetch_optional(pool)
+        .await
+}
+
+async fn record_sent(pool: &SqlitePool, period: Period, sent_at: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO digest_runs (period, sent_at) VALUES (?, ?)
+         ON CONFLICT (period) DO UPDATE SET sent_at = excluded.sent_at",
+    )
+        .bind(period.name())
+        .bind(sent_at)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "digest", skip_all, fields(period = period.title()))]
+async fn send_digests(archive: &Archive, mailer: &Mailer, watchlists: &[Watchlist], recipients: &[String], period: Period) {
+    let now = Utc::now().timestamp();
+    let articles = match archive.since(now - period.seconds()).await {
+        Ok(articles) => articles,
+        Err(e) => {
+            tracing::error!(error = %e, "failed to read archive");
+            return;
+        }
+    };
+    for watchlist in watchlists {
+        let digest = build(watchlist, &articles, period, now, SUMMARY_CHARS);
+        for recipient in recipients {
+            match mailer
+                .send(recipient, &digest.subject(), digest.to_text(), digest.to_html())
+                .await
+            {
+                Ok(()) => tracing::info!(watchlist = %watchlist.name, recipient = %recipient, "digest sent"),
+                Err(e) => tracing::error!(watchlist = %watchlist.name, recipient = %recipient, error = %e, "failed to email digest"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::models::news::NewsArticle;
+    use crate::services::db;
+
+    // A plain SMTP server that accepts every message and returns the session as text.
+    async fn smtp_sink(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut session = String::new();
+        let mut data = false;
+        writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            session.push_str(&line);
+            session.push('\n');
+            let reply: &[u8] = if data {
+                if line != "." {
+                    continue;
+                }
+                data = false;
+                b"250 queued\r\n"
+            } else {
+                match line.split(' ').next().unwrap_or("").to_uppercase().as_str() {
+                    "EHLO" | "HELO" => b"250 sink\r\n",
+                    "DATA" => {
+                        data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT" => {
+                        writer.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 ok\r\n",
+                }
+            };
+            writer.write_all(reply).await.unwrap();
+            if reply.starts_with(b"250 queued") {
+                break;
+            }
+        }
+        session
+    }
+
+    #[test]
+    fn digests_are_due_at_wall_clock_boundaries() {
+        // Monday 2026-10-19 10:00 UTC.
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 10, 0, 0).unwrap().timestamp();
+        let tomorrow = Utc.with_ymd_and_hms(2026, 10, 20, 0, 0, 0).unwrap().timestamp();
+        let next_monday = Utc.with_ymd_and_hms(2026, 10, 26, 0, 0, 0).unwrap().timestamp();
+        assert_eq!(Period::Daily.next_run(now), tomorrow);
+        assert_eq!(Period::Daily.next_run(tomorrow), tomorrow + Period::Daily.seconds());
+        assert_eq!(Period::Weekly.next_run(now), next_monday);
+        assert_eq!(Period::Weekly.next_run(next_monday - 1), next_monday);
+    }
+
+    #[test]
+    fn markdown_digests_escape_titles_and_links() {
+        let watchlist = Watchlist {
+            name: "majors".to_string(),
+            symbols: vec!["BTC".to_string()],
+        };
+        let article = ArchivedArticle {
+            id: 1,
+            symbol: "BTC".to_string(),
+            published_at: 1_000,
+            story_id: None,
+            article: NewsArticle {
+                title: "*BTC* tops [$70k](http://spam.example)".to_string(),
+                source: "wire_one".to_string(),
+                date: String::new(),
+                summary: String::new(),
+                link: "https://example.com/a (b)".to_string(),
+                sentiment: None,
+                lang: None,
+                content: None,
+            },
+        };
+        let digest = build(&watchlist, &[article], Period::Daily, 2_000, SUMMARY_CHARS);
+        let markdown = digest.to_markdown();
+        assert!(markdown.contains(
+            "- [\\*BTC\\* tops \\[$70k\\]\\(http://spam.example\\)](https://example.com/a%20%28b%29) — wire\\_one"
+        ));
+    }
+
+    #[tokio::test]
+    async fn digests_are_emailed_over_smtp_and_the_run_is_stored() {
+        let path = env::temp_dir().join(format!("cna-test-{}.db", uuid::Uuid::new_v4().simple()));
+        let pool = db::connect(&format!("sqlite://{}", path.display())).await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = tokio::spawn(smtp_sink(listener));
+
+        let mailer = Mailer::new("127.0.0.1", port, false, None, "digest@example.com").unwrap();
+        let watchlists = vec![Watchlist {
+            name: "majors".to_string(),
+            symbols: vec!["BTC".to_string()],
+        }];
+        let recipients = vec!["ops@example.com".to_string()];
+        send_digests(&Archive::new(pool.clone()), &mailer, &watchlists, &recipients, Period::Daily).await;
+        let session = sink.await.unwrap();
+        assert!(session.contains("MAIL FROM:<digest@example.com>"), "{}", session);
+        assert!(session.contains("RCPT TO:<ops@example.com>"), "{}", session);
+        assert!(session.contains("Subject: Daily digest: majors"), "{}", session);
+
+        assert_eq!(last_sent(&pool, Period::Daily).await.unwrap(), None);
+        record_sent(&pool, Period::Daily, 1_792_368_000).await.unwrap();
+        record_sent(&pool, Period::Daily, 1_792_454_400).await.unwrap();
+        assert_eq!(last_sent(&pool, Period::Daily).await.unwrap(), Some(1_792_454_400));
+        assert_eq!(last_sent(&pool, Period::Weekly).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,59 @@
+// The last steps for fetched articles, shared by /news and the sources managed through the admin
+// API: summaries, sentiment and language are filled in, new articles are archived and then
+// matched against saved searches.
+
+use crate::config::Config;
+use crate::models::news::NewsArticle;
+use crate::services::archive::Archive;
+use crate::services::metrics::Metrics;
+use crate::services::searches::SavedSearches;
+use crate::services::{language, sentiment, summarize};
+
+// Summaries filled in from article bodies at ingest time are stored up to this length.
+const ARCHIVED_SUMMARY_CHARS: usize = 600;
+
+pub fn prepare(article: &mut NewsArticle) {
+    if article.summary.trim().is_empty() {
+        article.summary = summarize::summarize_article("", article.content.as_deref(), ARCHIVED_SUMMARY_CHARS);
+    }
+    let text = format!("{} {}", article.title, article.summary);
+    article.sentiment = Some(sentiment::score(&text));
+    article.lang = Some(language::detect(&text).to_string());
+}
+
+#[derive(Clone)]
+pub struct Ingest {
+    archive: Archive,
+    metrics: Metrics,
+    searches: SavedSearches,
+}
+
+impl Ingest {
+    pub fn new(archive: Archive, metrics: Metrics, searches: SavedSearches) -> Self {
+        Ingest {
+            archive,
+            metrics,
+            searches,
+        }
+    }
+
+    // Archives prepared articles under `symbol`, `origin` being the source they were fetched from.
+    // Returns how many were new; failures are logged and skipped.
+    pub async fn store(&self, origin: &str, symbol: &str, articles: &[NewsArticle], config: &Config) -> usize {
+        let mut archived = Vec::new();
+        for article in articles {
+            match self.archive.insert(origin, symbol, article).await {
+                Ok(true) => archived.push(article.clone()),
+                Ok(false) => {}
+                Err(e) => tracing::error!(link = %article.link, error = %e, "failed to archive article"),
+            }
+        }
+        self.metrics.ingested(origin, articles.len(), archived.len());
+        if !archived.is_empty() {
+            if let Err(e) = self.searches.ingest(symbol, &archived, &config.searches).await {
+                tracing::error!(symbol, error = %e, "failed to match saved searches");
+            }
+        }
+        archived.len()
+    }
+}
@@ -0,0 +1,151 @@
+// Offline language detection for article text, plus parsing of language filters.
+
+use crate::services::text;
+
+pub const UNKNOWN: &str = "und";
+
+pub const SUPPORTED: &[&str] = &["en", "es", "ru", "zh", "ko", "ja"];
+
+const ENGLISH: &[&str] = &[
+    "the", "and", "of", "to", "in", "is", "for", "on", "with", "that", "as", "by", "its", "after", "from", "are",
+    "has", "will", "this", "at",
+];
+
+const SPANISH: &[&str] = &[
+    "el", "la", "de", "que", "y", "en", "los", "las", "del", "por", "con", "una", "para", "es", "al", "se", "su",
+    "como", "más", "tras",
+];
+
+// Scripts decide everything except Latin text, where English and Spanish are told apart by function words.
+pub fn detect(text: &str) -> &'static str {
+    let (mut hangul, mut kana, mut han, mut cyrillic, mut latin) = (0, 0, 0, 0, 0);
+    for c in text.chars() {
+        match c as u32 {
+            0xAC00..=0xD7AF | 0x1100..=0x11FF | 0x3130..=0x318F => hangul += 1,
+            0x3040..=0x30FF => kana += 1,
+            0x4E00..=0x9FFF | 0x3400..=0x4DBF => han += 1,
+            0x0400..=0x04FF => cyrillic += 1,
+            _ if c.is_ascii_alphabetic() || matches!(c, 'á' | 'é' | 'í' | 'ó' | 'ú' | 'ñ' | 'ü') => latin += 1,
+            _ => {}
+        }
+    }
+
+    // Headlines in CJK languages often carry Latin tickers, so a handful of CJK characters is enough.
+    if hangul > 0 && hangul >= han {
+        return "ko";
+    }
+    if kana > 0 {
+        return "ja";
+    }
+    if han > 0 && han * 4 >= latin {
+        return "zh";
+    }
+    if cyrillic > latin {
+        return "ru";
+    }
+    if latin == 0 {
+        return UNKNOWN;
+    }
+
+    let words = text::words(text);
+    let english = words.iter().filter(|w| ENGLISH.contains(&w.as_str())).count();
+    let spanish = words.iter().filter(|w| SPANISH.contains(&w.as_str())).count()
+        + text.chars().filter(|c| matches!(c, 'ñ' | '¿' | '¡')).count();
+    if spanish > english {
+        "es"
+    } else if english > 0 || spanish == 0 {
+        "en"
+    } else {
+        UNKNOWN
+    }
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|lang| lang.trim().to_lowercase())
+        .filter(|lang| !lang.is_empty())
+        .collect()
+}
+
+// Primary subtags of the supported languages in an Accept-Language header, e.g.
+// `es-ES,es;q=0.9,en;q=0.8` -> [es, en]. Weights only order, they never exclude.
+fn accepted(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            let primary = tag.split('-').next()?.to_string();
+            let weight = parts
+                .find_map(|param| param.trim().strip_prefix("q=").and_then(|q| q.parse().ok()))
+                .unwrap_or(1.0);
+            Some((primary, weight))
+        })
+        .filter(|(lang, weight)| SUPPORTED.contains(&lang.as_str()) && *weight > 0.0)
+        .collect();
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut result: Vec<String> = Vec::new();
+    for (lang, _) in languages {
+        if !result.contains(&lang) {
+            result.push(lang);
+        }
+    }
+    result
+}
+
+// Languages to keep: an explicit `lang` parameter wins, then the signed-in user's stored languages,
+// then Accept-Language; `all` disables filtering. Articles archived before detection are `und`;
+// only an explicit `lang` hides them.
+pub fn filter(lang: Option<&str>, stored: Option<&str>, accept_language: Option<&str>) -> Option<Vec<String>> {
+    let (mut languages, explicit) = match (lang, stored) {
+        (Some(lang), _) => (parse_list(lang), true),
+        (None, Some(stored)) => (parse_list(stored), false),
+        (None, None) => (accepted(accept_language?), false),
+    };
+    if languages.is_empty() || languages.iter().any(|lang| lang == "all" || lang == "*") {
+        return None;
+    }
+    if !explicit && !languages.iter().any(|lang| lang == UNKNOWN) {
+        languages.push(UNKNOWN.to_string());
+    }
+    Some(languages)
+}
+
+// A user's language preference as stored: supported languages, lowercased and deduplicated, or
+// `None` for no preference.
+pub fn preference(languages: &[String]) -> Result<Option<String>, String> {
+    let mut result: Vec<String> = Vec::new();
+    for lang in languages {
+        let lang = lang.trim().to_lowercase();
+        if !SUPPORTED.contains(&lang.as_str()) {
+            return Err(format!("unsupported language {:?}; use one of {}", lang, SUPPORTED.join(", ")));
+        }
+        if !result.contains(&lang) {
+            result.push(lang);
+        }
+    }
+    Ok(Some(result.join(",")).filter(|languages| !languages.is_empty()))
+}
+
+pub fn matches(filter: &Option<Vec<String>>, lang: Option<&str>) -> bool {
+    let lang = lang.unwrap_or(UNKNOWN);
+    filter.as_ref().is_none_or(|languages| languages.iter().any(|l| l == lang))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_an_explicit_lang_hides_undetected_articles() {
+        let header = Some("es-ES,es;q=0.9,en;q=0.8");
+        assert_eq!(filter(None, None, header), Some(vec!["es".to_string(), "en".to_string(), "und".to_string()]));
+        assert_eq!(filter(None, Some("zh"), header), Some(vec!["zh".to_string(), "und".to_string()]));
+        assert_eq!(filter(Some("ko"), Some("zh"), header), Some(vec!["ko".to_string()]));
+        assert!(matches(&filter(None, None, header), None));
+        assert!(!matches(&filter(Some("en"), None, None), Some(UNKNOWN)));
+        assert_eq!(filter(Some("all"), Some("zh"), header), None);
+        assert_eq!(filter(None, None, None), None);
+    }
+}
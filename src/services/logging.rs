@@ -0,0 +1,100 @@
+// Structured logging through `tracing`, as human-readable text or one JSON object per line.
+
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogFormat, LoggingConfig};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// The configured level plus per-module overrides, as `EnvFilter` directives.
+fn directives(config: &LoggingConfig) -> String {
+    let mut directives = vec![config.level.clone()];
+    // sqlx logs every statement at info.
+    if !config.modules.contains_key("sqlx") {
+        directives.push("sqlx=warn".to_string());
+    }
+    directives.extend(
+        config
+            .modules
+            .iter()
+            .map(|(module, level)| format!("{}={}", module, level)),
+    );
+    directives.join(",")
+}
+
+// `RUST_LOG` wins when set; otherwise `directives`, whose levels were validated when the config
+// was loaded.
+fn filter(config: &LoggingConfig) -> EnvFilter {
+    if let Ok(filter) = EnvFilter::try_from_default_env() {
+        return filter;
+    }
+    EnvFilter::new(directives(config))
+}
+
+pub fn init(config: &LoggingConfig) {
+    let builder = tracing_subscriber::fmt().with_env_filter(filter(config));
+    match config.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).init(),
+    }
+}
+
+// Incoming request IDs are kept when they're short printable tokens, otherwise a new one is made.
+pub fn request_id(header: Option<&str>) -> String {
+    match header {
+        Some(id) if !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic()) => id.to_string(),
+        _ => uuid::Uuid::new_v4().to_string(),
+    }
+}
+
+// The path to log for a request. Feed tokens are secrets, so under `/feeds/` only the route is
+// logged; `decoded` is the percent-decoded path the router matches, so `/%66eeds/...` counts too.
+pub fn loggable_path<'a>(path: &'a str, decoded: &str) -> &'a str {
+    if decoded.starts_with("/feeds/") {
+        "/feeds/{token}"
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feed_tokens_are_left_out_of_logged_paths() {
+        assert_eq!(loggable_path("/feeds/0123abcd", "/feeds/0123abcd"), "/feeds/{token}");
+        assert_eq!(loggable_path("/%66eeds/0123abcd", "/feeds/0123abcd"), "/feeds/{token}");
+        assert_eq!(loggable_path("/me/feed", "/me/feed"), "/me/feed");
+        assert_eq!(loggable_path("/news/%41", "/news/A"), "/news/%41");
+    }
+
+    #[test]
+    fn module_levels_follow_the_default_level() {
+        let mut config = LoggingConfig::default();
+        assert_eq!(directives(&config), "info,sqlx=warn");
+
+        config.level = "debug".to_string();
+        config.modules.insert("crypto_news_aggregator::api".to_string(), "trace".to_string());
+        assert_eq!(directives(&config), "debug,sqlx=warn,crypto_news_aggregator::api=trace");
+
+        // A configured sqlx level replaces the quiet default.
+        config.modules.insert("sqlx".to_string(), "info".to_string());
+        assert_eq!(directives(&config), "debug,crypto_news_aggregator::api=trace,sqlx=info");
+        assert!(directives(&config).parse::<EnvFilter>().is_ok());
+    }
+
+    #[test]
+    fn request_ids_are_kept_when_they_are_short_printable_tokens() {
+        assert_eq!(request_id(Some("abc-123")), "abc-123");
+        let long = "a".repeat(128);
+        assert_eq!(request_id(Some(&long)), long);
+
+        for rejected in [None, Some(""), Some("has space"), Some("line\nbreak"), Some("é")] {
+            let generated = request_id(rejected);
+            assert!(uuid::Uuid::parse_str(&generated).is_ok(), "{:?} gave {}", rejected, generated);
+        }
+        assert_ne!(request_id(Some(&"a".repeat(129))), "a".repeat(129));
+        assert_ne!(request_id(None), request_id(None));
+    }
+}
@@ -0,0 +1,59 @@
+// Sends multipart (plain text + HTML) email over SMTP.
+
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::config::SmtpConfig;
+
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    // `tls` is off for local SMTP sinks that only speak plain SMTP.
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: bool,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, String> {
+        let mut builder = if tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host).map_err(|e| e.to_string())?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        };
+        builder = builder.port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        let from = from.parse().map_err(|e| format!("invalid sender {}: {}", from, e))?;
+        Ok(Mailer {
+            transport: builder.build(),
+            from,
+        })
+    }
+
+    pub fn from_config(config: &SmtpConfig) -> Result<Self, String> {
+        let credentials = match (&config.username, &config.password) {
+            (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+            _ => None,
+        };
+        Mailer::new(&config.host, config.port, config.tls, credentials, &config.from)
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, text: String, html: String) -> Result<(), String> {
+        let to: Mailbox = to.parse().map_err(|e| format!("invalid recipient {}: {}", to, e))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .multipart(MultiPart::alternative_plain_html(text, html))
+            .map_err(|e| e.to_string())?;
+        self.transport.send(message).await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+// Current market quotes for symbols, cached briefly to stay within CoinGecko's rate limits.
+
+use std::sync::Mutex;
+
+use crate::api::{fetch_coin_markets, FetchError, HttpClient};
+use crate::config::SourceConfig;
+use crate::models::market::MarketQuote;
+use crate::services::cache::Cache;
+
+pub const VS_CURRENCY: &str = "usd";
+
+fn cache_key(symbol: &str) -> String {
+    format!("market:{}", symbol)
+}
+
+// Symbols CoinGecko doesn't know are left out of the result.
+pub async fn quotes(
+    client: &HttpClient,
+    source: &SourceConfig,
+    cache: &Mutex<Cache>,
+    symbols: &[String],
+) -> Result<Vec<MarketQuote>, FetchError> {
+    let mut quotes = Vec::new();
+    let mut missing = Vec::new();
+    {
+        let mut cache = cache.lock().unwrap();
+        for symbol in symbols.iter().map(|symbol| symbol.to_uppercase()) {
+            match cache.get(&cache_key(&symbol)).and_then(|value| serde_json::from_str(value).ok()) {
+                Some(quote) => quotes.push(quote),
+                None if !missing.contains(&symbol) => missing.push(symbol),
+                None => {}
+            }
+        }
+    }
+    if missing.is_empty() {
+        return Ok(quotes);
+    }
+
+    // Several coins share a ticker; markets come back by market cap, so the first match wins.
+    let markets = fetch_coin_markets(client, source, &missing, VS_CURRENCY).await?;
+    let mut cache = cache.lock().unwrap();
+    cache.clear();
+    for symbol in &missing {
+        if let Some(market) = markets.iter().find(|market| market.symbol.eq_ignore_ascii_case(symbol)) {
+            let quote = MarketQuote::from_market(market);
+            if let Ok(value) = serde_json::to_string(&quote) {
+                cache.set(cache_key(symbol), value);
+            }
+            quotes.push(quote);
+        }
+    }
+    Ok(quotes)
+}
@@ -0,0 +1,232 @@
+// Prometheus metrics for HTTP requests, upstream fetches, the quote cache, ingestion and the
+// notification queue, rendered in the text exposition format by `GET /metrics`.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::services::cache::Cache;
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    upstream_requests: IntCounterVec,
+    upstream_duration: HistogramVec,
+    upstream_errors: IntCounterVec,
+    circuit_state: IntGaugeVec,
+    cache_hits: IntCounter,
+    cache_misses: IntCounter,
+    cache_evictions: IntCounter,
+    cache_entries: IntGauge,
+    articles_fetched: IntCounterVec,
+    articles_stored: IntCounterVec,
+    notifications_queued: IntGauge,
+}
+
+// Every metric name and label set is fixed, so registration can only fail on a programming error.
+fn register<T: prometheus::core::Collector + Clone + 'static>(registry: &Registry, metric: T) -> T {
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("metric names are unique");
+    metric
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            register(&registry, IntCounterVec::new(Opts::new(name, help), labels).unwrap())
+        };
+        let histogram = |name: &str, help: &str, labels: &[&str]| {
+            register(&registry, HistogramVec::new(HistogramOpts::new(name, help), labels).unwrap())
+        };
+        Metrics {
+            http_requests: counter(
+                "http_requests_total",
+                "HTTP requests served, by route pattern, method and status.",
+                &["route", "method", "status"],
+            ),
+            http_duration: histogram(
+                "http_request_duration_seconds",
+                "HTTP request latency by route pattern and method.",
+                &["route", "method"],
+            ),
+            upstream_requests: counter(
+                "upstream_requests_total",
+                "Requests to upstream sources by outcome: ok, not_modified, fresh (served within max-age) or error.",
+                &["source", "outcome"],
+            ),
+            upstream_duration: histogram(
+                "upstream_request_duration_seconds",
+                "Upstream request latency by source.",
+                &["source"],
+            ),
+            upstream_errors: counter(
+                "upstream_errors_total",
+                "Failed upstream requests by source and kind: http, status, decode or circuit_open.",
+                &["source", "kind"],
+            ),
+            circuit_state: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("upstream_circuit_state", "Circuit breaker state by source: 0 closed, 1 half-open, 2 open."),
+                    &["source"],
+                )
+                .unwrap(),
+            ),
+            cache_hits: register(
+                &registry,
+                IntCounter::new("cache_hits_total", "Quote cache lookups that found a live entry.").unwrap(),
+            ),
+            cache_misses: register(
+                &registry,
+                IntCounter::new("cache_misses_total", "Quote cache lookups that missed or found an expired entry.").unwrap(),
+            ),
+            cache_evictions: register(
+                &registry,
+                IntCounter::new("cache_evictions_total", "Expired quote cache entries removed.").unwrap(),
+            ),
+            cache_entries: register(
+                &registry,
+                IntGauge::new("cache_entries", "Entries currently held in the quote cache.").unwrap(),
+            ),
+            // By source rather than symbol: symbols come from requests, sources only from the
+            // config and admins.
+            articles_fetched: counter(
+                "ingest_articles_fetched_total",
+                "Articles received from sources, by source.",
+                &["source"],
+            ),
+            articles_stored: counter(
+                "ingest_articles_stored_total",
+                "Articles added to the archive, by source; fetched minus stored were duplicates.",
+                &["source"],
+            ),
+            notifications_queued: register(
+                &registry,
+                IntGauge::new("notifications_queued", "Alert and saved search notifications waiting to be sent.").unwrap(),
+            ),
+            registry,
+        }
+    }
+
+    pub fn observe_request(&self, route: &str, method: &str, status: u16, elapsed: Duration) {
+        self.http_requests
+            .with_label_values(&[route, method, &status.to_string()])
+            .inc();
+        self.http_duration
+            .with_label_values(&[route, method])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_upstream(&self, source: &str, outcome: &str, elapsed: Option<Duration>) {
+        self.upstream_requests.with_label_values(&[source, outcome]).inc();
+        if let Some(elapsed) = elapsed {
+            self.upstream_duration
+                .with_label_values(&[source])
+                .observe(elapsed.as_secs_f64());
+        }
+    }
+
+    pub fn upstream_error(&self, source: &str, kind: &str) {
+        self.upstream_errors.with_label_values(&[source, kind]).inc();
+    }
+
+    pub fn circuit_state(&self, source: &str, level: i64) {
+        self.circuit_state.with_label_values(&[source]).set(level);
+    }
+
+    pub fn ingested(&self, source: &str, fetched: usize, stored: usize) {
+        self.articles_fetched
+            .with_label_values(&[source])
+            .inc_by(fetched as u64);
+        self.articles_stored
+            .with_label_values(&[source])
+            .inc_by(stored as u64);
+    }
+
+    pub fn notifications_queued(&self, queued: i64) {
+        self.notifications_queued.set(queued);
+    }
+
+    // Cache counters live in `Cache` itself; they're copied over at scrape time.
+    fn sync_cache(&self, cache: &Mutex<Cache>) {
+        let stats = cache.lock().unwrap().stats();
+        self.cache_hits.inc_by(stats.hits.saturating_sub(self.cache_hits.get()));
+        self.cache_misses.inc_by(stats.misses.saturating_sub(self.cache_misses.get()));
+        self.cache_evictions
+            .inc_by(stats.evictions.saturating_sub(self.cache_evictions.get()));
+        self.cache_entries.set(stats.entries as i64);
+    }
+
+    pub fn render(&self, cache: &Mutex<Cache>) -> Result<String, prometheus::Error> {
+        self.sync_cache(cache);
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_every_metric_in_the_text_format() {
+        let metrics = Metrics::new();
+        metrics.observe_request("/news", "GET", 200, Duration::from_millis(20));
+        metrics.observe_request("/news", "GET", 200, Duration::from_millis(30));
+        metrics.observe_upstream("coingecko", "ok", Some(Duration::from_millis(100)));
+        metrics.observe_upstream("coingecko", "fresh", None);
+        metrics.upstream_error("coingecko", "decode");
+        metrics.circuit_state("coingecko", 2);
+        metrics.ingested("cryptqnews", 10, 4);
+        metrics.notifications_queued(3);
+
+        let cache = Mutex::new(Cache::new(Duration::from_secs(60)));
+        cache.lock().unwrap().set("BTC".to_string(), "{}".to_string());
+        cache.lock().unwrap().get("BTC");
+        cache.lock().unwrap().get("ETH");
+        let text = metrics.render(&cache).unwrap();
+
+        for line in [
+            "http_requests_total{method=\"GET\",route=\"/news\",status=\"200\"} 2",
+            "http_request_duration_seconds_count{method=\"GET\",route=\"/news\"} 2",
+            "upstream_requests_total{outcome=\"ok\",source=\"coingecko\"} 1",
+            "upstream_requests_total{outcome=\"fresh\",source=\"coingecko\"} 1",
+            "upstream_request_duration_seconds_count{source=\"coingecko\"} 1",
+            "upstream_errors_total{kind=\"decode\",source=\"coingecko\"} 1",
+            "upstream_circuit_state{source=\"coingecko\"} 2",
+            "ingest_articles_fetched_total{source=\"cryptqnews\"} 10",
+            "ingest_articles_stored_total{source=\"cryptqnews\"} 4",
+            "notifications_queued 3",
+            "cache_hits_total 1",
+            "cache_misses_total 1",
+            "cache_entries 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn cache_counters_follow_the_cache_without_double_counting() {
+        let metrics = Metrics::new();
+        let cache = Mutex::new(Cache::new(Duration::ZERO));
+        cache.lock().unwrap().set("BTC".to_string(), "{}".to_string());
+        cache.lock().unwrap().get("BTC");
+        metrics.render(&cache).unwrap();
+        cache.lock().unwrap().clear();
+        let text = metrics.render(&cache).unwrap();
+
+        // Each scrape adds only what changed since the last one.
+        assert!(text.lines().any(|l| l == "cache_misses_total 1"), "{}", text);
+        assert!(text.lines().any(|l| l == "cache_hits_total 0"), "{}", text);
+        assert!(text.lines().any(|l| l == "cache_evictions_total 1"), "{}", text);
+        assert!(text.lines().any(|l| l == "cache_entries 0"), "{}", text);
+    }
+}
@@ -0,0 +1,26 @@
+pub mod alerts;
+pub mod apikeys;
+pub mod archive;
+pub mod article_states;
+pub mod cache;
+pub mod db;
+pub mod digest;
+pub mod ingest;
+pub mod language;
+pub mod logging;
+pub mod mailer;
+pub mod market;
+pub mod metrics;
+pub mod notify;
+pub mod prices;
+pub mod reload;
+pub mod searches;
+pub mod sentiment;
+pub mod shutdown;
+pub mod sources;
+pub mod stories;
+pub mod summarize;
+pub mod text;
+pub mod trending;
+pub mod users;
+pub mod watchlists;
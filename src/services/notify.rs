@@ -0,0 +1,276 @@
+// Delivers notifications by email and/or webhook. They are queued in the database and sent by
+// `run_deliveries`, so a slow mail server or webhook doesn't hold up the price poll or ingestion
+// that raised them, and notifications still queued at shutdown are sent after the next start.
+// Webhooks go through the shared HTTP client and its timeouts.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use tokio::sync::Notify;
+
+use crate::api::HttpClient;
+use crate::services::mailer::Mailer;
+use crate::services::metrics::Metrics;
+use crate::services::shutdown::Shutdown;
+
+// Beyond this many waiting notifications new ones are dropped, e.g. while a mail server is down.
+const MAX_PENDING: i64 = 1000;
+
+// For all of one notification's emails and its webhook together.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+const BATCH: i64 = 50;
+
+// Before the queue is read again after the database failed to claim a notification.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+    // Posted to the webhook.
+    pub payload: serde_json::Value,
+    pub recipients: Vec<String>,
+    pub webhook_url: Option<String>,
+}
+
+impl Notification {
+    pub fn new<T: Serialize>(subject: String, text: String, html: String, payload: &T) -> Self {
+        Notification {
+            subject,
+            text,
+            html,
+            payload: serde_json::to_value(payload).unwrap_or_default(),
+            recipients: Vec::new(),
+            webhook_url: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Notifier {
+    pool: SqlitePool,
+    mailer: Option<Mailer>,
+    http: HttpClient,
+    metrics: Metrics,
+    queued: Arc<Notify>,
+}
+
+impl Notifier {
+    pub fn new(pool: SqlitePool, mailer: Option<Mailer>, http: HttpClient, metrics: Metrics) -> Self {
+        Notifier {
+            pool,
+            mailer,
+            http,
+            metrics,
+            queued: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn can_email(&self) -> bool {
+        self.mailer.is_some()
+    }
+
+    // Returns false when the queue is full and the notification was dropped.
+    pub async fn queue(&self, notification: &Notification) -> Result<bool, sqlx::Error> {
+        if self.pending().await? >= MAX_PENDING {
+            tracing::warn!(subject = %notification.subject, "notification queue is full, dropping notification");
+            return Ok(false);
+        }
+        sqlx::query(
+            "INSERT INTO notifications (subject, text, html, payload, recipients, webhook_url, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&notification.subject)
+        .bind(&notification.text)
+        .bind(&notification.html)
+        .bind(notification.payload.to_string())
+        .bind(serde_json::to_string(&notification.recipients).unwrap_or_default())
+        .bind(notification.webhook_url.as_deref())
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        self.pending().await?;
+        self.queued.notify_one();
+        Ok(true)
+    }
+
+    // Also updates the queue's gauge.
+    pub async fn pending(&self) -> Result<i64, sqlx::Error> {
+        let pending = sqlx::query_scalar("SELECT COUNT(*) FROM notifications")
+            .fetch_one(&self.pool)
+            .await?;
+        self.metrics.notifications_queued(pending);
+        Ok(pending)
+    }
+
+    async fn next(&self) -> Result<Vec<(i64, Notification)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, subject, text, html, payload, recipients, webhook_url FROM notifications ORDER BY id LIMIT ?",
+        )
+        .bind(BATCH)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let payload: String = row.get("payload");
+                let recipients: String = row.get("recipients");
+                let notification = Notification {
+                    subject: row.get("subject"),
+                    text: row.get("text"),
+                    html: row.get("html"),
+                    payload: serde_json::from_str(&payload).unwrap_or_default(),
+                    recipients: serde_json::from_str(&recipients).unwrap_or_default(),
+                    webhook_url: row.get("webhook_url"),
+                };
+                (row.get("id"), notification)
+            })
+            .collect())
+    }
+
+    // Deletes the row; false when it was already gone.
+    async fn claim(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM notifications WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        self.pending().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Tries every channel; returns the errors of those that failed. Recipients are skipped when no
+    // mailer is configured.
+    pub async fn send(&self, notification: &Notification) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(mailer) = &self.mailer {
+            for recipient in &notification.recipients {
+                let result = mailer
+                    .send(
+                        recipient,
+                        &notification.subject,
+                        notification.text.clone(),
+                        notification.html.clone(),
+                    )
+                    .await;
+                if let Err(e) = result {
+                    errors.push(format!("email to {}: {}", recipient, e));
+                }
+            }
+        }
+        if let Some(url) = &notification.webhook_url {
+            if let Err(e) = self.http.post_json(url, &notification.payload).await {
+                errors.push(format!("webhook {}: {}", url, e));
+            }
+        }
+        errors
+    }
+
+    // Each notification is tried once, and given up after `DELIVERY_TIMEOUT`. It is claimed from the
+    // queue before it is sent, so it can't go out twice when the database fails afterwards.
+    async fn deliver(&self, id: i64, notification: &Notification) -> Result<(), sqlx::Error> {
+        if !self.claim(id).await? {
+            return Ok(());
+        }
+        match tokio::time::timeout(DELIVERY_TIMEOUT, self.send(notification)).await {
+            Ok(errors) => {
+                for error in errors {
+                    tracing::error!(subject = %notification.subject, error = %error, "failed to deliver notification");
+                }
+            }
+            Err(_) => tracing::error!(subject = %notification.subject, "notification delivery timed out"),
+        }
+        Ok(())
+    }
+}
+
+// Sends queued notifications, oldest first, until shutdown. One being sent then is finished; the
+// rest stay queued.
+pub async fn run_deliveries(notifier: Notifier, mut shutdown: Shutdown) {
+    if let Err(e) = notifier.pending().await {
+        tracing::error!(error = %e, "failed to count queued notifications");
+    }
+    loop {
+        let batch = match notifier.next().await {
+            Ok(batch) => batch,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to load queued notifications");
+                Vec::new()
+            }
+        };
+        if batch.is_empty() {
+            tokio::select! {
+                _ = notifier.queued.notified() => continue,
+                _ = shutdown.requested() => return,
+            }
+        }
+        for (id, notification) in &batch {
+            if let Err(e) = notifier.deliver(*id, notification).await {
+                // Unsent and still queued; wait before trying the queue again.
+                tracing::error!(error = %e, "failed to claim queued notification");
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_DELAY) => break,
+                    _ = shutdown.requested() => return,
+                }
+            }
+            if shutdown.is_requested() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::services::db;
+
+    #[actix_web::test]
+    async fn notifications_are_claimed_before_they_are_sent() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let webhook_url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let posts = Arc::new(AtomicUsize::new(0));
+        let counted = posts.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request).await;
+                counted.fetch_add(1, Ordering::SeqCst);
+                let _ = stream
+                    .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                    .await;
+            }
+        });
+
+        let config = Config::parse("", std::iter::empty()).unwrap();
+        let path = env::temp_dir().join(format!("cna-test-{}.db", uuid::Uuid::new_v4().simple()));
+        let pool = db::connect(&format!("sqlite://{}", path.display())).await.unwrap();
+        let metrics = Metrics::new();
+        let notifier = Notifier::new(pool, None, HttpClient::new(&config.http, metrics.clone()).unwrap(), metrics);
+        let mut notification = Notification::new("BTC alert".to_string(), String::new(), String::new(), &"moved");
+        notification.webhook_url = Some(webhook_url);
+        assert!(notifier.queue(&notification).await.unwrap());
+
+        let batch = notifier.next().await.unwrap();
+        assert_eq!(batch.len(), 1);
+        let (id, queued) = &batch[0];
+        notifier.deliver(*id, queued).await.unwrap();
+        // A second delivery of the same row, e.g. from a stale batch, finds it claimed.
+        notifier.deliver(*id, queued).await.unwrap();
+
+        assert_eq!(posts.load(Ordering::SeqCst), 1);
+        assert_eq!(notifier.pending().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,362 @@
+// Stored market price snapshots, and annotation of archived articles with the price of their
+// symbol at publication and at fixed horizons afterwards.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::api::{fetch_coin_markets, HttpClient};
+use crate::config::{Config, SourceConfig};
+use crate::services::alerts::{self, Alerts};
+use crate::services::archive::{self, Archive, ArchivedArticle};
+use crate::services::market::VS_CURRENCY;
+use crate::services::shutdown::Shutdown;
+
+// Seconds after publication at which price moves are recorded; 0 is the publication price.
+pub const HORIZONS: &[i64] = &[0, 15 * 60, 60 * 60, 4 * 60 * 60, 24 * 60 * 60];
+
+// Symbols with news in this period get their prices polled.
+const TRACKED_PERIOD: i64 = 7 * 24 * 60 * 60;
+
+// Polls that may fail in a row before an article's move goes unrecorded for now.
+const MISSED_POLLS: i64 = 2;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PriceMove {
+    pub horizon: i64,
+    pub price: f64,
+    // Percent change from the publication price.
+    pub change: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ImpactArticle {
+    #[serde(flatten)]
+    pub archived: ArchivedArticle,
+    pub moves: Vec<PriceMove>,
+}
+
+// Parses durations like `15m`, `1h` or `7d` into seconds.
+pub fn parse_duration(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit())?);
+    let number: i64 = number.parse().ok()?;
+    match unit {
+        "m" | "min" => Some(number * 60),
+        "h" => Some(number * 60 * 60),
+        "d" => Some(number * 24 * 60 * 60),
+        _ => None,
+    }
+}
+
+pub fn parse_horizon(value: &str) -> Option<i64> {
+    parse_duration(value).filter(|seconds| HORIZONS.contains(seconds))
+}
+
+#[derive(Clone)]
+pub struct Prices {
+    pool: SqlitePool,
+}
+
+impl Prices {
+    pub fn new(pool: SqlitePool) -> Self {
+        Prices { pool }
+    }
+
+    pub async fn insert_snapshot(&self, symbol: &str, timestamp: i64, price: f64) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT OR REPLACE INTO price_snapshots (symbol, timestamp, price) VALUES (?, ?, ?)")
+            .bind(symbol.to_uppercase())
+            .bind(timestamp)
+            .bind(price)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // The snapshot closest to `timestamp`, if one lies within `tolerance` seconds of it.
+    pub async fn price_near(&self, symbol: &str, timestamp: i64, tolerance: i64) -> Result<Option<f64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT price FROM price_snapshots
+             WHERE symbol = ?1 AND timestamp BETWEEN ?2 - ?3 AND ?2 + ?3
+             ORDER BY ABS(timestamp - ?2) LIMIT 1",
+        )
+        .bind(symbol.to_uppercase())
+        .bind(timestamp)
+        .bind(tolerance)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    // Articles about `symbol` published since `since`, largest absolute move at `horizon` first,
+    // each with all of its moves.
+    pub async fn highest_impact(
+        &self,
+        symbol: &str,
+        since: i64,
+        horizon: i64,
+        languages: Option<&[String]>,
+        limit: i64,
+    ) -> Result<Vec<ImpactArticle>, sqlx::Error> {
+        let rows = sqlx::query(
+            "WITH top AS (
+                 SELECT a.id, ABS(m.price - base.price) / base.price AS impact FROM articles a
+                 JOIN price_moves base ON base.article_id = a.id AND base.horizon = 0
+                 JOIN price_moves m ON m.article_id = a.id AND m.horizon = ?1
+                 WHERE a.symbol = ?2 AND a.published_at >= ?3 AND base.price IS NOT NULL AND m.price IS NOT NULL
+                   AND (?5 IS NULL OR instr(?5, ',' || a.lang || ',') > 0)
+                 ORDER BY impact DESC LIMIT ?4
+             )
+             SELECT a.id, a.symbol, a.title, a.source, a.date, a.summary, a.link, a.published_at, a.sentiment,
+                    a.story_id, a.lang, m.horizon, m.price, (m.price - base.price) / base.price * 100 AS change
+             FROM top JOIN articles a ON a.id = top.id
+             JOIN price_moves base ON base.article_id = a.id AND base.horizon = 0
+             JOIN price_moves m ON m.article_id = a.id AND m.price IS NOT NULL
+             ORDER BY top.impact DESC, a.id, m.horizon",
+        )
+        .bind(horizon)
+        .bind(symbol.to_uppercase())
+        .bind(since)
+        .bind(limit)
+        .bind(languages.map(|languages| format!(",{},", languages.join(","))))
+        .fetch_all(&self.pool)
+        .await?;
+
+        // One row per move, grouped by article in impact order.
+        let mut articles: Vec<ImpactArticle> = Vec::new();
+        for row in &rows {
+            let id: i64 = row.get("id");
+            if articles.last().is_none_or(|last| last.archived.id != id) {
+                articles.push(ImpactArticle {
+                    archived: archive::from_row(row),
+                    moves: Vec::new(),
+                });
+            }
+            if let Some(last) = articles.last_mut() {
+                last.moves.push(move_from_row(row));
+            }
+        }
+        Ok(articles)
+    }
+
+    // Records the moves that are now due, each from the snapshot nearest its time. Without a
+    // snapshot near enough nothing is recorded, and the article is looked at again next time, e.g.
+    // after the poll interval was raised.
+    pub async fn annotate(&self, config: &Config) -> Result<(), sqlx::Error> {
+        let now = Utc::now().timestamp();
+        let tolerance = snapshot_tolerance(config, MISSED_POLLS);
+        for &horizon in HORIZONS {
+            sqlx::query(
+                "INSERT OR REPLACE INTO price_moves (article_id, horizon, price)
+                 SELECT id, ?2, price FROM (
+                     SELECT a.id, s.price, ROW_NUMBER() OVER (
+                         PARTITION BY a.id ORDER BY ABS(s.timestamp - (a.published_at + ?2))
+                     ) AS nearest
+                     FROM articles a
+                     JOIN price_snapshots s ON s.symbol = upper(a.symbol)
+                       AND s.timestamp BETWEEN a.published_at + ?2 - ?4 AND a.published_at + ?2 + ?4
+                     WHERE a.published_at >= ?1 AND a.published_at + ?2 <= ?3 - ?4
+                       AND NOT EXISTS (SELECT 1 FROM price_moves m WHERE m.article_id = a.id AND m.horizon = ?2)
+                 )
+                 WHERE nearest = 1",
+            )
+            .bind(now - TRACKED_PERIOD)
+            .bind(horizon)
+            .bind(now)
+            .bind(tolerance)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+// How far from the wanted time a snapshot may be and still be used: snapshots are
+// `sources.coingecko.interval_secs` apart, and up to `missed_polls` in a row may have failed.
+// Without the source there are no snapshots at all.
+pub fn snapshot_tolerance(config: &Config, missed_polls: i64) -> i64 {
+    config
+        .sources
+        .get("coingecko")
+        .map_or(0, |source| source.interval_secs as i64 * (missed_polls + 1))
+}
+
+fn move_from_row(row: &SqliteRow) -> PriceMove {
+    PriceMove {
+        horizon: row.get("horizon"),
+        price: row.get("price"),
+        change: row.get("change"),
+    }
+}
+
+// Snapshots every symbol with recent news plus `extra` symbols (those with alert rules).
+pub async fn poll(
+    client: &HttpClient,
+    source: &SourceConfig,
+    archive: &Archive,
+    prices: &Prices,
+    extra: &[String],
+) -> Result<(), String> {
+    let now = Utc::now().timestamp();
+    let mut symbols = archive
+        .symbols_since(now - TRACKED_PERIOD)
+        .await
+        .map_err(|e| e.to_string())?;
+    for symbol in extra {
+        if !symbols.contains(symbol) {
+            symbols.push(symbol.clone());
+        }
+    }
+    if symbols.is_empty() {
+        return Ok(());
+    }
+    let markets = fetch_coin_markets(client, source, &symbols, VS_CURRENCY)
+        .await
+        .map_err(|e| e.to_string())?;
+    for symbol in &symbols {
+        let price = markets
+            .iter()
+            .find(|market| market.symbol.eq_ignore_ascii_case(symbol))
+            .and_then(|market| market.current_price);
+        if let Some(price) = price {
+            prices
+                .insert_snapshot(symbol, now, price)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "prices", skip_all)]
+async fn run_cycle(
+    client: &HttpClient,
+    source: &SourceConfig,
+    config: &Config,
+    archive: &Archive,
+    prices: &Prices,
+    alerts: &Alerts,
+) {
+    if let Err(e) = poll(client, source, archive, prices, &alerts::symbols(&config.alert_rules())).await {
+        tracing::error!(error = %e, "failed to poll market data");
+        return;
+    }
+    if let Err(e) = alerts.evaluate(archive, prices, config).await {
+        tracing::error!(error = %e, "failed to evaluate alert rules");
+    }
+    if let Err(e) = prices.annotate(config).await {
+        tracing::error!(error = %e, "failed to annotate articles");
+    }
+}
+
+// Polls every `sources.coingecko.interval_secs`. The config is re-read after each reload: a changed
+// interval is counted from the last poll, and polling pauses while the source is disabled.
+pub async fn run_schedule(
+    client: HttpClient,
+    mut config: watch::Receiver<Arc<Config>>,
+    archive: Archive,
+    prices: Prices,
+    alerts: Alerts,
+    mut shutdown: Shutdown,
+) {
+    let mut last_run: Option<Instant> = None;
+    loop {
+        let current = config.borrow().clone();
+        let source = match current.source("coingecko") {
+            Some(source) => source.clone(),
+            None => {
+                tokio::select! {
+                    changed = config.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                    }
+                    _ = shutdown.requested() => return,
+                }
+                continue;
+            }
+        };
+        let next = match last_run {
+            Some(last_run) => last_run + Duration::from_secs(source.interval_secs),
+            None => Instant::now(),
+        };
+        tokio::select! {
+            _ = tokio::time::sleep_until(next) => {
+                last_run = Some(Instant::now());
+                run_cycle(&client, &source, &current, &archive, &prices, &alerts).await;
+            }
+            changed = config.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            _ = shutdown.requested() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::models::news::NewsArticle;
+
+    async fn archive_at(state: &crate::AppState, symbol: &str, link: &str, published_at: i64) -> i64 {
+        let date = Utc.timestamp_opt(published_at, 0).unwrap().to_rfc3339();
+        let article = NewsArticle::new(
+            format!("{} story", symbol),
+            "wire".to_string(),
+            date,
+            String::new(),
+            link.to_string(),
+        );
+        state.archive.insert("wire", symbol, &article).await.unwrap();
+        state
+            .archive
+            .since(published_at)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|archived| archived.article.link == link)
+            .unwrap()
+            .id
+    }
+
+    #[actix_web::test]
+    async fn moves_come_from_the_nearest_snapshot_and_rank_by_impact() {
+        let config = Config::parse("", std::iter::empty()).unwrap();
+        let state = crate::test_state(config.clone()).await;
+        let prices = &state.prices;
+        let now = Utc::now().timestamp();
+
+        let (a, b) = (now - 2 * 60 * 60, now - 4 * 60 * 60);
+        let first = archive_at(&state, "BTC", "https://example.com/a", a).await;
+        let second = archive_at(&state, "BTC", "https://example.com/b", b).await;
+        archive_at(&state, "ETH", "https://example.com/c", a).await;
+        for (timestamp, price) in [(a - 600, 999.0), (a + 60, 100.0), (a + 3600, 110.0), (b, 100.0), (b + 3600, 95.0)] {
+            prices.insert_snapshot("btc", timestamp, price).await.unwrap();
+        }
+
+        prices.annotate(&config).await.unwrap();
+        // A second pass has nothing left to record and changes nothing.
+        prices.annotate(&config).await.unwrap();
+
+        let impact = prices.highest_impact("BTC", now - 24 * 60 * 60, 3600, None, 10).await.unwrap();
+        let ids: Vec<i64> = impact.iter().map(|article| article.archived.id).collect();
+        assert_eq!(ids, vec![first, second]);
+        let moves: Vec<(i64, f64)> = impact[0].moves.iter().map(|m| (m.horizon, m.price)).collect();
+        assert_eq!(moves, vec![(0, 100.0), (15 * 60, 100.0), (60 * 60, 110.0)]);
+        assert!((impact[0].moves[2].change - 10.0).abs() < 1e-9);
+        assert!((impact[1].moves[2].change + 5.0).abs() < 1e-9);
+
+        // No snapshots, so nothing recorded yet.
+        assert!(prices.highest_impact("ETH", now - 24 * 60 * 60, 0, None, 10).await.unwrap().is_empty());
+        assert_eq!(prices.highest_impact("BTC", now - 24 * 60 * 60, 3600, None, 1).await.unwrap().len(), 1);
+    }
+}
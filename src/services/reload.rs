@@ -0,0 +1,94 @@
+// Reloads the config file when it changes on disk or on SIGHUP. A config that fails to load or
+// validate is rejected and the previous one stays active.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ::notify::{RecursiveMode, Watcher};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch};
+
+use crate::config::Config;
+
+// Editors often write a file in several steps; wait for them to settle before reading it.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[tracing::instrument(name = "reload", skip_all)]
+async fn reload(path: &Path, sender: &watch::Sender<Arc<Config>>) {
+    let file = path.to_path_buf();
+    let loaded = tokio::task::spawn_blocking(move || Config::load(&file).map_err(|e| e.to_string()))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    let config = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!(error = %e, "rejected config, keeping the previous one");
+            return;
+        }
+    };
+    let previous = sender.borrow().clone();
+    let changes = config.diff(&previous);
+    if changes.is_empty() {
+        return;
+    }
+    for change in &changes {
+        tracing::info!(change = %change, "config changed");
+    }
+    if config.needs_restart(&previous) {
+        tracing::warn!("server, logging, http, storage, cache, smtp and digest recipient changes apply after a restart");
+    }
+    sender.send_replace(Arc::new(config));
+}
+
+// Watches the file's directory rather than the file, so replacing it (as most editors do) is noticed.
+fn watch_file(path: &Path, events: mpsc::UnboundedSender<()>) -> ::notify::Result<impl Watcher> {
+    let name = path.file_name().map(|name| name.to_os_string());
+    let mut watcher = ::notify::recommended_watcher(move |event: ::notify::Result<::notify::Event>| {
+        if let Ok(event) = event {
+            if event.paths.iter().any(|changed| changed.file_name() == name.as_deref()) {
+                let _ = events.send(());
+            }
+        }
+    })?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+pub async fn run(path: PathBuf, sender: watch::Sender<Arc<Config>>) {
+    let (events, mut changes) = mpsc::unbounded_channel();
+    // Kept alive for as long as the loop runs.
+    let _watcher = match watch_file(&path, events) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "cannot watch config file, reloading on SIGHUP only");
+            None
+        }
+    };
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::error!(error = %e, "cannot listen for SIGHUP");
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            Some(()) = changes.recv() => {
+                tokio::time::sleep(DEBOUNCE).await;
+                while changes.try_recv().is_ok() {}
+                tracing::info!(path = %path.display(), "config file changed, reloading");
+            }
+            Some(()) = hangup.recv() => {
+                tracing::info!(path = %path.display(), "SIGHUP received, reloading config");
+            }
+            else => return,
+        }
+        reload(&path, &sender).await;
+    }
+}
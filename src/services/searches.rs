@@ -0,0 +1,549 @@
+// Users' saved searches: a full-text query plus feed filters. Articles are matched against every
+// saved search as they are archived. Matches stay unread until the user reads them, and can be
+// emailed to the user or posted to a webhook.
+//
+// Ingestion doesn't scan every search per article. Each search is indexed under one word of its
+// query, the longest, and an article is only checked against the searches indexed under words
+// it contains.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+use crate::config::SearchesConfig;
+use crate::models::news::NewsArticle;
+use crate::services::notify::{Notification, Notifier};
+use crate::services::sentiment;
+use crate::services::text::{self, escape_html};
+
+const MAX_NAME_CHARS: usize = 64;
+const MAX_QUERY_CHARS: usize = 200;
+const MAX_ENTRIES: usize = 100;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SavedSearch {
+    #[serde(skip)]
+    pub id: i64,
+    pub name: String,
+    pub query: String,
+    pub symbols: Vec<String>,
+    pub sources: Vec<String>,
+    pub languages: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sentiment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_sentiment: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_sentiment: Option<f64>,
+    pub email: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    pub updated_at: i64,
+    // Matched articles the user hasn't read.
+    pub unread: i64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SearchUpdate {
+    pub query: String,
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    #[serde(default)]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    pub sentiment: Option<String>,
+    pub min_sentiment: Option<f64>,
+    pub max_sentiment: Option<f64>,
+    // Email matches to the account's address.
+    #[serde(default)]
+    pub email: bool,
+    pub webhook_url: Option<String>,
+}
+
+// Every word and quoted phrase must appear in the title or summary, e.g. `etf "spot bitcoin"`.
+// Words are compared like `text::words` splits them: lower case, alphanumeric runs.
+#[derive(Debug, Clone, PartialEq)]
+struct Query {
+    phrases: Vec<Vec<String>>,
+}
+
+impl Query {
+    fn parse(query: &str) -> Result<Query, String> {
+        let mut phrases: Vec<Vec<String>> = Vec::new();
+        // Quoted parts are the odd ones; an unbalanced quote runs to the end.
+        for (index, part) in query.split('"').enumerate() {
+            let words = text::words(part);
+            let parts = if index % 2 == 1 {
+                vec![words]
+            } else {
+                words.into_iter().map(|word| vec![word]).collect()
+            };
+            for phrase in parts {
+                if !phrase.is_empty() && !phrases.contains(&phrase) {
+                    phrases.push(phrase);
+                }
+            }
+        }
+        if phrases.is_empty() {
+            return Err("query needs at least one word".to_string());
+        }
+        Ok(Query { phrases })
+    }
+
+    // The longest word, which is likely to be the rarest.
+    fn key(&self) -> &str {
+        self.phrases
+            .iter()
+            .flatten()
+            .max_by_key(|word| word.chars().count())
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    fn matches(&self, words: &[String], present: &HashSet<&str>) -> bool {
+        self.phrases.iter().all(|phrase| match phrase.as_slice() {
+            [word] => present.contains(word.as_str()),
+            phrase => words.windows(phrase.len()).any(|window| window == phrase),
+        })
+    }
+}
+
+fn clean(items: &[String], upper: bool) -> Result<Vec<String>, String> {
+    let mut clean: Vec<String> = Vec::new();
+    for item in items {
+        let item = item.trim();
+        if item.is_empty() || item.contains(',') {
+            return Err(format!("invalid entry {:?}", item));
+        }
+        let item = if upper { item.to_uppercase() } else { item.to_lowercase() };
+        if !clean.contains(&item) {
+            clean.push(item);
+        }
+    }
+    if clean.len() > MAX_ENTRIES {
+        return Err(format!("at most {} entries per filter", MAX_ENTRIES));
+    }
+    Ok(clean)
+}
+
+fn split(list: String) -> Vec<String> {
+    list.split(',').filter(|item| !item.is_empty()).map(str::to_string).collect()
+}
+
+// A saved search as ingestion sees it.
+#[derive(Debug, Clone)]
+struct Compiled {
+    id: i64,
+    name: String,
+    query_text: String,
+    query: Query,
+    symbols: Vec<String>,
+    sources: Vec<String>,
+    languages: Vec<String>,
+    sentiment: Option<String>,
+    min_sentiment: Option<f64>,
+    max_sentiment: Option<f64>,
+    // The account's address, when the user asked for email.
+    email_to: Option<String>,
+    webhook_url: Option<String>,
+}
+
+impl Compiled {
+    fn matches(&self, symbol: &str, article: &NewsArticle, words: &[String], present: &HashSet<&str>) -> bool {
+        let score = article.sentiment.unwrap_or(0.0);
+        (self.symbols.is_empty() || self.symbols.iter().any(|s| s.eq_ignore_ascii_case(symbol)))
+            && (self.sources.is_empty() || self.sources.contains(&article.source.to_lowercase()))
+            && (self.languages.is_empty() || article.lang.as_ref().is_some_and(|lang| self.languages.contains(lang)))
+            && self.sentiment.as_deref().is_none_or(|label| sentiment::label(score) == label)
+            && self.min_sentiment.is_none_or(|min| score >= min)
+            && self.max_sentiment.is_none_or(|max| score <= max)
+            && self.query.matches(words, present)
+    }
+}
+
+#[derive(Default)]
+struct Index {
+    searches: Vec<Compiled>,
+    by_key: HashMap<String, Vec<usize>>,
+}
+
+impl Index {
+    fn new(searches: Vec<Compiled>) -> Self {
+        let mut by_key: HashMap<String, Vec<usize>> = HashMap::new();
+        for (position, search) in searches.iter().enumerate() {
+            by_key.entry(search.query.key().to_string()).or_default().push(position);
+        }
+        Index { searches, by_key }
+    }
+
+    fn matching(&self, symbol: &str, article: &NewsArticle) -> Vec<&Compiled> {
+        let words = text::words(&format!("{} {}", article.title, article.summary));
+        let present: HashSet<&str> = words.iter().map(String::as_str).collect();
+        present
+            .iter()
+            .filter_map(|word| self.by_key.get(*word))
+            .flatten()
+            .map(|&position| &self.searches[position])
+            .filter(|search| search.matches(symbol, article, &words, &present))
+            .collect()
+    }
+}
+
+// New matches for one saved search, as sent to its webhook.
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchMatches {
+    pub search: String,
+    pub query: String,
+    pub articles: Vec<NewsArticle>,
+}
+
+impl SearchMatches {
+    fn subject(&self) -> String {
+        format!("{} new articles for \"{}\"", self.articles.len(), self.search)
+    }
+
+    fn to_text(&self) -> String {
+        let mut out = format!("{}\nQuery: {}\n\n", self.subject(), self.query);
+        for article in &self.articles {
+            out.push_str(&format!("* {} ({})\n  {}\n", article.title, article.source, article.link));
+        }
+        out
+    }
+
+    fn to_html(&self) -> String {
+        let mut out = format!(
+            "<h1>{}</h1>\n<p>Query: {}</p>\n<ul>\n",
+            escape_html(&self.subject()),
+            escape_html(&self.query)
+        );
+        for article in &self.articles {
+            out.push_str(&format!(
+                "<li><a href=\"{}\">{}</a> ({})</li>\n",
+                escape_html(&article.link),
+                escape_html(&article.title),
+                escape_html(&article.source)
+            ));
+        }
+        out.push_str("</ul>\n");
+        out
+    }
+}
+
+#[derive(Clone)]
+pub struct SavedSearches {
+    pool: SqlitePool,
+    notifier: Notifier,
+    index: Arc<RwLock<Index>>,
+}
+
+impl SavedSearches {
+    // Deliveries go through the notification queue, so ingestion doesn't wait on mail servers and
+    // webhooks. Call `reload` before ingesting.
+    pub fn new(pool: SqlitePool, notifier: Notifier) -> Self {
+        SavedSearches {
+            pool,
+            notifier,
+            index: Arc::new(RwLock::new(Index::default())),
+        }
+    }
+
+    // Rebuilds the ingestion index from the database.
+    pub async fn reload(&self) -> Result<(), sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT s.id, s.name, s.query, s.symbols, s.sources, s.languages, s.sentiment, s.min_sentiment,
+                    s.max_sentiment, s.email, s.webhook_url, u.email AS address
+             FROM saved_searches s JOIN users u ON u.id = s.user_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut searches = Vec::new();
+        for row in &rows {
+            let query_text: String = row.get("query");
+            let query = match Query::parse(&query_text) {
+                Ok(query) => query,
+                Err(e) => {
+                    tracing::warn!(search = row.get::<i64, _>("id"), error = %e, "skipping saved search");
+                    continue;
+                }
+            };
+            searches.push(Compiled {
+                id: row.get("id"),
+                name: row.get("name"),
+                query_text,
+                query,
+                symbols: split(row.get("symbols")),
+                sources: split(row.get("sources")),
+                languages: split(row.get("languages")),
+                sentiment: row.get("sentiment"),
+                min_sentiment: row.get("min_sentiment"),
+                max_sentiment: row.get("max_sentiment"),
+                email_to: row.get::<bool, _>("email").then(|| row.get("address")),
+                webhook_url: row.get("webhook_url"),
+            });
+        }
+        tracing::debug!(searches = searches.len(), "indexed saved searches");
+        *self.index.write().unwrap() = Index::new(searches);
+        Ok(())
+    }
+
+    pub async fn list(&self, user_id: i64) -> Result<Vec<SavedSearch>, sqlx::Error> {
+        let rows = sqlx::query(&format!("{} WHERE s.user_id = ? ORDER BY s.name", SEARCH_QUERY))
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    pub async fn get(&self, user_id: i64, name: &str) -> Result<Option<SavedSearch>, sqlx::Error> {
+        let row = sqlx::query(&format!("{} WHERE s.user_id = ? AND s.name = ?", SEARCH_QUERY))
+            .bind(user_id)
+            .bind(name.trim())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(from_row))
+    }
+
+    // Creates the search or replaces it. The outer error is a validation message. Earlier matches
+    // are kept when a search is changed.
+    pub async fn put(
+        &self,
+        user_id: i64,
+        name: &str,
+        update: &SearchUpdate,
+        config: &SearchesConfig,
+    ) -> Result<Result<SavedSearch, String>, sqlx::Error> {
+        let name = name.trim();
+        if let Err(message) = self.validate(name, update, config) {
+            return Ok(Err(message));
+        }
+        let (symbols, sources, languages) = match (
+            clean(&update.symbols, true),
+            clean(&update.sources, false),
+            clean(&update.languages, false),
+        ) {
+            (Ok(symbols), Ok(sources), Ok(languages)) => (symbols, sources, languages),
+            (Err(message), _, _) | (_, Err(message), _) | (_, _, Err(message)) => return Ok(Err(message)),
+        };
+        if self.get(user_id, name).await?.is_none() {
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM saved_searches WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_one(&self.pool)
+                .await?;
+            if count as usize >= config.max_per_user {
+                return Ok(Err(format!("an account can save at most {} searches", config.max_per_user)));
+            }
+        }
+        sqlx::query(
+            "INSERT INTO saved_searches
+                (user_id, name, query, symbols, sources, languages, sentiment, min_sentiment, max_sentiment,
+                 email, webhook_url, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT (user_id, name) DO UPDATE
+             SET query = excluded.query, symbols = excluded.symbols, sources = excluded.sources,
+                 languages = excluded.languages, sentiment = excluded.sentiment,
+                 min_sentiment = excluded.min_sentiment, max_sentiment = excluded.max_sentiment,
+                 email = excluded.email, webhook_url = excluded.webhook_url, updated_at = excluded.updated_at",
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(update.query.trim())
+        .bind(symbols.join(","))
+        .bind(sources.join(","))
+        .bind(languages.join(","))
+        .bind(update.sentiment.as_deref())
+        .bind(update.min_sentiment)
+        .bind(update.max_sentiment)
+        .bind(update.email)
+        .bind(update.webhook_url.as_deref())
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        self.reload().await?;
+        Ok(self.get(user_id, name).await?.ok_or_else(|| "saved search disappeared".to_string()))
+    }
+
+    fn validate(&self, name: &str, update: &SearchUpdate, config: &SearchesConfig) -> Result<(), String> {
+        if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+            return Err(format!("name must be 1 to {} characters", MAX_NAME_CHARS));
+        }
+        if update.query.chars().count() > MAX_QUERY_CHARS {
+            return Err(format!("query must be at most {} characters", MAX_QUERY_CHARS));
+        }
+        Query::parse(&update.query)?;
+        if let Some(label) = update.sentiment.as_deref() {
+            if !["positive", "neutral", "negative"].contains(&label) {
+                return Err("sentiment must be positive, neutral or negative".to_string());
+            }
+        }
+        if update.email && !self.notifier.can_email() {
+            return Err("email delivery isn't configured on this server".to_string());
+        }
+        if let Some(url) = &update.webhook_url {
+            if !config.webhooks {
+                return Err("webhook delivery is disabled on this server".to_string());
+            }
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err("webhook_url must start with http:// or https://".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Returns false when the user has no saved search by that name.
+    pub async fn delete(&self, user_id: i64, name: &str) -> Result<bool, sqlx::Error> {
+        let Some(search) = self.get(user_id, name).await? else {
+            return Ok(false);
+        };
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM saved_search_matches WHERE search_id = ?")
+            .bind(search.id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query("DELETE FROM saved_searches WHERE id = ?")
+            .bind(search.id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        self.reload().await?;
+        Ok(true)
+    }
+
+    // Matches newly archived articles against every saved search, records the matches and queues
+    // their deliveries. Returns how many matches were new; an article already matched, e.g. under
+    // another symbol, isn't delivered twice.
+    pub async fn ingest(&self, symbol: &str, articles: &[NewsArticle], config: &SearchesConfig) -> Result<usize, sqlx::Error> {
+        let mut matched: Vec<(Compiled, Vec<NewsArticle>)> = Vec::new();
+        {
+            let index = self.index.read().unwrap();
+            for article in articles {
+                for search in index.matching(symbol, article) {
+                    match matched.iter_mut().find(|(other, _)| other.id == search.id) {
+                        Some((_, articles)) => articles.push(article.clone()),
+                        None => matched.push((search.clone(), vec![article.clone()])),
+                    }
+                }
+            }
+        }
+
+        let now = Utc::now().timestamp();
+        let mut total = 0;
+        for (search, articles) in matched {
+            let mut new = Vec::new();
+            for article in articles {
+                let result = sqlx::query("INSERT OR IGNORE INTO saved_search_matches (search_id, link, matched_at) VALUES (?, ?, ?)")
+                    .bind(search.id)
+                    .bind(&article.link)
+                    .bind(now)
+                    .execute(&self.pool)
+                    .await?;
+                if result.rows_affected() > 0 {
+                    new.push(article);
+                }
+            }
+            if new.is_empty() {
+                continue;
+            }
+            total += new.len();
+            tracing::debug!(search = search.id, matches = new.len(), "saved search matched");
+            // Webhooks may have been turned off since the search was saved.
+            let webhook_url = search.webhook_url.filter(|_| config.webhooks);
+            if search.email_to.is_none() && webhook_url.is_none() {
+                continue;
+            }
+            let matches = SearchMatches {
+                search: search.name,
+                query: search.query_text,
+                articles: new,
+            };
+            let mut notification = Notification::new(matches.subject(), matches.to_text(), matches.to_html(), &matches);
+            notification.recipients = search.email_to.into_iter().collect();
+            notification.webhook_url = webhook_url;
+            self.notifier.queue(&notification).await?;
+        }
+        Ok(total)
+    }
+}
+
+const SEARCH_QUERY: &str = "SELECT s.id, s.name, s.query, s.symbols, s.sources, s.languages, s.sentiment,
+        s.min_sentiment, s.max_sentiment, s.email, s.webhook_url, s.updated_at,
+        (SELECT COUNT(*) FROM saved_search_matches m
+         LEFT JOIN article_states st ON st.user_id = s.user_id AND st.link = m.link
+         WHERE m.search_id = s.id AND st.read_at IS NULL) AS unread
+    FROM saved_searches s";
+
+fn from_row(row: &SqliteRow) -> SavedSearch {
+    SavedSearch {
+        id: row.get("id"),
+        name: row.get("name"),
+        query: row.get("query"),
+        symbols: split(row.get("symbols")),
+        sources: split(row.get("sources")),
+        languages: split(row.get("languages")),
+        sentiment: row.get("sentiment"),
+        min_sentiment: row.get("min_sentiment"),
+        max_sentiment: row.get("max_sentiment"),
+        email: row.get("email"),
+        webhook_url: row.get("webhook_url"),
+        updated_at: row.get("updated_at"),
+        unread: row.get("unread"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn article(title: &str, link: &str) -> NewsArticle {
+        let mut article = NewsArticle::new(
+            title.to_string(),
+            "wire".to_string(),
+            "2026-10-19T08:00:00Z".to_string(),
+            String::new(),
+            link.to_string(),
+        );
+        crate::services::ingest::prepare(&mut article);
+        article
+    }
+
+    #[actix_web::test]
+    async fn articles_match_a_saved_search_once() {
+        let config = Config::parse("[auth]\njwt_secret = \"0123456789abcdef0123456789abcdef-test\"", std::iter::empty()).unwrap();
+        let state = crate::test_state(config.clone()).await;
+        let user = state
+            .users
+            .register(&config.auth, "203.0.113.1", "ana@example.com", "password123")
+            .await
+            .unwrap()
+            .user;
+        let update = SearchUpdate {
+            query: "etf \"spot bitcoin\"".to_string(),
+            symbols: Vec::new(),
+            sources: Vec::new(),
+            languages: Vec::new(),
+            sentiment: None,
+            min_sentiment: None,
+            max_sentiment: None,
+            email: false,
+            webhook_url: None,
+        };
+        state.searches.put(user.id, "etfs", &update, &config.searches).await.unwrap().unwrap();
+
+        let matching = article("Spot bitcoin ETF sees record inflows", "https://example.com/etf");
+        let other = article("Spot bitcoin trading volume rises", "https://example.com/volume");
+        let articles = [matching.clone(), other];
+        assert_eq!(state.searches.ingest("BTC", &articles, &config.searches).await.unwrap(), 1);
+        // The same article again, as when it is archived under another symbol.
+        assert_eq!(state.searches.ingest("ETH", std::slice::from_ref(&matching), &config.searches).await.unwrap(), 0);
+        assert_eq!(state.ingest.store("wire", "SOL", &[matching], &config).await, 1);
+
+        let search = state.searches.get(user.id, "etfs").await.unwrap().unwrap();
+        assert_eq!(search.unread, 1);
+    }
+}
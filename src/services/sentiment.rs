@@ -0,0 +1,293 @@
+// Offline lexicon-based sentiment scoring tuned for crypto headlines.
+
+// Weights range from -4 (very negative) to +4 (very positive). Phrases are matched before single words.
+const LEXICON: &[(&str, f64)] = &[
+    ("rug pull", -4.0),
+    ("rugpull", -4.0),
+    ("exit scam", -4.0),
+    ("etf approval", 3.0),
+    ("etf approved", 3.0),
+    ("etf approves", 3.0),
+    ("etf rejection", -3.0),
+    ("etf rejected", -3.0),
+    ("etf delay", -1.5),
+    ("etf delayed", -1.5),
+    ("all time high", 2.5),
+    ("all-time high", 2.5),
+    ("record high", 2.5),
+    ("ath", 2.5),
+    ("new high", 2.0),
+    ("short squeeze", 1.5),
+    ("flash crash", -3.0),
+    ("death cross", -2.0),
+    ("golden cross", 2.0),
+    ("sec charges", -3.0),
+    ("sec sues", -3.0),
+    ("class action", -2.0),
+    ("chapter 11", -3.5),
+    ("withdrawals halted", -3.5),
+    ("halts withdrawals", -3.5),
+    ("net inflows", 1.5),
+    ("net outflows", -1.5),
+    ("hack", -3.0),
+    ("hacked", -3.0),
+    ("hacker", -2.5),
+    ("hackers", -2.5),
+    ("exploit", -3.0),
+    ("exploited", -3.0),
+    ("drained", -3.0),
+    ("stolen", -3.0),
+    ("theft", -3.0),
+    ("scam", -3.0),
+    ("fraud", -3.5),
+    ("ponzi", -3.5),
+    ("bankrupt", -3.5),
+    ("bankruptcy", -3.5),
+    ("insolvent", -3.5),
+    ("insolvency", -3.5),
+    ("collapse", -3.0),
+    ("collapses", -3.0),
+    ("crash", -3.0),
+    ("crashes", -3.0),
+    ("plunge", -2.5),
+    ("plunges", -2.5),
+    ("tumble", -2.0),
+    ("tumbles", -2.0),
+    ("slump", -2.0),
+    ("slumps", -2.0),
+    ("dump", -2.0),
+    ("dumps", -2.0),
+    ("selloff", -2.0),
+    ("sell-off", -2.0),
+    ("liquidation", -1.5),
+    ("liquidations", -1.5),
+    ("liquidated", -2.0),
+    ("bearish", -2.0),
+    ("fear", -1.5),
+    ("fud", -1.5),
+    ("lawsuit", -2.0),
+    ("sued", -2.0),
+    ("ban", -2.5),
+    ("banned", -2.5),
+    ("bans", -2.5),
+    ("crackdown", -2.5),
+    ("delist", -2.5),
+    ("delisted", -2.5),
+    ("delisting", -2.5),
+    ("outage", -2.0),
+    ("vulnerability", -2.0),
+    ("warning", -1.0),
+    ("decline", -1.5),
+    ("declines", -1.5),
+    ("drop", -1.5),
+    ("drops", -1.5),
+    ("falls", -1.5),
+    ("loss", -1.5),
+    ("losses", -1.5),
+    ("outflows", -1.0),
+    ("surge", 2.5),
+    ("surges", 2.5),
+    ("soar", 2.5),
+    ("soars", 2.5),
+    ("skyrocket", 3.0),
+    ("skyrockets", 3.0),
+    ("rally", 2.0),
+    ("rallies", 2.0),
+    ("moon", 2.0),
+    ("pump", 1.0),
+    ("bullish", 2.0),
+    ("breakout", 2.0),
+    ("gain", 1.5),
+    ("gains", 1.5),
+    ("rise", 1.5),
+    ("rises", 1.5),
+    ("jump", 1.5),
+    ("jumps", 1.5),
+    ("climbs", 1.5),
+    ("recovery", 1.5),
+    ("rebound", 1.5),
+    ("rebounds", 1.5),
+    ("inflows", 1.0),
+    ("adoption", 2.0),
+    ("partnership", 1.5),
+    ("partners", 1.0),
+    ("launch", 1.0),
+    ("launches", 1.0),
+    ("upgrade", 1.5),
+    ("approval", 2.0),
+    ("approved", 2.0),
+    ("approves", 2.0),
+    ("listing", 1.5),
+    ("listed", 1.0),
+    ("integration", 1.0),
+    ("milestone", 1.5),
+    ("record", 1.0),
+    ("win", 1.5),
+    ("wins", 1.5),
+    ("optimism", 1.5),
+    ("optimistic", 1.5),
+];
+
+const NEGATIONS: &[&str] = &["not", "no", "never", "without", "denies", "deny", "fails", "fail", "avoids"];
+
+// How many following tokens a negation word flips; it also flips a term ending fewer than this
+// many tokens before it, as in "ETF approval fails".
+const NEGATION_SCOPE: usize = 3;
+
+// A negated term counts for less than its opposite: "not bullish" is weaker than "bearish".
+const NEGATION_FACTOR: f64 = 0.75;
+
+// Scale the sentiment term right before or right after them.
+const INTENSIFIERS: &[(&str, f64)] = &[
+    ("massive", 1.5),
+    ("huge", 1.5),
+    ("biggest", 1.5),
+    ("sharply", 1.5),
+    ("heavily", 1.5),
+    ("major", 1.25),
+    ("slightly", 0.5),
+    ("minor", 0.5),
+];
+
+const MAX_PHRASE_WORDS: usize = 3;
+
+pub const NEUTRAL_THRESHOLD: f64 = 0.05;
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '-'))
+        .map(|token| token.trim_matches('-'))
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn lookup(phrase: &str) -> Option<f64> {
+    LEXICON
+        .iter()
+        .find(|(term, _)| *term == phrase)
+        .map(|(_, weight)| *weight)
+}
+
+fn intensifier(token: &str) -> Option<f64> {
+    INTENSIFIERS
+        .iter()
+        .find(|(word, _)| *word == token)
+        .map(|(_, factor)| *factor)
+}
+
+// A matched lexicon term: the index of the token after it, its weight so far and whether a
+// negation already flipped it.
+struct Term {
+    end: usize,
+    weight: f64,
+    negated: bool,
+}
+
+// Returns a score in [-1, 1]; 0 means no sentiment-bearing terms were found.
+pub fn score(text: &str) -> f64 {
+    let tokens = tokenize(text);
+    let mut terms: Vec<Term> = Vec::new();
+    let mut negated_until = 0;
+    let mut boost = 1.0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = tokens[i].as_str();
+        if NEGATIONS.contains(&token) {
+            for term in terms.iter_mut().filter(|term| !term.negated && i - term.end < NEGATION_SCOPE) {
+                term.weight *= -NEGATION_FACTOR;
+                term.negated = true;
+            }
+            negated_until = i + 1 + NEGATION_SCOPE;
+            i += 1;
+            continue;
+        }
+        if let Some(factor) = intensifier(token) {
+            match terms.last_mut() {
+                Some(term) if term.end == i => term.weight *= factor,
+                _ => boost = factor,
+            }
+            i += 1;
+            continue;
+        }
+
+        let mut matched = None;
+        for len in (1..=MAX_PHRASE_WORDS.min(tokens.len() - i)).rev() {
+            if let Some(weight) = lookup(&tokens[i..i + len].join(" ")) {
+                matched = Some((weight, len));
+                break;
+            }
+        }
+
+        match matched {
+            Some((weight, len)) => {
+                let negated = i < negated_until;
+                terms.push(Term {
+                    end: i + len,
+                    weight: weight * boost * if negated { -NEGATION_FACTOR } else { 1.0 },
+                    negated,
+                });
+                i += len;
+            }
+            None => i += 1,
+        }
+        boost = 1.0;
+    }
+
+    // Squash the unbounded sum into [-1, 1] so long and short texts are comparable.
+    let total: f64 = terms.iter().map(|term| term.weight).sum();
+    total / (total * total + 15.0).sqrt()
+}
+
+pub fn label(score: f64) -> &'static str {
+    if score >= NEUTRAL_THRESHOLD {
+        "positive"
+    } else if score <= -NEUTRAL_THRESHOLD {
+        "negative"
+    } else {
+        "neutral"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terms_set_the_polarity() {
+        assert_eq!(label(score("Bitcoin surges to record high")), "positive");
+        assert_eq!(label(score("Exchange hacked, user funds stolen")), "negative");
+        assert_eq!(score("Bitcoin trades sideways"), 0.0);
+        assert_eq!(label(score("Bitcoin trades sideways")), "neutral");
+        // Phrases win over their words: "pull" alone means nothing.
+        assert!(score("Developers pull rug pull") < score("Developers hack"));
+        let many = score(&"rally ".repeat(50));
+        assert!(many > 0.99 && many < 1.0);
+    }
+
+    #[test]
+    fn negations_flip_terms_on_either_side() {
+        assert!(score("ETF approval") > 0.0);
+        assert!(score("ETF approval fails") < 0.0);
+        assert!(score("Not bullish") < 0.0);
+        assert!(score("Not bullish") > score("Bearish"));
+        assert!(score("No hack, funds are safe") > 0.0);
+        // Too far away on either side.
+        assert!(score("ETF approval announced today as rival fund fails") > 0.0);
+        assert!(score("Never mind the weather, bitcoin rallies") > 0.0);
+        // A term flipped by the negation before it isn't flipped back by one after it.
+        assert!(score("Not bullish, fails") < 0.0);
+    }
+
+    #[test]
+    fn intensifiers_scale_the_adjacent_term() {
+        assert!(score("Massive hack") < score("Hack"));
+        assert!(score("Bitcoin falls sharply") < score("Bitcoin falls"));
+        assert!(score("Slightly bearish outlook") > score("Bearish outlook"));
+        assert!(score("Slightly bearish outlook") < 0.0);
+        assert!(score("Huge rally") > score("Rally"));
+        // Only the term next to it.
+        assert_eq!(score("Massive crowd as bitcoin rallies"), score("Bitcoin rallies"));
+    }
+}
@@ -0,0 +1,121 @@
+// Background jobs and graceful shutdown. Jobs check `Shutdown::requested` between runs, so a run
+// that has started (a price poll, a clustering pass, a digest) finishes before the job stops.
+// `Jobs::drain` asks every job to stop and waits up to a deadline.
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+#[derive(Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    // Resolves once shutdown has started; safe to use in `select!` over and over.
+    pub async fn requested(&mut self) {
+        // A dropped sender means the jobs are going away anyway.
+        let _ = self.receiver.wait_for(|stopping| *stopping).await;
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.receiver.borrow()
+    }
+}
+
+pub struct Jobs {
+    sender: watch::Sender<bool>,
+    running: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        Jobs {
+            sender: watch::channel(false).0,
+            running: Vec::new(),
+        }
+    }
+
+    pub fn shutdown(&self) -> Shutdown {
+        Shutdown {
+            receiver: self.sender.subscribe(),
+        }
+    }
+
+    pub fn spawn(&mut self, name: &'static str, job: impl Future<Output = ()> + Send + 'static) {
+        self.running.push((name, tokio::spawn(job)));
+    }
+
+    // Jobs still running at the deadline are aborted.
+    pub async fn drain(self, timeout: Duration) {
+        self.sender.send_replace(true);
+        let deadline = tokio::time::Instant::now() + timeout;
+        for (name, mut job) in self.running {
+            match tokio::time::timeout_at(deadline, &mut job).await {
+                Ok(Ok(())) => tracing::debug!(job = name, "job stopped"),
+                Ok(Err(e)) => tracing::error!(job = name, error = %e, "job failed"),
+                Err(_) => {
+                    tracing::warn!(job = name, "job still running at the shutdown deadline, aborting it");
+                    job.abort();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn draining_lets_a_started_run_finish() {
+        let mut jobs = Jobs::new();
+        let mut shutdown = jobs.shutdown();
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        jobs.spawn("poll", async move {
+            loop {
+                // A run in progress when shutdown starts.
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                if shutdown.is_requested() {
+                    flag.store(true, Ordering::SeqCst);
+                    return;
+                }
+                shutdown.requested().await;
+            }
+        });
+        assert!(!jobs.shutdown().is_requested());
+
+        jobs.drain(Duration::from_secs(5)).await;
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[actix_web::test]
+    async fn jobs_still_running_at_the_deadline_are_aborted() {
+        let mut jobs = Jobs::new();
+        let mut waiting = jobs.shutdown();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = stopped.clone();
+        jobs.spawn("waiting", async move {
+            waiting.requested().await;
+            flag.store(true, Ordering::SeqCst);
+        });
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        jobs.spawn("stuck", async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        let started = std::time::Instant::now();
+        jobs.drain(Duration::from_millis(100)).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(stopped.load(Ordering::SeqCst));
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,520 @@
+// News sources managed at runtime through the admin API and stored in the database: RSS feeds and
+// generic JSON endpoints, polled on their own interval. Sources under `[sources]` in the config
+// keep working as before and can't be edited here, only have their circuit reset.
+//
+// Each fetch, scheduled or triggered by hand, is written to the fetch log, which keeps a week.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use tokio::sync::watch;
+
+use crate::api::{fetch_json_feed, fetch_rss, HttpClient, JsonMapping};
+use crate::config::{Config, SourceConfig, DEFAULT_API_KEY_HEADER};
+use crate::models::news::NewsArticle;
+use crate::services::ingest::{self, Ingest};
+use crate::services::shutdown::Shutdown;
+
+const MAX_NAME_CHARS: usize = 64;
+const MIN_INTERVAL_SECS: u64 = 60;
+const LOG_RETENTION: i64 = 7 * 24 * 60 * 60;
+
+// How often the schedule looks for sources that are due.
+const TICK: Duration = Duration::from_secs(15);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    Rss,
+    Json,
+}
+
+impl SourceKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            SourceKind::Rss => "rss",
+            SourceKind::Json => "json",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ManagedSource {
+    pub name: String,
+    pub kind: SourceKind,
+    pub url: String,
+    // Articles are archived under each of these symbols, or with `require_mention` only under
+    // those whose ticker appears in the title or summary.
+    pub symbols: Vec<String>,
+    pub require_mention: bool,
+    #[serde(skip)]
+    pub api_key: Option<String>,
+    pub api_key_header: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mapping: Option<JsonMapping>,
+    pub interval_secs: u64,
+    pub enabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl ManagedSource {
+    // For the HTTP client, which knows sources by their config.
+    pub fn config(&self) -> SourceConfig {
+        SourceConfig {
+            name: self.name.clone(),
+            base_url: self.url.clone(),
+            api_key: self.api_key.clone(),
+            api_key_header: Some(
+                self.api_key_header
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_API_KEY_HEADER.to_string()),
+            ),
+            headers: self.headers.clone(),
+            interval_secs: self.interval_secs,
+            enabled: self.enabled,
+        }
+    }
+
+    // Tickers count as written in capitals only, so "SOL" matches but "sol" doesn't.
+    fn mentions(&self, article: &NewsArticle, symbol: &str) -> bool {
+        !self.require_mention
+            || [&article.title, &article.summary]
+                .iter()
+                .any(|text| text.split(|c: char| !c.is_alphanumeric()).any(|word| word == symbol))
+    }
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// POST /admin/sources
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NewSource {
+    pub name: String,
+    pub kind: SourceKind,
+    pub url: String,
+    pub symbols: Vec<String>,
+    #[serde(default)]
+    pub require_mention: bool,
+    pub api_key: Option<String>,
+    pub api_key_header: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // Only for `json` sources; the defaults of `JsonMapping` when left out.
+    pub mapping: Option<JsonMapping>,
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+// Managed source names are stored trimmed and lowercased, and looked up the same way.
+pub fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+impl NewSource {
+    // Normalizes the name and symbols and checks the rest. Names of config sources are taken.
+    pub fn validate(&mut self, config: &Config) -> Result<(), String> {
+        self.name = normalize_name(&self.name);
+        if self.name.is_empty()
+            || self.name.chars().count() > MAX_NAME_CHARS
+            || !self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "name must be 1 to {} letters, digits, dashes or underscores",
+                MAX_NAME_CHARS
+            ));
+        }
+        if config.sources.contains_key(&self.name) {
+            return Err(format!("{} is a configured source", self.name));
+        }
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err("url must start with http:// or https://".to_string());
+        }
+        let mut symbols: Vec<String> = Vec::new();
+        for symbol in &self.symbols {
+            let symbol = symbol.trim().to_uppercase();
+            if symbol.is_empty() || !symbol.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(format!("invalid symbol {:?}", symbol));
+            }
+            if !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+        if symbols.is_empty() {
+            return Err("a source needs at least one symbol to archive its articles under".to_string());
+        }
+        self.symbols = symbols;
+        match self.kind {
+            SourceKind::Rss if self.mapping.is_some() => {
+                return Err("only json sources take a mapping".to_string());
+            }
+            SourceKind::Rss => {}
+            SourceKind::Json => {
+                self.mapping.get_or_insert_with(JsonMapping::default);
+            }
+        }
+        if self.interval_secs < MIN_INTERVAL_SECS {
+            return Err(format!("interval_secs must be at least {}", MIN_INTERVAL_SECS));
+        }
+        if let Some(header) = &self.api_key_header {
+            if HeaderName::from_bytes(header.as_bytes()).is_err() {
+                return Err("api_key_header must be a valid header name".to_string());
+            }
+        }
+        if let Some(api_key) = &self.api_key {
+            if HeaderValue::from_str(api_key).is_err() {
+                return Err("api_key must be a valid header value".to_string());
+            }
+        }
+        for (name, value) in &self.headers {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() || HeaderValue::from_str(value).is_err() {
+                return Err(format!("invalid header {:?}", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+// PATCH /admin/sources/{name}: only the fields given change. An empty `api_key` removes it, and an
+// empty `api_key_header` goes back to the default.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SourcePatch {
+    pub kind: Option<SourceKind>,
+    pub url: Option<String>,
+    pub symbols: Option<Vec<String>>,
+    pub require_mention: Option<bool>,
+    pub api_key: Option<String>,
+    pub api_key_header: Option<String>,
+    pub headers: Option<BTreeMap<String, String>>,
+    pub mapping: Option<JsonMapping>,
+    pub interval_secs: Option<u64>,
+    pub enabled: Option<bool>,
+}
+
+impl SourcePatch {
+    pub fn apply(self, source: &ManagedSource) -> NewSource {
+        let kind = self.kind.unwrap_or(source.kind);
+        NewSource {
+            name: source.name.clone(),
+            kind,
+            url: self.url.unwrap_or_else(|| source.url.clone()),
+            symbols: self.symbols.unwrap_or_else(|| source.symbols.clone()),
+            require_mention: self.require_mention.unwrap_or(source.require_mention),
+            api_key: match self.api_key {
+                Some(api_key) => Some(api_key).filter(|api_key| !api_key.is_empty()),
+                None => source.api_key.clone(),
+            },
+            api_key_header: match self.api_key_header {
+                Some(header) => Some(header).filter(|header| !header.is_empty()),
+                None => source.api_key_header.clone(),
+            },
+            headers: self.headers.unwrap_or_else(|| source.headers.clone()),
+            // A source turned into an RSS feed drops its mapping.
+            mapping: match kind {
+                SourceKind::Rss => None,
+                SourceKind::Json => self.mapping.or_else(|| source.mapping.clone()),
+            },
+            interval_secs: self.interval_secs.unwrap_or(source.interval_secs),
+            enabled: self.enabled.unwrap_or(source.enabled),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FetchLogEntry {
+    pub id: i64,
+    pub source: String,
+    // Triggered through the admin API rather than by the schedule.
+    pub manual: bool,
+    pub started_at: i64,
+    pub elapsed_ms: i64,
+    // Articles in the response, and how many of them were new. Both are 0 when the upstream
+    // answered 304 or its max-age hadn't passed.
+    pub fetched: i64,
+    pub stored: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Sources {
+    pool: SqlitePool,
+}
+
+impl Sources {
+    pub fn new(pool: SqlitePool) -> Self {
+        Sources { pool }
+    }
+
+    pub async fn list(&self) -> Result<Vec<ManagedSource>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM sources ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Option<ManagedSource>, sqlx::Error> {
+        let row = sqlx::query("SELECT * FROM sources WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(from_row))
+    }
+
+    // `new` must have been validated. None when the name is taken.
+    pub async fn create(&self, new: &NewSource) -> Result<Option<ManagedSource>, sqlx::Error> {
+        let now = Utc::now().timestamp();
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO sources
+                (name, kind, url, symbols, require_mention, api_key, api_key_header, headers, mapping,
+                 interval_secs, enabled, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&new.name)
+        .bind(new.kind.as_str())
+        .bind(&new.url)
+        .bind(new.symbols.join(","))
+        .bind(new.require_mention)
+        .bind(new.api_key.as_deref())
+        .bind(new.api_key_header.as_deref())
+        .bind(json(&new.headers))
+        .bind(new.mapping.as_ref().map(json))
+        .bind(new.interval_secs as i64)
+        .bind(new.enabled)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get(&new.name).await
+    }
+
+    // `source` must have been validated. None when there is no such source.
+    pub async fn update(&self, source: &NewSource) -> Result<Option<ManagedSource>, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE sources
+             SET kind = ?, url = ?, symbols = ?, require_mention = ?, api_key = ?, api_key_header = ?,
+                 headers = ?, mapping = ?, interval_secs = ?, enabled = ?, updated_at = ?
+             WHERE name = ?",
+        )
+        .bind(source.kind.as_str())
+        .bind(&source.url)
+        .bind(source.symbols.join(","))
+        .bind(source.require_mention)
+        .bind(source.api_key.as_deref())
+        .bind(source.api_key_header.as_deref())
+        .bind(json(&source.headers))
+        .bind(source.mapping.as_ref().map(json))
+        .bind(source.interval_secs as i64)
+        .bind(source.enabled)
+        .bind(Utc::now().timestamp())
+        .bind(&source.name)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get(&source.name).await
+    }
+
+    // Its fetch log goes too; archived articles stay.
+    pub async fn delete(&self, name: &str) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM fetch_log WHERE source = ?")
+            .bind(name)
+            .execute(&mut tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM sources WHERE name = ?")
+            .bind(name)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record(&self, entry: &FetchLogEntry) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO fetch_log (source, manual, started_at, elapsed_ms, fetched, stored, error)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&entry.source)
+        .bind(entry.manual)
+        .bind(entry.started_at)
+        .bind(entry.elapsed_ms)
+        .bind(entry.fetched)
+        .bind(entry.stored)
+        .bind(entry.error.as_deref())
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("DELETE FROM fetch_log WHERE started_at < ?")
+            .bind(entry.started_at - LOG_RETENTION)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    // Newest first, for one source or all of them.
+    pub async fn log(&self, source: Option<&str>, errors_only: bool, limit: i64) -> Result<Vec<FetchLogEntry>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, source, manual, started_at, elapsed_ms, fetched, stored, error FROM fetch_log
+             WHERE (?1 IS NULL OR source = ?1) AND (NOT ?2 OR error IS NOT NULL)
+             ORDER BY id DESC LIMIT ?3",
+        )
+        .bind(source)
+        .bind(errors_only)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| FetchLogEntry {
+                id: row.get("id"),
+                source: row.get("source"),
+                manual: row.get("manual"),
+                started_at: row.get("started_at"),
+                elapsed_ms: row.get("elapsed_ms"),
+                fetched: row.get("fetched"),
+                stored: row.get("stored"),
+                error: row.get("error"),
+            })
+            .collect())
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn from_row(row: &SqliteRow) -> ManagedSource {
+    let kind: String = row.get("kind");
+    let headers: String = row.get("headers");
+    let mapping: Option<String> = row.get("mapping");
+    ManagedSource {
+        name: row.get("name"),
+        kind: if kind == "json" { SourceKind::Json } else { SourceKind::Rss },
+        url: row.get("url"),
+        symbols: row
+            .get::<String, _>("symbols")
+            .split(',')
+            .filter(|symbol| !symbol.is_empty())
+            .map(str::to_string)
+            .collect(),
+        require_mention: row.get("require_mention"),
+        api_key: row.get("api_key"),
+        api_key_header: row.get("api_key_header"),
+        headers: serde_json::from_str(&headers).unwrap_or_default(),
+        mapping: mapping.and_then(|mapping| serde_json::from_str(&mapping).ok()),
+        interval_secs: row.get::<i64, _>("interval_secs") as u64,
+        enabled: row.get("enabled"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+#[derive(Clone)]
+pub struct Fetcher {
+    http: HttpClient,
+    sources: Sources,
+    ingest: Ingest,
+}
+
+impl Fetcher {
+    pub fn new(http: HttpClient, sources: Sources, ingest: Ingest) -> Self {
+        Fetcher { http, sources, ingest }
+    }
+
+    // Fetches the source once and archives what is new. Upstream failures end up in the entry;
+    // the error is the log's own storage failing.
+    #[tracing::instrument(name = "source_fetch", skip_all, fields(source = %source.name, manual))]
+    pub async fn fetch(&self, source: &ManagedSource, manual: bool, config: &Config) -> Result<FetchLogEntry, sqlx::Error> {
+        let started_at = Utc::now().timestamp();
+        let started = Instant::now();
+        let upstream = source.config();
+        let result = match (source.kind, &source.mapping) {
+            (SourceKind::Json, Some(mapping)) => fetch_json_feed(&self.http, &upstream, mapping).await,
+            (SourceKind::Json, None) => fetch_json_feed(&self.http, &upstream, &JsonMapping::default()).await,
+            (SourceKind::Rss, _) => fetch_rss(&self.http, &upstream).await,
+        };
+        let mut entry = FetchLogEntry {
+            id: 0,
+            source: source.name.clone(),
+            manual,
+            started_at,
+            elapsed_ms: 0,
+            fetched: 0,
+            stored: 0,
+            error: None,
+        };
+        match result {
+            Ok(fetched) if fetched.modified => {
+                let mut articles = fetched.value;
+                articles.iter_mut().for_each(ingest::prepare);
+                entry.fetched = articles.len() as i64;
+                for symbol in &source.symbols {
+                    let mentioning: Vec<NewsArticle> = articles
+                        .iter()
+                        .filter(|article| source.mentions(article, symbol))
+                        .cloned()
+                        .collect();
+                    entry.stored += self.ingest.store(&source.name, symbol, &mentioning, config).await as i64;
+                }
+            }
+            // Nothing new: the body is the one kept from an earlier response.
+            Ok(_) => {}
+            Err(e) => entry.error = Some(e.to_string()),
+        }
+        entry.elapsed_ms = started.elapsed().as_millis() as i64;
+        entry.id = self.sources.record(&entry).await?;
+        tracing::info!(fetched = entry.fetched, stored = entry.stored, error = ?entry.error, "source fetched");
+        Ok(entry)
+    }
+}
+
+// Fetches every enabled source once its interval has passed since its last scheduled fetch. The
+// sources are re-read on every tick, so changes through the admin API apply without a restart.
+pub async fn run_schedule(fetcher: Fetcher, config: watch::Receiver<Arc<Config>>, mut shutdown: Shutdown) {
+    let mut last_run: HashMap<String, Instant> = HashMap::new();
+    loop {
+        match fetcher.sources.list().await {
+            Ok(sources) => {
+                let current = config.borrow().clone();
+                for source in sources.iter().filter(|source| source.enabled) {
+                    let due = last_run
+                        .get(&source.name)
+                        .is_none_or(|last| last.elapsed() >= Duration::from_secs(source.interval_secs));
+                    if !due {
+                        continue;
+                    }
+                    last_run.insert(source.name.clone(), Instant::now());
+                    if let Err(e) = fetcher.fetch(source, false, &current).await {
+                        tracing::error!(source = %source.name, error = %e, "failed to record fetch");
+                    }
+                }
+            }
+            Err(e) => tracing::error!(error = %e, "failed to load sources"),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(TICK) => {}
+            _ = shutdown.requested() => return,
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// Offline clustering of archived articles into stories using TF-IDF cosine similarity
+// between articles published close to each other.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use tracing::Instrument;
+
+use crate::services::archive::{Archive, ArchivedArticle};
+use crate::services::shutdown::Shutdown;
+use crate::services::text;
+
+// Articles further apart than this are never placed in the same story.
+const WINDOW: i64 = 48 * 60 * 60;
+const SIMILARITY_THRESHOLD: f64 = 0.4;
+// Terms in more articles than this, like a coin's name, don't single out similar articles; they
+// still count towards the similarity of those found through rarer terms.
+const MAX_POSTINGS: usize = 100;
+const RUN_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StoryRef {
+    Existing(i64),
+    // Index into the list of stories created by this run.
+    New(usize),
+}
+
+// Headlines are what usually gives an event away, so title terms count double.
+fn terms(article: &ArchivedArticle) -> Vec<String> {
+    let mut terms = text::content_words(&article.article.title);
+    terms.extend(terms.clone());
+    terms.extend(text::content_words(&article.article.summary));
+    terms
+}
+
+// Assigns each of `pending` to a story, either one already holding an article in `clustered`
+// or a new one. Returns the assignment per pending article id, in publication order.
+//
+// Only articles sharing one of the rarer terms are compared, so an article is compared with a
+// bounded number of others rather than with everything in the window.
+pub fn cluster(clustered: &[ArchivedArticle], pending: &[ArchivedArticle]) -> Vec<(i64, StoryRef)> {
+    let mut pending: Vec<&ArchivedArticle> = pending.iter().collect();
+    pending.sort_by_key(|archived| archived.published_at);
+
+    let docs: Vec<&ArchivedArticle> = clustered.iter().chain(pending.iter().copied()).collect();
+    let vectors = text::tfidf(&docs.iter().map(|doc| terms(doc)).collect::<Vec<_>>());
+    let mut postings: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, vector) in vectors.iter().enumerate() {
+        for term in vector.keys() {
+            postings.entry(term.as_str()).or_default().push(index);
+        }
+    }
+
+    let mut stories: Vec<Option<StoryRef>> = clustered
+        .iter()
+        .map(|archived| archived.story_id.map(StoryRef::Existing))
+        .collect();
+    let mut new_stories = 0;
+    let mut assignments = Vec::new();
+
+    for (offset, archived) in pending.iter().enumerate() {
+        let index = clustered.len() + offset;
+        let candidates: HashSet<usize> = vectors[index]
+            .keys()
+            .map(|term| &postings[term.as_str()])
+            .filter(|docs| docs.len() <= MAX_POSTINGS)
+            .flat_map(|docs| docs.iter().copied().take_while(|&other| other < index))
+            .collect();
+        // Ties go to the later article, so the result doesn't depend on the set's order.
+        let best = candidates
+            .into_iter()
+            .filter(|&other| stories[other].is_some())
+            .filter(|&other| (docs[other].published_at - archived.published_at).abs() <= WINDOW)
+            .map(|other| (other, text::cosine(&vectors[index], &vectors[other])))
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        let story = match best {
+            Some((other, similarity)) if similarity >= SIMILARITY_THRESHOLD => stories[other].unwrap(),
+            _ => {
+                new_stories += 1;
+                StoryRef::New(new_stories - 1)
+            }
+        };
+        stories.push(Some(story));
+        assignments.push((archived.id, story));
+    }
+    assignments
+}
+
+// The member most similar to all the others best represents the story.
+pub fn representative(members: &[ArchivedArticle]) -> Option<&ArchivedArticle> {
+    let vectors = text::tfidf(&members.iter().map(terms).collect::<Vec<_>>());
+    (0..members.len())
+        .map(|i| {
+            let centrality: f64 = (0..members.len())
+                .filter(|&j| j != i)
+                .map(|j| text::cosine(&vectors[i], &vectors[j]))
+                .sum();
+            (i, centrality)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| &members[i])
+}
+
+pub async fn run_once(archive: &Archive) -> Result<usize, sqlx::Error> {
+    let pending = archive.unclustered().await?;
+    let earliest = match pending.iter().map(|archived| archived.published_at).min() {
+        Some(earliest) => earliest,
+        None => return Ok(0),
+    };
+    let clustered = archive.clustered_since(earliest - WINDOW).await?;
+
+    let mut created: Vec<i64> = Vec::new();
+    let mut touched = HashSet::new();
+    for (article_id, story) in cluster(&clustered, &pending) {
+        let story_id = match story {
+            StoryRef::Existing(id) => id,
+            StoryRef::New(index) if index < created.len() => created[index],
+            StoryRef::New(_) => {
+                let archived = pending.iter().find(|archived| archived.id == article_id).unwrap();
+                let id = archive.create_story(&archived.article.title, archived.published_at).await?;
+                created.push(id);
+                id
+            }
+        };
+        archive.assign_story(article_id, story_id).await?;
+        touched.insert(story_id);
+    }
+
+    for story_id in touched {
+        let members = archive.story_articles(story_id).await?;
+        if let Some(headline) = representative(&members) {
+            archive.refresh_story(story_id, &headline.article.title).await?;
+        }
+    }
+    Ok(pending.len())
+}
+
+pub async fn run_schedule(archive: Archive, mut shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(RUN_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => return,
+        }
+        let span = tracing::info_span!("stories");
+        match run_once(&archive).instrument(span.clone()).await {
+            Ok(0) => {}
+            Ok(clustered) => span.in_scope(|| tracing::info!(articles = clustered, "clustered articles")),
+            Err(e) => span.in_scope(|| tracing::error!(error = %e, "clustering failed")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::news::NewsArticle;
+
+    const HOUR: i64 = 60 * 60;
+
+    fn article(id: i64, title: &str, published_at: i64, story_id: Option<i64>) -> ArchivedArticle {
+        ArchivedArticle {
+            id,
+            symbol: "BTC".to_string(),
+            published_at,
+            story_id,
+            article: NewsArticle::new(
+                title.to_string(),
+                "wire".to_string(),
+                String::new(),
+                String::new(),
+                format!("https://example.com/{}", id),
+            ),
+        }
+    }
+
+    #[test]
+    fn articles_join_similar_stories_within_the_window() {
+        let clustered = vec![
+            article(1, "Binance halts withdrawals after exchange hack", 0, Some(7)),
+            article(2, "Mining difficulty reaches record high", 0, Some(8)),
+        ];
+        let pending = vec![
+            article(13, "Lawmakers debate stablecoin rules", 3 * HOUR, None),
+            article(12, "Binance halts withdrawals following hack", HOUR, None),
+            article(14, "Stablecoin rules debated by lawmakers", 4 * HOUR, None),
+            article(15, "Binance halts withdrawals after hack", WINDOW + HOUR, None),
+        ];
+
+        let assignments = cluster(&clustered, &pending);
+
+        assert_eq!(
+            assignments,
+            vec![
+                (12, StoryRef::Existing(7)),
+                (13, StoryRef::New(0)),
+                (14, StoryRef::New(0)),
+                // Too late for article 1, but it joins article 12's story.
+                (15, StoryRef::Existing(7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unrelated_articles_start_their_own_stories() {
+        let pending = vec![
+            article(1, "Binance halts withdrawals after exchange hack", 0, None),
+            article(2, "Mining difficulty reaches record high", 2 * WINDOW, None),
+            article(3, "Binance halts withdrawals after exchange hack", 4 * WINDOW, None),
+        ];
+
+        let stories: Vec<StoryRef> = cluster(&[], &pending).into_iter().map(|(_, story)| story).collect();
+
+        assert_eq!(stories, vec![StoryRef::New(0), StoryRef::New(1), StoryRef::New(2)]);
+    }
+
+    #[test]
+    fn the_headline_is_the_member_closest_to_the_others() {
+        let members = vec![
+            article(1, "Alpha bravo", 0, Some(1)),
+            article(2, "Bravo charlie", 0, Some(1)),
+            article(3, "Charlie delta", 0, Some(1)),
+        ];
+        assert_eq!(representative(&members).map(|archived| archived.id), Some(2));
+        assert!(representative(&[]).is_none());
+    }
+}
@@ -0,0 +1,182 @@
+// Offline extractive summarization: picks the most central sentences of one or more texts.
+
+use crate::services::text;
+
+// Sentences this similar to one already picked add nothing new (common across sources).
+const REDUNDANCY_THRESHOLD: f64 = 0.6;
+const MIN_SENTENCE_WORDS: usize = 4;
+// Chinese and Japanese aren't written with spaces, so their sentences are measured in characters.
+const MIN_SENTENCE_CHARS: usize = 8;
+
+// Han, kana and the CJK punctuation and full-width forms.
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3000}'..='\u{30FF}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{FF00}'..='\u{FFEF}')
+}
+
+fn long_enough(sentence: &str) -> bool {
+    if sentence.chars().any(is_cjk) {
+        sentence.chars().filter(|c| !c.is_whitespace()).count() >= MIN_SENTENCE_CHARS
+    } else {
+        sentence.split_whitespace().count() >= MIN_SENTENCE_WORDS
+    }
+}
+
+pub fn sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        current.push(c);
+        // The full-width marks end a sentence even when the next one follows without a space.
+        if matches!(c, '。' | '！' | '？') {
+            sentences.push(current.trim().to_string());
+            current.clear();
+            continue;
+        }
+        let ends_sentence = matches!(c, '.' | '!' | '?');
+        if ends_sentence && chars.peek().is_none_or(|next| next.is_whitespace()) {
+            // Single letters and common abbreviations ("U.S.", "Inc.") don't end a sentence.
+            let last_word = current.split_whitespace().last().unwrap_or("");
+            let abbreviation = last_word.trim_end_matches('.').len() <= 1
+                || ["Inc.", "Corp.", "Ltd.", "Mr.", "Ms.", "Dr.", "vs.", "e.g.", "i.e."].contains(&last_word);
+            if c != '.' || !abbreviation {
+                sentences.push(current.trim().to_string());
+                current.clear();
+            }
+        }
+    }
+    if !current.trim().is_empty() {
+        sentences.push(current.trim().to_string());
+    }
+    sentences
+        .into_iter()
+        .filter(|sentence| long_enough(sentence))
+        .collect()
+}
+
+// Builds a summary of at most `max_chars` from the sentences of `texts`, ranked by how similar
+// each sentence is to all the others, and returned in their original order.
+pub fn summarize(texts: &[&str], max_chars: usize) -> String {
+    let sentences: Vec<String> = texts.iter().flat_map(|text| sentences(text)).collect();
+    if sentences.is_empty() {
+        return String::new();
+    }
+
+    let vectors = text::tfidf(&sentences.iter().map(|s| text::content_words(s)).collect::<Vec<_>>());
+    let mut ranked: Vec<(usize, f64)> = (0..sentences.len())
+        .map(|i| {
+            let centrality = (0..sentences.len())
+                .filter(|&j| j != i)
+                .map(|j| text::cosine(&vectors[i], &vectors[j]))
+                .sum::<f64>();
+            (i, centrality)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut picked: Vec<usize> = Vec::new();
+    let mut length = 0;
+    for (i, _) in ranked {
+        if picked
+            .iter()
+            .any(|&j| text::cosine(&vectors[i], &vectors[j]) >= REDUNDANCY_THRESHOLD)
+        {
+            continue;
+        }
+        let added = sentences[i].chars().count() + if picked.is_empty() { 0 } else { 1 };
+        if length + added > max_chars {
+            continue;
+        }
+        length += added;
+        picked.push(i);
+    }
+
+    if picked.is_empty() {
+        return truncate(&sentences[0], max_chars);
+    }
+    picked.sort_unstable();
+    picked
+        .iter()
+        .map(|&i| sentences[i].as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Cuts at a word boundary and marks the cut with an ellipsis.
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(index) if index > 0 => &cut[..index],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace()))
+}
+
+// A summary for one article: its own if it fits, otherwise extracted from the longest text we have,
+// or that text cut short when it has no usable sentences.
+pub fn summarize_article(summary: &str, content: Option<&str>, max_chars: usize) -> String {
+    let summary = summary.trim();
+    if !summary.is_empty() && summary.chars().count() <= max_chars {
+        return summary.to_string();
+    }
+    let source = match content {
+        Some(content) if content.len() > summary.len() => content,
+        _ => summary,
+    };
+    let extracted = summarize(&[source], max_chars);
+    if extracted.is_empty() {
+        return truncate(source.trim(), max_chars);
+    }
+    extracted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHINESE: &str = "比特币价格今天突破了十万美元大关。分析师认为机构投资者的持续买入是主要原因！\
+        以太坊也随之上涨了百分之五。市场情绪整体乐观，交易量创下新高？监管机构表示将继续关注加密市场的发展。";
+
+    #[test]
+    fn splits_chinese_without_spaces() {
+        let sentences = sentences(CHINESE);
+        assert_eq!(sentences.len(), 5);
+        assert_eq!(sentences[0], "比特币价格今天突破了十万美元大关。");
+        assert_eq!(sentences[1], "分析师认为机构投资者的持续买入是主要原因！");
+    }
+
+    #[test]
+    fn splits_japanese_and_drops_short_fragments() {
+        let sentences = sentences("ビットコインが過去最高値を更新した。はい。イーサリアムも大きく上昇している。");
+        assert_eq!(
+            sentences,
+            vec!["ビットコインが過去最高値を更新した。", "イーサリアムも大きく上昇している。"]
+        );
+    }
+
+    #[test]
+    fn summarizes_long_chinese_articles() {
+        let summary = summarize_article("", Some(CHINESE), 60);
+        assert!(!summary.is_empty());
+        assert!(summary.chars().count() <= 60);
+        assert!(summary.starts_with("比特币价格"));
+    }
+
+    #[test]
+    fn falls_back_to_truncation_without_sentences() {
+        let text = "比特币".repeat(200);
+        let summary = summarize_article(&text, None, 280);
+        assert_eq!(summary.chars().count(), 280);
+        assert!(summary.ends_with('…'));
+    }
+
+    #[test]
+    fn keeps_english_abbreviations_together() {
+        let sentences = sentences("The U.S. regulator approved the fund today. Bitcoin rose on the news at once.");
+        assert_eq!(sentences.len(), 2);
+    }
+}
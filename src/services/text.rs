@@ -0,0 +1,134 @@
+// Tokenizing and similarity helpers shared by the offline text analysis stages.
+
+use std::collections::{HashMap, HashSet};
+
+pub type Vector = HashMap<String, f64>;
+
+pub const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "against", "all", "amid", "an", "and", "are", "as", "at", "be", "been", "before", "but",
+    "by", "can", "could", "did", "does", "for", "from", "has", "have", "here", "how", "in", "into", "is", "it",
+    "its", "may", "more", "new", "not", "now", "of", "on", "or", "over", "says", "than", "that", "the", "their",
+    "this", "to", "today", "up", "us", "was", "week", "what", "when", "which", "who", "why", "will", "with",
+    "price", "prices", "crypto", "cryptocurrency", "market", "markets", "news",
+];
+
+pub fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Words that carry meaning on their own: no stopwords, numbers or very short tokens.
+pub fn content_words(text: &str) -> Vec<String> {
+    words(text)
+        .into_iter()
+        .filter(|word| {
+            word.chars().count() > 2
+                && !STOPWORDS.contains(&word.as_str())
+                && !word.chars().all(|c| c.is_ascii_digit())
+        })
+        .collect()
+}
+
+// Unit-length TF-IDF vectors, one per document; IDF is computed over `docs` only.
+pub fn tfidf(docs: &[Vec<String>]) -> Vec<Vector> {
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for doc in docs {
+        for term in doc.iter().collect::<HashSet<_>>() {
+            *document_frequency.entry(term.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    let total = docs.len() as f64;
+    docs.iter()
+        .map(|doc| {
+            let mut vector: Vector = HashMap::new();
+            for term in doc {
+                *vector.entry(term.clone()).or_insert(0.0) += 1.0;
+            }
+            for (term, weight) in vector.iter_mut() {
+                let df = document_frequency[term.as_str()] as f64;
+                *weight *= (1.0 + total / df).ln();
+            }
+            let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                vector.values_mut().for_each(|w| *w /= norm);
+            }
+            vector
+        })
+        .collect()
+}
+
+pub fn cosine(a: &Vector, b: &Vector) -> f64 {
+    let (small, large) = if a.len() < b.len() { (a, b) } else { (b, a) };
+    small
+        .iter()
+        .filter_map(|(term, weight)| large.get(term).map(|other| weight * other))
+        .sum()
+}
+
+pub fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Backslash-escapes Markdown punctuation so a headline renders as the text it is.
+pub fn escape_markdown(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.split_whitespace().collect::<Vec<_>>().join(" ").chars() {
+        if "\\`*_[]()<>#|~".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// Percent-encodes the characters that would end a Markdown link destination early.
+pub fn escape_markdown_link(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.trim().chars() {
+        match c {
+            ' ' => out.push_str("%20"),
+            '(' => out.push_str("%28"),
+            ')' => out.push_str("%29"),
+            '<' => out.push_str("%3C"),
+            '>' => out.push_str("%3E"),
+            '\\' => out.push_str("%5C"),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Plain text from an HTML fragment such as an RSS description: tags dropped, common entities
+// decoded and whitespace collapsed.
+pub fn strip_html(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut in_tag = false;
+    for c in value.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
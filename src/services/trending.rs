@@ -0,0 +1,290 @@
+// Finds symbols and title phrases whose mention volume in the latest window is abnormal
+// compared with the same-sized windows before it.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::services::archive::{ArchivedArticle, MentionCount};
+use crate::services::text;
+
+const MIN_MENTIONS: usize = 3;
+const EXAMPLES: usize = 3;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Window {
+    Hour,
+    Day,
+}
+
+impl Window {
+    pub fn parse(value: &str) -> Option<Window> {
+        match value.to_lowercase().as_str() {
+            "hour" | "1h" => Some(Window::Hour),
+            "day" | "24h" | "1d" => Some(Window::Day),
+            _ => None,
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Window::Hour => 60 * 60,
+            Window::Day => 24 * 60 * 60,
+        }
+    }
+
+    // Number of preceding windows used as the baseline: a week of hours or a month of days.
+    pub fn baseline_windows(&self) -> i64 {
+        match self {
+            Window::Hour => 7 * 24,
+            Window::Day => 30,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TrendingExample {
+    pub title: String,
+    pub source: String,
+    pub link: String,
+    pub published_at: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TrendingTerm {
+    pub term: String,
+    pub mentions: usize,
+    pub baseline_mean: f64,
+    pub z_score: f64,
+    pub examples: Vec<TrendingExample>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Trending {
+    pub window: Window,
+    pub symbols: Vec<TrendingTerm>,
+    pub phrases: Vec<TrendingTerm>,
+}
+
+fn phrases(title: &str) -> HashSet<String> {
+    let words = text::content_words(title);
+    let mut phrases: HashSet<String> = words.iter().cloned().collect();
+    for pair in words.windows(2) {
+        phrases.insert(pair.join(" "));
+    }
+    phrases
+}
+
+// Counts per window for one term; index 0 is the current window.
+struct Series<'a> {
+    counts: Vec<usize>,
+    current: Vec<&'a ArchivedArticle>,
+}
+
+impl Series<'_> {
+    fn new(windows: usize) -> Self {
+        Series {
+            counts: vec![0; windows],
+            current: Vec::new(),
+        }
+    }
+}
+
+fn z_score(series: &Series, baseline_windows: usize) -> (f64, f64) {
+    let baseline = &series.counts[1..];
+    let mean = baseline.iter().sum::<usize>() as f64 / baseline_windows as f64;
+    let variance = baseline
+        .iter()
+        .map(|&count| (count as f64 - mean).powi(2))
+        .sum::<f64>()
+        / baseline_windows as f64;
+    // Sparse baselines have tiny variance; fall back to a Poisson-like floor so one-off spikes aren't infinite.
+    let sigma = variance.sqrt().max(mean.sqrt()).max(1.0);
+    (mean, (series.counts[0] as f64 - mean) / sigma)
+}
+
+fn rank(series: HashMap<String, Series>, baseline_windows: usize, limit: usize) -> Vec<TrendingTerm> {
+    let mut terms: Vec<TrendingTerm> = series
+        .into_iter()
+        .filter(|(_, series)| series.counts[0] >= MIN_MENTIONS)
+        .map(|(term, series)| {
+            let (baseline_mean, z_score) = z_score(&series, baseline_windows);
+            let mut current = series.current;
+            current.sort_by_key(|archived| std::cmp::Reverse(archived.published_at));
+            TrendingTerm {
+                term,
+                mentions: series.counts[0],
+                baseline_mean,
+                z_score,
+                examples: current
+                    .into_iter()
+                    .take(EXAMPLES)
+                    .map(|archived| TrendingExample {
+                        title: archived.article.title.clone(),
+                        source: archived.article.source.clone(),
+                        link: archived.article.link.clone(),
+                        published_at: archived.published_at,
+                    })
+                    .collect(),
+            }
+        })
+        .filter(|term| term.z_score > 0.0)
+        .collect();
+    terms.sort_by(|a, b| b.z_score.total_cmp(&a.z_score));
+    terms.truncate(limit);
+    terms
+}
+
+// `counts` are the symbol mentions per window from `Archive::mention_counts`, `current` the articles
+// of the current window and `earlier` the (title, published_at) of each link in the baseline windows.
+pub fn compute(
+    counts: &[MentionCount],
+    current: &[ArchivedArticle],
+    earlier: &[(String, i64)],
+    window: Window,
+    now: i64,
+    limit: usize,
+) -> Trending {
+    let windows = window.baseline_windows() as usize + 1;
+
+    let mut symbols: HashMap<String, Series> = HashMap::new();
+    for count in counts.iter().filter(|count| (0..windows as i64).contains(&count.window)) {
+        let series = symbols.entry(count.symbol.clone()).or_insert_with(|| Series::new(windows));
+        series.counts[count.window as usize] += count.articles as usize;
+    }
+    for archived in current {
+        if let Some(series) = symbols.get_mut(&archived.symbol) {
+            series.current.push(archived);
+        }
+    }
+
+    let mut phrase_series: HashMap<String, Series> = HashMap::new();
+    let mut seen_links = HashSet::new();
+    for archived in current {
+        // The archive keeps one row per symbol, so the same article can appear several times.
+        if !seen_links.insert(archived.article.link.as_str()) {
+            continue;
+        }
+        for phrase in phrases(&archived.article.title) {
+            let series = phrase_series.entry(phrase).or_insert_with(|| Series::new(windows));
+            series.counts[0] += 1;
+            series.current.push(archived);
+        }
+    }
+    // Only phrases of the current window can trend, so earlier titles just fill in their baselines.
+    for (title, published_at) in earlier {
+        let age = now - published_at;
+        let index = (age / window.seconds()) as usize;
+        if age < 0 || index == 0 || index >= windows {
+            continue;
+        }
+        for phrase in phrases(title) {
+            if let Some(series) = phrase_series.get_mut(&phrase) {
+                series.counts[index] += 1;
+            }
+        }
+    }
+
+    Trending {
+        window,
+        symbols: rank(symbols, windows - 1, limit),
+        phrases: rank(phrase_series, windows - 1, limit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::news::NewsArticle;
+
+    const NOW: i64 = 1_000_000;
+    const HOUR: i64 = 60 * 60;
+
+    fn series(counts: &[usize]) -> Series<'static> {
+        Series {
+            counts: counts.to_vec(),
+            current: Vec::new(),
+        }
+    }
+
+    fn article(symbol: &str, title: &str, link: &str, published_at: i64) -> ArchivedArticle {
+        ArchivedArticle {
+            id: 0,
+            symbol: symbol.to_string(),
+            published_at,
+            story_id: None,
+            article: NewsArticle::new(
+                title.to_string(),
+                "wire".to_string(),
+                String::new(),
+                String::new(),
+                link.to_string(),
+            ),
+        }
+    }
+
+    fn count(symbol: &str, window: i64, articles: i64) -> MentionCount {
+        MentionCount {
+            symbol: symbol.to_string(),
+            window,
+            articles,
+        }
+    }
+
+    #[test]
+    fn z_scores_compare_the_current_window_with_the_baseline() {
+        // Steady baseline: no variance, so the Poisson floor sqrt(mean) applies.
+        let (mean, z) = z_score(&series(&[10, 4, 4, 4, 4]), 4);
+        assert_eq!(mean, 4.0);
+        assert_eq!(z, 3.0);
+
+        // Mean 2, standard deviation 2.
+        let (mean, z) = z_score(&series(&[8, 0, 4, 0, 4]), 4);
+        assert_eq!(mean, 2.0);
+        assert_eq!(z, 3.0);
+
+        // An empty baseline is floored at one so a first spike isn't infinite.
+        assert_eq!(z_score(&series(&[3, 0, 0, 0, 0]), 4), (0.0, 3.0));
+    }
+
+    #[test]
+    fn symbols_trend_on_their_baseline_and_phrases_count_links_once() {
+        let windows = Window::Hour.baseline_windows();
+        let mut counts = vec![count("BTC", 0, 6), count("ETH", 0, 3), count("SOL", windows + 1, 50)];
+        for window in 1..=windows {
+            counts.push(count("BTC", window, 1));
+            counts.push(count("ETH", window, 3));
+        }
+        let current = vec![
+            article("BTC", "Exchange outage halts withdrawals", "https://example.com/a", NOW - 60),
+            article("ETH", "Exchange outage halts withdrawals", "https://example.com/a", NOW - 60),
+            article("BTC", "Second exchange outage reported", "https://example.com/b", NOW - 120),
+            article("BTC", "Exchange outage ends", "https://example.com/c", NOW - 180),
+            article("BTC", "Bitcoin holds steady", "https://example.com/d", NOW - 240),
+        ];
+        let earlier = vec![
+            ("Exchange outage last month".to_string(), NOW - 2 * HOUR),
+            // Past the baseline.
+            ("Exchange outage long ago".to_string(), NOW - (windows + 1) * HOUR - 1),
+        ];
+
+        let trending = compute(&counts, &current, &earlier, Window::Hour, NOW, 10);
+
+        let symbols: Vec<&str> = trending.symbols.iter().map(|term| term.term.as_str()).collect();
+        assert_eq!(symbols, vec!["BTC"]);
+        let btc = &trending.symbols[0];
+        assert_eq!((btc.mentions, btc.baseline_mean, btc.z_score), (6, 1.0, 5.0));
+        let examples: Vec<&str> = btc.examples.iter().map(|example| example.link.as_str()).collect();
+        assert_eq!(examples, vec!["https://example.com/a", "https://example.com/b", "https://example.com/c"]);
+
+        let outage = trending
+            .phrases
+            .iter()
+            .find(|term| term.term == "exchange outage")
+            .unwrap();
+        assert_eq!(outage.mentions, 3);
+        assert_eq!(outage.baseline_mean, 1.0 / windows as f64);
+        assert!(trending.phrases.iter().all(|term| term.term != "bitcoin"));
+    }
+}
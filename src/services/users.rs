@@ -0,0 +1,465 @@
+// User accounts and their sessions. Passwords are hashed with Argon2id. A session is a short-lived
+// access token and a longer-lived refresh token, both JWTs signed with `auth.jwt_secret`. Refresh
+// tokens are also recorded, so each can be used once (a refresh replaces it) and revoked on logout.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::config::AuthConfig;
+use crate::services::{apikeys, language};
+
+// Sign-in and registration attempts allowed per client address and per email address in each
+// `ATTEMPT_WINDOW`. Every attempt runs Argon2, so they are counted before any hashing.
+const ATTEMPTS_PER_ADDRESS: u32 = 30;
+const ATTEMPTS_PER_EMAIL: u32 = 10;
+const ATTEMPT_WINDOW: Duration = Duration::from_secs(15 * 60);
+// Past this many counters the expired ones are dropped.
+const MAX_ATTEMPT_COUNTERS: usize = 10_000;
+
+const MIN_PASSWORD_CHARS: usize = 8;
+const MAX_PASSWORD_CHARS: usize = 128;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct User {
+    pub id: i64,
+    pub email: String,
+    pub created_at: i64,
+    pub last_login_at: Option<i64>,
+    // Applied to news endpoints called with the user's access token and no `lang`.
+    pub languages: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Session {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,
+    // Seconds until the access token expires.
+    pub expires_in: u64,
+    pub user: User,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum TokenKind {
+    Access,
+    Refresh,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Claims {
+    sub: String,
+    email: String,
+    typ: TokenKind,
+    iat: i64,
+    exp: i64,
+    // Refresh tokens only: the `refresh_tokens` row.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jti: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    // `auth.jwt_secret` isn't set.
+    Disabled,
+    Invalid(String),
+    EmailTaken,
+    InvalidCredentials,
+    InvalidToken,
+    // Seconds until another attempt is allowed.
+    TooManyAttempts(u64),
+    Internal(String),
+    Storage(sqlx::Error),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Disabled => write!(f, "user accounts are disabled"),
+            AuthError::Invalid(message) => write!(f, "{}", message),
+            AuthError::EmailTaken => write!(f, "an account with this email already exists"),
+            AuthError::InvalidCredentials => write!(f, "wrong email or password"),
+            AuthError::InvalidToken => write!(f, "the token is invalid or has expired"),
+            AuthError::TooManyAttempts(retry_after) => write!(f, "too many attempts, retry in {}s", retry_after),
+            AuthError::Internal(message) => write!(f, "{}", message),
+            AuthError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl From<sqlx::Error> for AuthError {
+    fn from(e: sqlx::Error) -> Self {
+        AuthError::Storage(e)
+    }
+}
+
+fn secret(config: &AuthConfig) -> Result<&[u8], AuthError> {
+    config
+        .jwt_secret
+        .as_deref()
+        .map(str::as_bytes)
+        .ok_or(AuthError::Disabled)
+}
+
+fn decode(config: &AuthConfig, token: &str, kind: TokenKind) -> Result<Claims, AuthError> {
+    let key = DecodingKey::from_secret(secret(config)?);
+    let claims = jsonwebtoken::decode::<Claims>(token, &key, &Validation::new(Algorithm::HS256))
+        .map_err(|_| AuthError::InvalidToken)?
+        .claims;
+    if claims.typ != kind {
+        return Err(AuthError::InvalidToken);
+    }
+    Ok(claims)
+}
+
+// Checks an access token without touching the database; returns the user's ID.
+pub fn verify_access(config: &AuthConfig, token: &str) -> Result<i64, AuthError> {
+    let claims = decode(config, token, TokenKind::Access)?;
+    claims.sub.parse().map_err(|_| AuthError::InvalidToken)
+}
+
+fn normalize_email(email: &str) -> Result<String, AuthError> {
+    let email = email.trim().to_lowercase();
+    match email.split_once('@') {
+        Some((local, domain)) if !local.is_empty() && domain.contains('.') && email.len() <= 254 => Ok(email),
+        _ => Err(AuthError::Invalid("email is not a valid address".to_string())),
+    }
+}
+
+// Argon2 is deliberately slow, so it runs off the async workers.
+async fn hash_password(password: String) -> Result<String, AuthError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AuthError::Internal(e.to_string()))
+    })
+    .await
+    .map_err(|e| AuthError::Internal(e.to_string()))?
+}
+
+async fn verify_password(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash)
+            .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+            .unwrap_or(false)
+    })
+    .await
+    .unwrap_or(false)
+}
+
+// Checked against when the email is unknown, so a login takes as long either way.
+fn dummy_hash() -> String {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(b"not a password", &salt)
+            .map(|hash| hash.to_string())
+            .unwrap_or_default()
+    })
+    .clone()
+}
+
+// Fixed-window counters of sign-in and registration attempts, keyed like `address:203.0.113.7` or
+// `email:ana@example.com`; kept in memory like API key rate limits.
+#[derive(Clone, Default)]
+struct Attempts {
+    windows: Arc<Mutex<HashMap<String, (Instant, u32)>>>,
+}
+
+impl Attempts {
+    // Counts an attempt for each key, or returns the seconds until one is allowed again when any
+    // key has used up its limit. Nothing is counted then.
+    fn count(&self, keys: &[(String, u32)]) -> Result<(), AuthError> {
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > MAX_ATTEMPT_COUNTERS {
+            windows.retain(|_, (started, _)| started.elapsed() < ATTEMPT_WINDOW);
+        }
+        for (key, limit) in keys {
+            if let Some((started, attempts)) = windows.get(key) {
+                if started.elapsed() < ATTEMPT_WINDOW && attempts >= limit {
+                    let retry_after = ATTEMPT_WINDOW.saturating_sub(started.elapsed()).as_secs().max(1);
+                    return Err(AuthError::TooManyAttempts(retry_after));
+                }
+            }
+        }
+        for (key, _) in keys {
+            let window = windows.entry(key.clone()).or_insert((Instant::now(), 0));
+            if window.0.elapsed() >= ATTEMPT_WINDOW {
+                *window = (Instant::now(), 0);
+            }
+            window.1 += 1;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct Users {
+    pool: SqlitePool,
+    attempts: Attempts,
+}
+
+impl Users {
+    pub fn new(pool: SqlitePool) -> Self {
+        Users {
+            pool,
+            attempts: Attempts::default(),
+        }
+    }
+
+    // Counts a sign-in or registration attempt from `address` (the client's IP) for `email`.
+    fn attempt(&self, address: &str, email: &str) -> Result<(), AuthError> {
+        self.attempts.count(&[
+            (format!("address:{}", address), ATTEMPTS_PER_ADDRESS),
+            (format!("email:{}", email.trim().to_lowercase()), ATTEMPTS_PER_EMAIL),
+        ])
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<User>, sqlx::Error> {
+        let row = sqlx::query("SELECT id, email, created_at, last_login_at, languages FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(from_row))
+    }
+
+    pub async fn register(&self, config: &AuthConfig, address: &str, email: &str, password: &str) -> Result<Session, AuthError> {
+        secret(config)?;
+        self.attempt(address, email)?;
+        let email = normalize_email(email)?;
+        let length = password.chars().count();
+        if !(MIN_PASSWORD_CHARS..=MAX_PASSWORD_CHARS).contains(&length) {
+            return Err(AuthError::Invalid(format!(
+                "password must be {} to {} characters",
+                MIN_PASSWORD_CHARS, MAX_PASSWORD_CHARS
+            )));
+        }
+        let hash = hash_password(password.to_string()).await?;
+        let now = Utc::now().timestamp();
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO users (email, password_hash, created_at, last_login_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&email)
+        .bind(hash)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AuthError::EmailTaken);
+        }
+        self.session(config, result.last_insert_rowid()).await
+    }
+
+    pub async fn login(&self, config: &AuthConfig, address: &str, email: &str, password: &str) -> Result<Session, AuthError> {
+        secret(config)?;
+        self.attempt(address, email)?;
+        let row = sqlx::query("SELECT id, password_hash FROM users WHERE email = ?")
+            .bind(email.trim().to_lowercase())
+            .fetch_optional(&self.pool)
+            .await?;
+        let (id, hash) = match &row {
+            Some(row) => (Some(row.get::<i64, _>("id")), row.get("password_hash")),
+            None => (None, dummy_hash()),
+        };
+        let verified = verify_password(password.to_string(), hash).await;
+        let Some(id) = id.filter(|_| verified) else {
+            return Err(AuthError::InvalidCredentials);
+        };
+        sqlx::query("UPDATE users SET last_login_at = ? WHERE id = ?")
+            .bind(Utc::now().timestamp())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        self.session(config, id).await
+    }
+
+    // Exchanges a refresh token for a new session; the old refresh token stops working. Presenting
+    // one that was already exchanged means it leaked, so all of the user's sessions are ended.
+    pub async fn refresh(&self, config: &AuthConfig, refresh_token: &str) -> Result<Session, AuthError> {
+        let claims = decode(config, refresh_token, TokenKind::Refresh)?;
+        let jti = claims.jti.ok_or(AuthError::InvalidToken)?;
+        let user_id: i64 = sqlx::query_scalar("SELECT user_id FROM refresh_tokens WHERE id = ?")
+            .bind(&jti)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(AuthError::InvalidToken)?;
+        let revoked = sqlx::query("UPDATE refresh_tokens SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(Utc::now().timestamp())
+            .bind(&jti)
+            .execute(&self.pool)
+            .await?;
+        if revoked.rows_affected() == 0 {
+            tracing::warn!(user_id, "refresh token reused, ending all sessions");
+            self.revoke_all(user_id).await?;
+            return Err(AuthError::InvalidToken);
+        }
+        self.session(config, user_id).await
+    }
+
+    pub async fn logout(&self, config: &AuthConfig, refresh_token: &str) -> Result<(), AuthError> {
+        let claims = decode(config, refresh_token, TokenKind::Refresh)?;
+        sqlx::query("UPDATE refresh_tokens SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(Utc::now().timestamp())
+            .bind(claims.jti)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Replaces the user's language preference; an empty list removes it.
+    pub async fn set_languages(&self, user_id: i64, languages: &[String]) -> Result<Result<Option<User>, String>, sqlx::Error> {
+        let languages = match language::preference(languages) {
+            Ok(languages) => languages,
+            Err(message) => return Ok(Err(message)),
+        };
+        sqlx::query("UPDATE users SET languages = ? WHERE id = ?")
+            .bind(languages)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        self.get(user_id).await.map(Ok)
+    }
+
+    // The user's stored language preference, comma-separated.
+    pub async fn languages(&self, user_id: i64) -> Result<Option<String>, sqlx::Error> {
+        let languages: Option<Option<String>> = sqlx::query_scalar("SELECT languages FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(languages.flatten())
+    }
+
+    // Replaces the user's RSS feed token and returns it; only its hash is kept.
+    pub async fn rotate_feed_token(&self, user_id: i64) -> Result<String, sqlx::Error> {
+        let token = Uuid::new_v4().simple().to_string();
+        sqlx::query("UPDATE users SET feed_token_hash = ? WHERE id = ?")
+            .bind(apikeys::hash(&token))
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(token)
+    }
+
+    pub async fn clear_feed_token(&self, user_id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE users SET feed_token_hash = NULL WHERE id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // The user a feed token belongs to.
+    pub async fn by_feed_token(&self, token: &str) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar("SELECT id FROM users WHERE feed_token_hash = ?")
+            .bind(apikeys::hash(token))
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn revoke_all(&self, user_id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE refresh_tokens SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL")
+            .bind(Utc::now().timestamp())
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn session(&self, config: &AuthConfig, user_id: i64) -> Result<Session, AuthError> {
+        let user = self.get(user_id).await?.ok_or(AuthError::InvalidToken)?;
+        let key = EncodingKey::from_secret(secret(config)?);
+        let now = Utc::now().timestamp();
+        let encode = |typ, lifetime: u64, jti: Option<String>| {
+            let claims = Claims {
+                sub: user.id.to_string(),
+                email: user.email.clone(),
+                typ,
+                iat: now,
+                exp: now + lifetime as i64,
+                jti,
+            };
+            jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &key)
+                .map_err(|e| AuthError::Internal(e.to_string()))
+        };
+        let jti = Uuid::new_v4().simple().to_string();
+        let access_token = encode(TokenKind::Access, config.access_token_secs, None)?;
+        let refresh_token = encode(TokenKind::Refresh, config.refresh_token_secs, Some(jti.clone()))?;
+
+        sqlx::query("DELETE FROM refresh_tokens WHERE expires_at < ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("INSERT INTO refresh_tokens (id, user_id, expires_at) VALUES (?, ?, ?)")
+            .bind(jti)
+            .bind(user.id)
+            .bind(now + config.refresh_token_secs as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(Session {
+            access_token,
+            refresh_token,
+            token_type: "Bearer",
+            expires_in: config.access_token_secs,
+            user,
+        })
+    }
+}
+
+fn from_row(row: &SqliteRow) -> User {
+    User {
+        id: row.get("id"),
+        email: row.get("email"),
+        created_at: row.get("created_at"),
+        last_login_at: row.get("last_login_at"),
+        languages: row
+            .get::<Option<String>, _>("languages")
+            .map(|languages| languages.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[actix_web::test]
+    async fn attempts_are_limited_per_address_and_per_email() {
+        let config = Config::parse("[auth]\njwt_secret = \"0123456789abcdef0123456789abcdef-test\"", std::iter::empty()).unwrap();
+        let state = crate::test_state(config.clone()).await;
+
+        for i in 0..ATTEMPTS_PER_EMAIL {
+            state.users.attempt(&format!("198.51.100.{}", i), "ana@example.com").unwrap();
+        }
+        let denied = state.users.login(&config.auth, "203.0.113.1", " Ana@Example.com", "password123").await;
+        assert!(matches!(denied, Err(AuthError::TooManyAttempts(_))), "{:?}", denied.map(|_| ()));
+
+        // The denied attempt wasn't counted against the address.
+        let wrong = state.users.login(&config.auth, "203.0.113.1", "bob@example.com", "password123").await;
+        assert!(matches!(wrong, Err(AuthError::InvalidCredentials)), "{:?}", wrong.map(|_| ()));
+        for i in 1..ATTEMPTS_PER_ADDRESS {
+            state.users.attempt("203.0.113.1", &format!("user{}@example.com", i)).unwrap();
+        }
+        let denied = state.users.register(&config.auth, "203.0.113.1", "carol@example.com", "password123").await;
+        assert!(matches!(denied, Err(AuthError::TooManyAttempts(_))), "{:?}", denied.map(|_| ()));
+        assert!(state.users.attempt("203.0.113.2", "carol@example.com").is_ok());
+    }
+}
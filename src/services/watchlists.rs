@@ -0,0 +1,135 @@
+// Users' own watchlists of symbols and news sources, which make up their personal feed. Unlike
+// the `[digest]` watchlists in the config, these are edited through the API.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+const MAX_NAME_CHARS: usize = 64;
+const MAX_ENTRIES: usize = 100;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UserWatchlist {
+    pub name: String,
+    // Upper case, e.g. `BTC`.
+    pub symbols: Vec<String>,
+    // Publishers as they appear in articles' `source`, compared case-insensitively.
+    pub sources: Vec<String>,
+    pub updated_at: i64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WatchlistUpdate {
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+impl WatchlistUpdate {
+    // Normalizes the entries: trimmed, symbols upper case, sources lower case, no duplicates.
+    fn normalize(&self, name: &str) -> Result<(Vec<String>, Vec<String>), String> {
+        if name.trim().is_empty() || name.chars().count() > MAX_NAME_CHARS {
+            return Err(format!("name must be 1 to {} characters", MAX_NAME_CHARS));
+        }
+        let clean = |items: &[String], upper: bool| -> Result<Vec<String>, String> {
+            let mut clean: Vec<String> = Vec::new();
+            for item in items {
+                let item = item.trim();
+                if item.is_empty() || item.contains(',') {
+                    return Err(format!("invalid entry {:?}", item));
+                }
+                let item = if upper { item.to_uppercase() } else { item.to_lowercase() };
+                if !clean.contains(&item) {
+                    clean.push(item);
+                }
+            }
+            Ok(clean)
+        };
+        let symbols = clean(&self.symbols, true)?;
+        let sources = clean(&self.sources, false)?;
+        if symbols.is_empty() && sources.is_empty() {
+            return Err("a watchlist needs at least one symbol or source".to_string());
+        }
+        if symbols.len() + sources.len() > MAX_ENTRIES {
+            return Err(format!("a watchlist holds at most {} symbols and sources", MAX_ENTRIES));
+        }
+        Ok((symbols, sources))
+    }
+}
+
+#[derive(Clone)]
+pub struct Watchlists {
+    pool: SqlitePool,
+}
+
+fn split(list: String) -> Vec<String> {
+    list.split(',').filter(|item| !item.is_empty()).map(str::to_string).collect()
+}
+
+impl Watchlists {
+    pub fn new(pool: SqlitePool) -> Self {
+        Watchlists { pool }
+    }
+
+    pub async fn list(&self, user_id: i64) -> Result<Vec<UserWatchlist>, sqlx::Error> {
+        let rows = sqlx::query("SELECT name, symbols, sources, updated_at FROM watchlists WHERE user_id = ? ORDER BY name")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    // Creates the watchlist or replaces its entries. The outer error is a validation message.
+    pub async fn put(
+        &self,
+        user_id: i64,
+        name: &str,
+        update: &WatchlistUpdate,
+    ) -> Result<Result<UserWatchlist, String>, sqlx::Error> {
+        let (symbols, sources) = match update.normalize(name) {
+            Ok(entries) => entries,
+            Err(message) => return Ok(Err(message)),
+        };
+        let now = Utc::now().timestamp();
+        sqlx::query(
+            "INSERT INTO watchlists (user_id, name, symbols, sources, updated_at) VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT (user_id, name) DO UPDATE
+             SET symbols = excluded.symbols, sources = excluded.sources, updated_at = excluded.updated_at",
+        )
+        .bind(user_id)
+        .bind(name.trim())
+        .bind(symbols.join(","))
+        .bind(sources.join(","))
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(Ok(UserWatchlist {
+            name: name.trim().to_string(),
+            symbols,
+            sources,
+            updated_at: now,
+        }))
+    }
+
+    // Returns false when the user has no watchlist by that name.
+    pub async fn delete(&self, user_id: i64, name: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM watchlists WHERE user_id = ? AND name = ?")
+            .bind(user_id)
+            .bind(name.trim())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn from_row(row: &SqliteRow) -> UserWatchlist {
+    UserWatchlist {
+        name: row.get("name"),
+        symbols: split(row.get("symbols")),
+        sources: split(row.get("sources")),
+        updated_at: row.get("updated_at"),
+    }
+}
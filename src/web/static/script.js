@@ -1,6 +1,6 @@
 document.addEventListener('DOMContentLoaded', function() {
     const searchForm = document.getElementById('search-form');
-    const resultsContainer = document.getElementById('results');
+    const resultsContainer = document.getElementById('news-list');
 
     searchForm.addEventListener('submit', async function(event) {
         event.preventDefault();
@@ -12,7 +12,7 @@ document.addEventListener('DOMContentLoaded', function() {
 
     async function fetchNews(crypto) {
         try {
-            const response = await fetch(`/api/news?crypto=${encodeURIComponent(crypto)}`);
+            const response = await fetch(`/news?symbol=${encodeURIComponent(crypto)}`);
             if (!response.ok) {
                 throw new Error('Network response was not ok');
             }